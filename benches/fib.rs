@@ -0,0 +1,73 @@
+//! Engine comparison benchmark.
+//!
+//! Run with `cargo bench --bench fib`. Computes a recursive Fibonacci number
+//! with the tree-walking evaluator and with the bytecode VM, and reports how
+//! much faster the VM is. Compilation is timed as part of the VM's run.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use runkey::ast::*;
+use runkey::compiler::*;
+use runkey::evaluator::*;
+use runkey::lexer::*;
+use runkey::object::*;
+use runkey::parser::*;
+use runkey::vm::*;
+
+const FIB: &str = "
+let fib = fn(n) {
+    if (n < 2) {
+        n
+    } else {
+        fib(n - 1) + fib(n - 2)
+    }
+};
+fib(N)
+";
+
+fn parse(n: u32) -> ast::Program {
+    let source = FIB.replace('N', &n.to_string());
+    let mut p = parser::Parser::new(lexer::Lexer::new(source));
+    let program = p.parse_program();
+    assert!(p.errors().is_empty(), "{:?}", p.errors());
+    program
+}
+
+fn tree(program: &ast::Program) -> object::Object {
+    evaluator::eval(program, &mut environment::Environment::new())
+}
+
+fn vm(program: &ast::Program) -> object::Object {
+    let mut c = compiler::Compiler::new();
+    c.compile(program).unwrap();
+    vm::VM::new(c.bytecode()).run()
+}
+
+fn bench(program: &ast::Program, run: fn(&ast::Program) -> object::Object) -> (Duration, object::Object) {
+    let mut best = Duration::MAX;
+    let mut result = object::Object::Null;
+    for _ in 0..3 {
+        let start = Instant::now();
+        result = black_box(run(black_box(program)));
+        best = best.min(start.elapsed());
+    }
+    (best, result)
+}
+
+fn main() {
+    for n in [20, 25] {
+        let program = parse(n);
+        let (tree_elapsed, tree_result) = bench(&program, tree);
+        let (vm_elapsed, vm_result) = bench(&program, vm);
+        assert_eq!(tree_result, vm_result);
+        println!(
+            "fib({}) = {}: tree {:>10.2?}, vm {:>10.2?} ({:.1}x)",
+            n,
+            vm_result,
+            tree_elapsed,
+            vm_elapsed,
+            tree_elapsed.as_secs_f64() / vm_elapsed.as_secs_f64(),
+        );
+    }
+}
@@ -0,0 +1,68 @@
+//! Lexer throughput benchmark.
+//!
+//! Run with `cargo bench --bench lexer`. Lexes generated sources of growing
+//! size and reports throughput; since the lexer is linear, MB/s should stay
+//! roughly flat as the input grows.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use runkey::lexer::*;
+use runkey::token::*;
+
+const SNIPPET: &str = "let five = 5;
+let ten = 10;
+let add = fn(x, y) {
+    x + y;
+};
+let résultat = add(five, ten);
+if (5 < 10) {
+    return true;
+} else {
+    return false;
+}
+10 != 9;
+";
+
+fn source(bytes: usize) -> String {
+    let mut s = String::with_capacity(bytes + SNIPPET.len());
+    while s.len() < bytes {
+        s.push_str(SNIPPET);
+    }
+    s
+}
+
+fn lex(input: &str) -> usize {
+    let mut l = lexer::Lexer::new(input.to_string());
+    let mut count = 0;
+    loop {
+        let tok = l.next_token();
+        count += 1;
+        if tok.kind == token::TokenKind::Eof {
+            return count;
+        }
+    }
+}
+
+fn bench(input: &str) -> (Duration, usize) {
+    let mut best = Duration::MAX;
+    let mut tokens = 0;
+    for _ in 0..5 {
+        let start = Instant::now();
+        tokens = black_box(lex(black_box(input)));
+        best = best.min(start.elapsed());
+    }
+    (best, tokens)
+}
+
+fn main() {
+    for mb in [1, 4, 16] {
+        let input = source(mb * 1024 * 1024);
+        let (elapsed, tokens) = bench(&input);
+        let throughput = input.len() as f64 / elapsed.as_secs_f64() / (1024.0 * 1024.0);
+        println!(
+            "lex {:>2} MiB: {:>9} tokens in {:>10.2?} ({:.1} MiB/s)",
+            mb, tokens, elapsed, throughput,
+        );
+    }
+}
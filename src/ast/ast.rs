@@ -1,13 +1,122 @@
+use std::fmt;
+
+use crate::token::*;
+
 #[derive(Debug, Clone)]
+#[allow(clippy::enum_variant_names)]
 pub enum Statement {
-    EmptyStatement,
-    LetStatement(Identifier, Expression),
-    ReturnStatement(Expression),
+    LetStatement(LetStatement),
+    ReturnStatement(Expression, token::Span),
+    ExpressionStatement(Expression, token::Span),
+    /// `label: while (condition) { body }`; the label is optional.
+    WhileStatement(Option<Identifier>, Expression, BlockStatement, token::Span),
+    /// `label: for variable in iterable { body }`; the label is optional.
+    ForStatement(Option<Identifier>, Identifier, Expression, BlockStatement, token::Span),
+    /// `break` out of the innermost loop, or the one with the given label.
+    BreakStatement(Option<Identifier>, token::Span),
+    /// `continue` with the next iteration of the innermost or labelled loop.
+    ContinueStatement(Option<Identifier>, token::Span),
+    /// `import "path" as name;` or `import { a, b as c } from "path";`, at
+    /// the top level.
+    ImportStatement(String, Import, token::Span),
+    /// `export` before a top-level `let` statement, which other programs
+    /// can then import the binding from.
+    ExportStatement(LetStatement, token::Span),
+}
+
+impl Statement {
+    pub fn span(&self) -> token::Span {
+        match self {
+            Statement::LetStatement(stmt) => stmt.span,
+            Statement::ReturnStatement(_, span)
+            | Statement::ExpressionStatement(_, span)
+            | Statement::WhileStatement(_, _, _, span)
+            | Statement::ForStatement(_, _, _, _, span)
+            | Statement::BreakStatement(_, span)
+            | Statement::ContinueStatement(_, span)
+            | Statement::ImportStatement(_, _, span)
+            | Statement::ExportStatement(_, span) => *span,
+        }
+    }
+}
+
+/// What an `import` statement binds.
+#[derive(Debug, Clone)]
+pub enum Import {
+    /// The whole module, as a hash of its exports by name.
+    Module(Identifier),
+    /// Some of the module's exports, each bound under the second name.
+    Names(Vec<(Identifier, Identifier)>),
 }
 
 #[derive(Debug, Clone)]
 pub enum Expression {
-    EmptyExpression,
+    Identifier(Identifier),
+    IntegerLiteral(i64, token::Span),
+    FloatLiteral(f64, token::Span),
+    Boolean(bool, token::Span),
+    StringLiteral(String, token::Span),
+    ArrayLiteral(Vec<Expression>, token::Span),
+    /// `{key: value, ...}`, with the pairs in source order.
+    HashLiteral(Vec<(Expression, Expression)>, token::Span),
+    /// An operator token applied to its operand, e.g. `-x` or `!ok`.
+    PrefixExpression(token::TokenKind, Box<Expression>, token::Span),
+    /// A binary operator token between two operands, e.g. `a + b`.
+    InfixExpression(Box<Expression>, token::TokenKind, Box<Expression>, token::Span),
+    /// An assignment `target = value`, or a compound one such as `x += 1`
+    /// that applies the operator to the target's value and the right-hand
+    /// side. The target is a name or an index expression such as `a[i]`.
+    AssignExpression(Box<Expression>, token::TokenKind, Box<Expression>, token::Span),
+    /// `if (condition) { consequence } else { alternative }`.
+    IfExpression(Box<Expression>, BlockStatement, Option<BlockStatement>, token::Span),
+    FunctionLiteral(Vec<Identifier>, BlockStatement, token::Span),
+    /// A call of the function expression with the argument expressions.
+    CallExpression(Box<Expression>, Vec<Expression>, token::Span),
+    /// `left[index]`.
+    IndexExpression(Box<Expression>, Box<Expression>, token::Span),
+    /// `left[start:end]`, where either bound may be omitted.
+    SliceExpression(Box<Expression>, Option<Box<Expression>>, Option<Box<Expression>>, token::Span),
+}
+
+impl Expression {
+    pub fn span(&self) -> token::Span {
+        match self {
+            Expression::Identifier(ident) => ident.span,
+            Expression::IntegerLiteral(_, span)
+            | Expression::FloatLiteral(_, span)
+            | Expression::Boolean(_, span)
+            | Expression::StringLiteral(_, span)
+            | Expression::ArrayLiteral(_, span)
+            | Expression::HashLiteral(_, span)
+            | Expression::PrefixExpression(_, _, span)
+            | Expression::InfixExpression(_, _, _, span)
+            | Expression::AssignExpression(_, _, _, span)
+            | Expression::IfExpression(_, _, _, span)
+            | Expression::FunctionLiteral(_, _, span)
+            | Expression::CallExpression(_, _, span)
+            | Expression::IndexExpression(_, _, span)
+            | Expression::SliceExpression(_, _, _, span) => *span,
+        }
+    }
+}
+
+/// `let name = value;`, `let mut name = value;` or `var name = value;`.
+#[derive(Debug, Clone)]
+pub struct LetStatement {
+    pub name: Identifier,
+    pub value: Expression,
+    /// Whether the binding was made with `let mut` or `var`.
+    pub mutable: bool,
+    /// The text of any `///` comments above the statement.
+    pub doc: Option<String>,
+    pub span: token::Span,
+}
+
+/// A brace-delimited sequence of statements, such as a function body.
+#[derive(Debug, Clone)]
+pub struct BlockStatement {
+    pub statements: Vec<Statement>,
+    pub span: token::Span,
 }
 
 #[derive(Debug, Clone)]
@@ -17,5 +126,262 @@ pub struct Program {
 
 #[derive(Debug, Clone)]
 pub struct Identifier {
-    pub value: String
+    pub value: String,
+    pub span: token::Span,
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for statement in &self.statements {
+            writeln!(f, "{}", statement)?;
+        }
+        Ok(())
+    }
+}
+
+/// Statements print as canonical source: `var` becomes `let mut`, every
+/// statement that takes a `;` gets one, and doc comments are kept.
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Statement::LetStatement(stmt) => write_let(f, "", stmt),
+            Statement::ReturnStatement(value, _) => write!(f, "return {};", value),
+            Statement::ExpressionStatement(expr, _) => write!(f, "{};", expr),
+            Statement::WhileStatement(label, condition, body, _) => {
+                write_label(f, label)?;
+                write!(f, "while {} {}", Parenthesized(condition), body)
+            }
+            Statement::ForStatement(label, variable, iterable, body, _) => {
+                write_label(f, label)?;
+                write!(f, "for {} in {} {}", variable, iterable, body)
+            }
+            Statement::BreakStatement(label, _) => write_loop_control(f, "break", label),
+            Statement::ContinueStatement(label, _) => write_loop_control(f, "continue", label),
+            Statement::ImportStatement(path, Import::Module(name), _) => {
+                write!(f, "import \"{}\" as {};", token::escape(path), name)
+            }
+            Statement::ImportStatement(path, Import::Names(names), _) => {
+                write!(f, "import {{ ")?;
+                for (i, (name, binding)) in names.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", name)?;
+                    if binding.value != name.value {
+                        write!(f, " as {}", binding)?;
+                    }
+                }
+                write!(f, " }} from \"{}\";", token::escape(path))
+            }
+            Statement::ExportStatement(stmt, _) => write_let(f, "export ", stmt),
+        }
+    }
+}
+
+/// Writes a `let` statement, with its doc comment above any `prefix`.
+fn write_let(f: &mut fmt::Formatter, prefix: &str, stmt: &LetStatement) -> fmt::Result {
+    if let Some(doc) = &stmt.doc {
+        for line in doc.lines() {
+            match line {
+                "" => writeln!(f, "///")?,
+                line => writeln!(f, "/// {}", line)?,
+            }
+        }
+    }
+    let keyword = if stmt.mutable { "let mut" } else { "let" };
+    write!(f, "{}{} {} = {};", prefix, keyword, stmt.name, stmt.value)
+}
+
+fn write_label(f: &mut fmt::Formatter, label: &Option<Identifier>) -> fmt::Result {
+    match label {
+        Some(label) => write!(f, "{}: ", label),
+        None => Ok(()),
+    }
+}
+
+fn write_loop_control(f: &mut fmt::Formatter, keyword: &str, label: &Option<Identifier>) -> fmt::Result {
+    match label {
+        Some(label) => write!(f, "{} {};", keyword, label),
+        None => write!(f, "{};", keyword),
+    }
+}
+
+/// Every operator application, index and slice is wrapped in parentheses,
+/// so the printed form shows how the parser grouped it, e.g. `1 + 2 * 3`
+/// prints as `(1 + (2 * 3))`. Parsing the output gives back the same tree.
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expression::Identifier(ident) => write!(f, "{}", ident),
+            Expression::IntegerLiteral(value, _) => write!(f, "{}", value),
+            // `{:?}` always includes a `.` or exponent, so the literal
+            // reads back as a float.
+            Expression::FloatLiteral(value, _) => write!(f, "{:?}", value),
+            Expression::Boolean(value, _) => write!(f, "{}", value),
+            Expression::StringLiteral(value, _) => write!(f, "\"{}\"", token::escape(value)),
+            Expression::ArrayLiteral(elements, _) => {
+                write!(f, "[")?;
+                write_list(f, elements)?;
+                write!(f, "]")
+            }
+            Expression::HashLiteral(pairs, _) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", key, value)?;
+                }
+                write!(f, "}}")
+            }
+            Expression::PrefixExpression(op, right, _) => write!(f, "({}{})", op, right),
+            Expression::InfixExpression(left, op, right, _) | Expression::AssignExpression(left, op, right, _) => {
+                write!(f, "({} {} {})", left, op, right)
+            }
+            Expression::IfExpression(condition, consequence, alternative, _) => {
+                write!(f, "if {} {}", Parenthesized(condition), consequence)?;
+                match alternative {
+                    Some(alternative) => write!(f, " else {}", alternative),
+                    None => Ok(()),
+                }
+            }
+            Expression::FunctionLiteral(params, body, _) => {
+                write!(f, "fn(")?;
+                write_list(f, params)?;
+                write!(f, ") {}", body)
+            }
+            Expression::CallExpression(function, args, _) => {
+                write!(f, "{}(", function)?;
+                write_list(f, args)?;
+                write!(f, ")")
+            }
+            Expression::IndexExpression(left, index, _) => write!(f, "({}[{}])", left, index),
+            Expression::SliceExpression(left, start, end, _) => {
+                write!(f, "({}[", left)?;
+                if let Some(start) = start {
+                    write!(f, "{}", start)?;
+                }
+                write!(f, ":")?;
+                if let Some(end) = end {
+                    write!(f, "{}", end)?;
+                }
+                write!(f, "])")
+            }
+        }
+    }
+}
+
+fn write_list<T: fmt::Display>(f: &mut fmt::Formatter, items: &[T]) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
+/// An `if` or `while` condition, which the grammar requires to be in
+/// parentheses. Expressions that already print their own are not wrapped
+/// a second time.
+struct Parenthesized<'a>(&'a Expression);
+
+impl fmt::Display for Parenthesized<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Expression::PrefixExpression(..)
+            | Expression::InfixExpression(..)
+            | Expression::AssignExpression(..)
+            | Expression::IndexExpression(..)
+            | Expression::SliceExpression(..) => write!(f, "{}", self.0),
+            expr => write!(f, "({})", expr),
+        }
+    }
+}
+
+impl fmt::Display for BlockStatement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{{")?;
+        for statement in &self.statements {
+            write!(f, " {}", statement)?;
+        }
+        write!(f, " }}")
+    }
+}
+
+impl fmt::Display for Identifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::*;
+    use crate::lexer::*;
+    use crate::parser::*;
+
+    fn parse(input: &str) -> ast::Program {
+        let l = lexer::Lexer::new(input.to_string());
+        let mut p = parser::Parser::new(l);
+        let program = p.parse_program();
+        assert!(p.errors().is_empty(), "input: {}, errors: {:?}", input, p.errors());
+        program
+    }
+
+    #[test]
+    fn display() {
+        let tests = [
+            ("let x = 1 + 2 * 3;", "let x = (1 + (2 * 3));\n"),
+            ("var s = \"a\\\"b\\n\";", "let mut s = \"a\\\"b\\n\";\n"),
+            ("/// Doc.\n///\n///   indented\nlet y = -2.5e-3;", "/// Doc.\n///\n///   indented\nlet y = (-0.0025);\n"),
+            ("return f(a, b)[0]", "return (f(a, b)[0]);\n"),
+            ("if (x) { y } else { z; 1 }", "if (x) { y; } else { z; 1; };\n"),
+            ("var i = 0; while (i < 3) { i += 1; }", "let mut i = 0;\nwhile (i < 3) { (i += 1); }\n"),
+            ("outer: for x in 0..n { break outer; continue }", "outer: for x in (0 .. n) { break outer; continue; }\n"),
+            ("fn() {}", "fn() { };\n"),
+            (
+                "import \"a.rk\" as a\nimport {x, y as z} from \"b.rk\"",
+                "import \"a.rk\" as a;\nimport { x, y as z } from \"b.rk\";\n",
+            ),
+            ("/// Doc.\nexport var n = 1", "/// Doc.\nexport let mut n = 1;\n"),
+            ("{\"k\": [1, 2][1:], 2: a[:-1]}", "{\"k\": ([1, 2][1:]), 2: (a[:(-1)])};\n"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(parse(input).to_string(), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let tests = [
+            "let mut x = 1; x = x * 2 ** 3 ** 2; x -= -x;",
+            "let a = [1, \"two\", 3.0, true, [0x1F, 1_000]]; a[1:]; a[:2]; a[:]; a[-1];",
+            "let mut h = {\"a\": 1, 2: fn(x) { return x; }}; h[\"a\"] += h[2](1);",
+            "/// Adds.\n/// Twice.\nlet add = fn(a, b) { let c = a + b; c + c };",
+            "if (!ok || a < b && b <= c) { 1 } else { if (d) { 2 } };",
+            "var x = 0; if (x = 1) { 2 }; while ((x)) { break; }",
+            "outer: while (true) { inner: for k in {1: 2} { continue outer; } }",
+            "for i in 0..10 { puts(\"\\t\\u{1}\", i % 3 != 0 == false); }",
+            "fn(f) { f(f) }(fn(g) { 1e300 })(-(-1))[0][1:2];",
+            "import \"lib/math.rk\" as math; import { add, sub as minus } from \"../ops.rk\";",
+            "/// Exported.\nexport let mut x = 1; export let f = fn() { x };",
+        ];
+        for input in tests {
+            let printed = parse(input).to_string();
+            assert_eq!(shape(&parse(&printed)), shape(&parse(input)), "input: {}", input);
+            assert_eq!(parse(&printed).to_string(), printed, "input: {}", input);
+        }
+    }
+
+    /// The tree of `program` without its spans, which printing moves, so
+    /// two trees can be compared.
+    fn shape(program: &ast::Program) -> String {
+        let mut debug = format!("{:?}", program);
+        while let Some(start) = debug.find("Span {") {
+            let end = start + debug[start..].find('}').expect("a Span's closing brace") + 1;
+            debug.replace_range(start..end, "_");
+        }
+        debug
+    }
 }
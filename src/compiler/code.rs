@@ -0,0 +1,328 @@
+use std::fmt::Write as _;
+
+/// A bytecode instruction. Each is one byte, followed by its operands as
+/// big-endian integers of the widths in its `Definition`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Opcode {
+    /// Pushes the constant at the index.
+    Constant,
+    Pop,
+    Null,
+    True,
+    False,
+
+    // Binary operators: pop the right operand, then the left, and push the
+    // result.
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Pow,
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    Range,
+
+    Minus,
+    Bang,
+
+    /// Continues at the offset.
+    Jump,
+    /// Pops a value and continues at the offset if it is not truthy.
+    JumpNotTruthy,
+
+    GetGlobal,
+    /// Pops a value and binds the global to it.
+    SetGlobal,
+    GetLocal,
+    /// Pops a value and binds the local to it.
+    SetLocal,
+    /// Unbinds a local, so the next iteration of a loop starts afresh even
+    /// if a closure captured the last one's.
+    ClearLocal,
+    /// Pushes the value of a variable the current closure captured.
+    GetFree,
+    GetBuiltin,
+    /// Pushes the cell holding a local, for a closure to capture.
+    CaptureLocal,
+    /// Pushes the cell of a variable the current closure captured, for a
+    /// closure nested in it to capture in turn.
+    CaptureFree,
+    /// Pops as many cells as the second operand says and pushes a closure
+    /// of the compiled function constant at the first operand over them.
+    Closure,
+
+    /// Pops as many elements as the operand says and pushes an array.
+    Array,
+    /// Pops as many key-value pairs as the operand says and pushes a hash.
+    Hash,
+    Index,
+    /// Pops the bounds the operand's flags say are present (`SLICE_START`,
+    /// `SLICE_END`), then the value, and pushes the slice.
+    Slice,
+
+    /// Pushes the value of the variable at a scope (`SCOPE_GLOBAL`,
+    /// `SCOPE_LOCAL`, `SCOPE_FREE`) and index, indexed in turn by the keys on
+    /// top of the stack, as many as the last operand says. The keys stay.
+    LoadPath,
+    /// Pops a value and that many keys, stores the value at the position
+    /// they lead to within the variable, and pushes the value.
+    Assign,
+
+    /// Replaces an array, hash or range with what a `for` loop iterates
+    /// over and a counter.
+    IterStart,
+    /// Pushes the next element of the iteration below it, or pops the
+    /// iteration and continues at the offset if it is done.
+    IterNext,
+
+    /// Calls the function below as many arguments as the operand says.
+    Call,
+    /// Returns the value on top of the stack from the current function.
+    ReturnValue,
+}
+
+pub const SCOPE_GLOBAL: usize = 0;
+pub const SCOPE_LOCAL: usize = 1;
+pub const SCOPE_FREE: usize = 2;
+
+pub const SLICE_START: usize = 1;
+pub const SLICE_END: usize = 2;
+
+/// Every opcode, in the order of their byte values.
+const OPCODES: &[Opcode] = &[
+    Opcode::Constant,
+    Opcode::Pop,
+    Opcode::Null,
+    Opcode::True,
+    Opcode::False,
+    Opcode::Add,
+    Opcode::Sub,
+    Opcode::Mul,
+    Opcode::Div,
+    Opcode::Mod,
+    Opcode::Pow,
+    Opcode::Equal,
+    Opcode::NotEqual,
+    Opcode::Less,
+    Opcode::Greater,
+    Opcode::LessEqual,
+    Opcode::GreaterEqual,
+    Opcode::Range,
+    Opcode::Minus,
+    Opcode::Bang,
+    Opcode::Jump,
+    Opcode::JumpNotTruthy,
+    Opcode::GetGlobal,
+    Opcode::SetGlobal,
+    Opcode::GetLocal,
+    Opcode::SetLocal,
+    Opcode::ClearLocal,
+    Opcode::GetFree,
+    Opcode::GetBuiltin,
+    Opcode::CaptureLocal,
+    Opcode::CaptureFree,
+    Opcode::Closure,
+    Opcode::Array,
+    Opcode::Hash,
+    Opcode::Index,
+    Opcode::Slice,
+    Opcode::LoadPath,
+    Opcode::Assign,
+    Opcode::IterStart,
+    Opcode::IterNext,
+    Opcode::Call,
+    Opcode::ReturnValue,
+];
+
+/// An opcode's name and the width in bytes of each of its operands.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Definition {
+    pub name: &'static str,
+    pub operand_widths: &'static [usize],
+}
+
+impl Opcode {
+    pub fn from_byte(byte: u8) -> Option<Opcode> {
+        OPCODES.get(byte as usize).copied()
+    }
+
+    pub fn definition(self) -> Definition {
+        let (name, operand_widths): (&'static str, &'static [usize]) = match self {
+            Opcode::Constant => ("OpConstant", &[2]),
+            Opcode::Pop => ("OpPop", &[]),
+            Opcode::Null => ("OpNull", &[]),
+            Opcode::True => ("OpTrue", &[]),
+            Opcode::False => ("OpFalse", &[]),
+            Opcode::Add => ("OpAdd", &[]),
+            Opcode::Sub => ("OpSub", &[]),
+            Opcode::Mul => ("OpMul", &[]),
+            Opcode::Div => ("OpDiv", &[]),
+            Opcode::Mod => ("OpMod", &[]),
+            Opcode::Pow => ("OpPow", &[]),
+            Opcode::Equal => ("OpEqual", &[]),
+            Opcode::NotEqual => ("OpNotEqual", &[]),
+            Opcode::Less => ("OpLess", &[]),
+            Opcode::Greater => ("OpGreater", &[]),
+            Opcode::LessEqual => ("OpLessEqual", &[]),
+            Opcode::GreaterEqual => ("OpGreaterEqual", &[]),
+            Opcode::Range => ("OpRange", &[]),
+            Opcode::Minus => ("OpMinus", &[]),
+            Opcode::Bang => ("OpBang", &[]),
+            Opcode::Jump => ("OpJump", &[2]),
+            Opcode::JumpNotTruthy => ("OpJumpNotTruthy", &[2]),
+            Opcode::GetGlobal => ("OpGetGlobal", &[2]),
+            Opcode::SetGlobal => ("OpSetGlobal", &[2]),
+            Opcode::GetLocal => ("OpGetLocal", &[1]),
+            Opcode::SetLocal => ("OpSetLocal", &[1]),
+            Opcode::ClearLocal => ("OpClearLocal", &[1]),
+            Opcode::GetFree => ("OpGetFree", &[1]),
+            Opcode::GetBuiltin => ("OpGetBuiltin", &[1]),
+            Opcode::CaptureLocal => ("OpCaptureLocal", &[1]),
+            Opcode::CaptureFree => ("OpCaptureFree", &[1]),
+            Opcode::Closure => ("OpClosure", &[2, 1]),
+            Opcode::Array => ("OpArray", &[2]),
+            Opcode::Hash => ("OpHash", &[2]),
+            Opcode::Index => ("OpIndex", &[]),
+            Opcode::Slice => ("OpSlice", &[1]),
+            Opcode::LoadPath => ("OpLoadPath", &[1, 2, 1]),
+            Opcode::Assign => ("OpAssign", &[1, 2, 1]),
+            Opcode::IterStart => ("OpIterStart", &[]),
+            Opcode::IterNext => ("OpIterNext", &[2]),
+            Opcode::Call => ("OpCall", &[1]),
+            Opcode::ReturnValue => ("OpReturnValue", &[]),
+        };
+        Definition { name, operand_widths }
+    }
+}
+
+/// Encodes an instruction. Operands wider than their slot are truncated, so
+/// callers check limits first.
+pub fn make(op: Opcode, operands: &[usize]) -> Vec<u8> {
+    let definition = op.definition();
+    let mut instruction = vec![op as u8];
+    for (&operand, &width) in operands.iter().zip(definition.operand_widths) {
+        match width {
+            2 => instruction.extend_from_slice(&(operand as u16).to_be_bytes()),
+            1 => instruction.push(operand as u8),
+            _ => unreachable!("operand width {}", width),
+        }
+    }
+    instruction
+}
+
+/// Decodes the operands of an instruction of `definition` from the start of
+/// `instructions`, returning them and the number of bytes they take.
+pub fn read_operands(definition: &Definition, instructions: &[u8]) -> (Vec<usize>, usize) {
+    let mut operands = Vec::with_capacity(definition.operand_widths.len());
+    let mut offset = 0;
+    for &width in definition.operand_widths {
+        match width {
+            2 => operands.push(read_u16(instructions, offset) as usize),
+            1 => operands.push(instructions[offset] as usize),
+            _ => unreachable!("operand width {}", width),
+        }
+        offset += width;
+    }
+    (operands, offset)
+}
+
+pub fn read_u16(instructions: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([instructions[offset], instructions[offset + 1]])
+}
+
+/// Decodes the instruction at `offset`, returning its opcode, its operands
+/// and its length in bytes, or `None` if the byte there is not an opcode or
+/// the operands run past the end.
+pub fn decode_instruction(instructions: &[u8], offset: usize) -> Option<(Opcode, Vec<usize>, usize)> {
+    let op = Opcode::from_byte(instructions[offset])?;
+    let definition = op.definition();
+    let width: usize = definition.operand_widths.iter().sum();
+    let operands = instructions.get(offset + 1..offset + 1 + width)?;
+    let (operands, read) = read_operands(&definition, operands);
+    Some((op, operands, 1 + read))
+}
+
+/// Writes one instruction as its offset, name and operands, returning its
+/// length in bytes.
+pub fn write_instruction(out: &mut String, instructions: &[u8], offset: usize) -> usize {
+    let (op, operands, len) = match decode_instruction(instructions, offset) {
+        Some(decoded) => decoded,
+        None => {
+            let _ = write!(out, "{:04} ERROR: bad instruction {}", offset, instructions[offset]);
+            return 1;
+        }
+    };
+    let _ = write!(out, "{:04} {}", offset, op.definition().name);
+    for operand in operands {
+        let _ = write!(out, " {}", operand);
+    }
+    len
+}
+
+/// Lists instructions one per line, each with its byte offset.
+pub fn disassemble(instructions: &[u8]) -> String {
+    let mut out = String::new();
+    let mut i = 0;
+    while i < instructions.len() {
+        i += write_instruction(&mut out, instructions, i);
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::*;
+
+    #[test]
+    fn opcodes_match_their_bytes() {
+        for (i, &op) in code::OPCODES.iter().enumerate() {
+            assert_eq!(op as usize, i, "{:?}", op);
+            assert_eq!(code::Opcode::from_byte(i as u8), Some(op));
+        }
+        assert_eq!(code::Opcode::from_byte(code::OPCODES.len() as u8), None);
+    }
+
+    #[test]
+    fn make() {
+        let tests = [
+            (code::Opcode::Constant, vec![65534], vec![code::Opcode::Constant as u8, 255, 254]),
+            (code::Opcode::Add, vec![], vec![code::Opcode::Add as u8]),
+            (code::Opcode::GetLocal, vec![255], vec![code::Opcode::GetLocal as u8, 255]),
+            (code::Opcode::Closure, vec![65534, 255], vec![code::Opcode::Closure as u8, 255, 254, 255]),
+        ];
+        for (op, operands, expected) in tests {
+            let instruction = code::make(op, &operands);
+            assert_eq!(instruction, expected, "{:?}", op);
+            let (read, n) = code::read_operands(&op.definition(), &instruction[1..]);
+            assert_eq!(read, operands);
+            assert_eq!(n, instruction.len() - 1);
+        }
+    }
+
+    #[test]
+    fn disassemble() {
+        let instructions = [
+            code::make(code::Opcode::Add, &[]),
+            code::make(code::Opcode::GetLocal, &[1]),
+            code::make(code::Opcode::Constant, &[2]),
+            code::make(code::Opcode::Constant, &[65535]),
+            code::make(code::Opcode::Assign, &[1, 2, 3]),
+        ]
+        .concat();
+        let expected = "\
+0000 OpAdd
+0001 OpGetLocal 1
+0003 OpConstant 2
+0006 OpConstant 65535
+0009 OpAssign 1 2 3
+";
+        assert_eq!(code::disassemble(&instructions), expected);
+    }
+}
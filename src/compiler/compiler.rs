@@ -0,0 +1,972 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::rc::Rc;
+
+use crate::ast::*;
+use crate::compiler::*;
+use crate::evaluator::*;
+use crate::object::*;
+use crate::token::*;
+
+/// A compiled program: the code of its top level, the constants the code
+/// refers to by index and the names of its globals, by index.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bytecode {
+    pub main: Rc<object::CompiledFunction>,
+    pub constants: Vec<object::Object>,
+    pub globals: Vec<String>,
+}
+
+impl Bytecode {
+    /// Lists the instructions of the top level and then of each function
+    /// constant. Operands that index the constants, globals or builtins are
+    /// followed by what they refer to, and each run of instructions from
+    /// one source line is headed by the line number and, given the
+    /// `source`, the line's text.
+    pub fn disassemble(&self, source: Option<&str>) -> String {
+        let mut out = String::new();
+        out.push_str("== main ==\n");
+        self.disassemble_function(&mut out, &self.main, source);
+        for (i, constant) in self.constants.iter().enumerate() {
+            if let object::Object::CompiledFunction(function) = constant {
+                let _ = writeln!(out, "\n== constant {}: {} ==", i, function);
+                self.disassemble_function(&mut out, function, source);
+            }
+        }
+        out
+    }
+
+    fn disassemble_function(&self, out: &mut String, function: &object::CompiledFunction, source: Option<&str>) {
+        let instructions = &function.instructions;
+        let mut line = None;
+        let mut offset = 0;
+        while offset < instructions.len() {
+            if function.line(offset) != line {
+                line = function.line(offset);
+                if let Some(line) = line {
+                    let _ = match source.and_then(|source| source.lines().nth(line - 1)) {
+                        Some(text) => writeln!(out, "; {}: {}", line, text.trim()),
+                        None => writeln!(out, "; line {}", line),
+                    };
+                }
+            }
+            let start = out.len();
+            let len = code::write_instruction(out, instructions, offset);
+            if let Some(note) = self.operand_note(instructions, offset) {
+                let width = out.len() - start;
+                let _ = write!(out, "{:pad$}; {}", "", note, pad = 28usize.saturating_sub(width).max(1));
+            }
+            out.push('\n');
+            offset += len;
+        }
+    }
+
+    /// What the instruction at `offset` refers to by index, if anything.
+    fn operand_note(&self, instructions: &[u8], offset: usize) -> Option<String> {
+        let (op, operands, _) = code::decode_instruction(instructions, offset)?;
+        let note = match op {
+            code::Opcode::Constant | code::Opcode::Closure => match self.constants.get(operands[0])? {
+                object::Object::String(value) => format!("\"{}\"", token::escape(value)),
+                constant => constant.to_string(),
+            },
+            code::Opcode::GetGlobal | code::Opcode::SetGlobal => self.globals.get(operands[0])?.clone(),
+            code::Opcode::LoadPath | code::Opcode::Assign if operands[0] == code::SCOPE_GLOBAL => {
+                self.globals.get(operands[1])?.clone()
+            }
+            code::Opcode::GetBuiltin => builtins::BUILTINS.get(operands[0])?.name.to_string(),
+            _ => return None,
+        };
+        Some(note)
+    }
+}
+
+/// What a name refers to where it is used.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Symbol {
+    Global(usize),
+    Local(usize),
+    /// A variable of an enclosing function, by its index in the closure.
+    Free(usize),
+    Builtin(usize),
+}
+
+/// Where a closure's captured variable lives in the function that makes the
+/// closure.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Capture {
+    Local(usize),
+    Free(usize),
+}
+
+/// A loop being compiled, so `break` and `continue` can find it.
+struct Loop {
+    label: Option<String>,
+    /// The stack depth at the start of each statement of the body.
+    depth: usize,
+    break_jumps: Vec<usize>,
+    continue_jumps: Vec<usize>,
+}
+
+/// The state of one function being compiled; the program's top level is
+/// compiled as a function too.
+#[derive(Default)]
+struct FunctionScope {
+    instructions: Vec<u8>,
+    /// The line table of `object::CompiledFunction`.
+    lines: Vec<(usize, usize)>,
+    /// Local bindings, innermost scope last. Like the evaluator's
+    /// environments there is one scope per function and one per `for` loop
+    /// iteration. At the top level the outermost scope is the globals, so
+    /// only loop scopes are listed.
+    scopes: Vec<HashMap<String, usize>>,
+    num_locals: usize,
+    free: Vec<(String, Capture)>,
+    /// The names the function's body binds at its top level, so that a
+    /// function defined earlier in the body can refer to one bound later.
+    declared: HashSet<String>,
+    loops: Vec<Loop>,
+    /// How many values the code so far leaves on the stack, so `break` and
+    /// `continue` can pop the ones above their loop's.
+    depth: usize,
+}
+
+/// Lowers a program's syntax tree to bytecode for `vm::VM`.
+///
+/// Names are resolved as the compiler meets them: a name refers to the
+/// innermost binding made before it, much as the evaluator looks names up in
+/// the environments that exist when it runs. The exception is a name a
+/// nested function uses before an enclosing function binds it, which refers
+/// to that later binding, as it will by the time the nested function runs.
+/// A local that a closure captures is moved into a shared cell, so
+/// assignments through either are seen by both.
+pub struct Compiler {
+    constants: Vec<object::Object>,
+    globals: HashMap<String, usize>,
+    global_names: Vec<String>,
+    functions: Vec<FunctionScope>,
+}
+
+impl Default for Compiler {
+    fn default() -> Compiler {
+        Compiler::new()
+    }
+}
+
+impl Compiler {
+    pub fn new() -> Compiler {
+        Compiler {
+            constants: vec![],
+            globals: HashMap::new(),
+            global_names: vec![],
+            functions: vec![FunctionScope::default()],
+        }
+    }
+
+    pub fn compile(&mut self, program: &ast::Program) -> Result<(), String> {
+        self.compile_block_value(&program.statements)?;
+        self.emit(code::Opcode::ReturnValue, &[]);
+        Ok(())
+    }
+
+    pub fn bytecode(self) -> Bytecode {
+        let main = &self.functions[0];
+        Bytecode {
+            main: Rc::new(object::CompiledFunction {
+                instructions: main.instructions.clone(),
+                num_locals: main.num_locals,
+                num_free: 0,
+                parameters: vec![],
+                lines: main.lines.clone(),
+            }),
+            constants: self.constants,
+            globals: self.global_names,
+        }
+    }
+
+    /// Compiles statements whose last one gives their value, as a block's
+    /// does: the value is left on the stack, or null if the last statement
+    /// is not an expression.
+    fn compile_block_value(&mut self, statements: &[ast::Statement]) -> Result<(), String> {
+        for (i, statement) in statements.iter().enumerate() {
+            match statement {
+                ast::Statement::ExpressionStatement(expr, span) if i == statements.len() - 1 => {
+                    self.mark_line(span.line);
+                    return self.compile_expression(expr);
+                }
+                statement => self.compile_statement(statement)?,
+            }
+        }
+        self.emit(code::Opcode::Null, &[]);
+        Ok(())
+    }
+
+    fn compile_statement(&mut self, statement: &ast::Statement) -> Result<(), String> {
+        self.mark_line(statement.span().line);
+        match statement {
+            ast::Statement::LetStatement(ast::LetStatement { name, value, .. })
+            | ast::Statement::ExportStatement(ast::LetStatement { name, value, .. }, _) => {
+                // A function is bound before its body is compiled so it can
+                // call itself; any other value may refer to an outer binding
+                // of the same name.
+                let symbol = match value {
+                    ast::Expression::FunctionLiteral(..) => Some(self.define(&name.value)?),
+                    _ => None,
+                };
+                self.compile_expression(value)?;
+                let symbol = match symbol {
+                    Some(symbol) => symbol,
+                    None => self.define(&name.value)?,
+                };
+                match symbol {
+                    Symbol::Global(index) => self.emit(code::Opcode::SetGlobal, &[index]),
+                    Symbol::Local(index) => self.emit(code::Opcode::SetLocal, &[index]),
+                    Symbol::Free(_) | Symbol::Builtin(_) => unreachable!("bindings are global or local"),
+                };
+            }
+            ast::Statement::ReturnStatement(value, _) => {
+                self.compile_expression(value)?;
+                self.emit(code::Opcode::ReturnValue, &[]);
+            }
+            ast::Statement::ExpressionStatement(expr, _) => {
+                self.compile_expression(expr)?;
+                self.emit(code::Opcode::Pop, &[]);
+            }
+            ast::Statement::WhileStatement(label, condition, body, _) => {
+                let start = self.scope().instructions.len();
+                self.compile_expression(condition)?;
+                let exit = self.emit(code::Opcode::JumpNotTruthy, &[0]);
+                self.push_loop(label);
+                for statement in &body.statements {
+                    self.compile_statement(statement)?;
+                }
+                self.emit_jump(code::Opcode::Jump, start)?;
+                let finished = self.scope_mut().loops.pop().expect("loop");
+                self.patch_jumps(&finished.continue_jumps, start)?;
+                let end = self.scope().instructions.len();
+                self.patch_jumps(&[exit], end)?;
+                self.patch_jumps(&finished.break_jumps, end)?;
+            }
+            ast::Statement::ForStatement(label, variable, iterable, body, _) => {
+                self.compile_expression(iterable)?;
+                self.emit(code::Opcode::IterStart, &[]);
+                let next = self.emit(code::Opcode::IterNext, &[0]);
+                self.scope_mut().scopes.push(HashMap::new());
+                match self.define(&variable.value)? {
+                    Symbol::Local(index) => self.emit(code::Opcode::SetLocal, &[index]),
+                    _ => unreachable!("loop variables are local"),
+                };
+                self.push_loop(label);
+                for statement in &body.statements {
+                    self.compile_statement(statement)?;
+                }
+                let finished = self.scope_mut().loops.pop().expect("loop");
+                let continue_target = self.scope().instructions.len();
+                self.patch_jumps(&finished.continue_jumps, continue_target)?;
+                let mut locals: Vec<usize> = self.scope_mut().scopes.pop().expect("scope").into_values().collect();
+                locals.sort_unstable();
+                for index in locals {
+                    self.emit(code::Opcode::ClearLocal, &[index]);
+                }
+                self.emit_jump(code::Opcode::Jump, next)?;
+
+                // `break` leaves the iteration on the stack; running out of
+                // elements has already popped it.
+                let break_target = self.scope().instructions.len();
+                self.patch_jumps(&finished.break_jumps, break_target)?;
+                self.emit(code::Opcode::Pop, &[]);
+                self.emit(code::Opcode::Pop, &[]);
+                let end = self.scope().instructions.len();
+                self.patch_jumps(&[next], end)?;
+            }
+            ast::Statement::BreakStatement(label, _) => self.compile_loop_control(label, true)?,
+            ast::Statement::ContinueStatement(label, _) => self.compile_loop_control(label, false)?,
+            // Modules are loaded as the tree evaluator runs; a compiled
+            // program is a single file.
+            ast::Statement::ImportStatement(..) => {
+                return Err("`import` is not supported by the compiler; only the tree engine loads modules".to_string())
+            }
+        }
+        Ok(())
+    }
+
+    fn push_loop(&mut self, label: &Option<ast::Identifier>) {
+        let depth = self.scope().depth;
+        self.scope_mut().loops.push(Loop {
+            label: label.as_ref().map(|label| label.value.clone()),
+            depth,
+            break_jumps: vec![],
+            continue_jumps: vec![],
+        });
+    }
+
+    /// Pops what the enclosing expressions have pushed since the start of
+    /// the loop body, then jumps out of or back to the loop.
+    fn compile_loop_control(&mut self, label: &Option<ast::Identifier>, is_break: bool) -> Result<(), String> {
+        let loops = &self.scope().loops;
+        let target = match label {
+            None => loops.len().checked_sub(1),
+            Some(label) => loops.iter().rposition(|l| l.label.as_deref() == Some(label.value.as_str())),
+        };
+        let target = match target {
+            Some(target) => target,
+            None => {
+                let keyword = if is_break { "break" } else { "continue" };
+                return Err(format!("`{}` outside of a loop", keyword));
+            }
+        };
+        let extra = self.scope().depth - loops[target].depth;
+        for _ in 0..extra {
+            self.emit(code::Opcode::Pop, &[]);
+        }
+        let jump = self.emit(code::Opcode::Jump, &[0]);
+        let target = &mut self.scope_mut().loops[target];
+        if is_break {
+            target.break_jumps.push(jump);
+        } else {
+            target.continue_jumps.push(jump);
+        }
+        // The code after the jump, if any, is unreachable but is compiled
+        // as if the values were still there.
+        self.scope_mut().depth += extra;
+        Ok(())
+    }
+
+    fn compile_expression(&mut self, expr: &ast::Expression) -> Result<(), String> {
+        // Long chains of operators recurse as deep as they are long.
+        if limits::check_stack().is_err() {
+            return Err("expression too long to compile; bind parts of it to names with `let`".to_string());
+        }
+        match expr {
+            ast::Expression::Identifier(ident) => {
+                match self.resolve(&ident.value) {
+                    Symbol::Global(index) => self.emit(code::Opcode::GetGlobal, &[index]),
+                    Symbol::Local(index) => self.emit(code::Opcode::GetLocal, &[index]),
+                    Symbol::Free(index) => self.emit(code::Opcode::GetFree, &[index]),
+                    Symbol::Builtin(index) => self.emit(code::Opcode::GetBuiltin, &[index]),
+                };
+            }
+            ast::Expression::IntegerLiteral(value, _) => self.emit_constant(object::Object::Integer(*value))?,
+            ast::Expression::FloatLiteral(value, _) => self.emit_constant(object::Object::Float(*value))?,
+            ast::Expression::StringLiteral(value, _) => self.emit_constant(object::Object::String(value.clone()))?,
+            ast::Expression::Boolean(true, _) => {
+                self.emit(code::Opcode::True, &[]);
+            }
+            ast::Expression::Boolean(false, _) => {
+                self.emit(code::Opcode::False, &[]);
+            }
+            ast::Expression::ArrayLiteral(elements, _) => {
+                for element in elements {
+                    self.compile_expression(element)?;
+                }
+                let len = check_operand(elements.len(), u16::MAX as usize, "array elements")?;
+                self.emit(code::Opcode::Array, &[len]);
+            }
+            ast::Expression::HashLiteral(pairs, _) => {
+                for (key, value) in pairs {
+                    self.compile_expression(key)?;
+                    self.compile_expression(value)?;
+                }
+                let len = check_operand(pairs.len(), u16::MAX as usize, "hash entries")?;
+                self.emit(code::Opcode::Hash, &[len]);
+            }
+            ast::Expression::PrefixExpression(operator, right, _) => {
+                self.compile_expression(right)?;
+                match operator {
+                    token::TokenKind::Minus => self.emit(code::Opcode::Minus, &[]),
+                    token::TokenKind::Bang => self.emit(code::Opcode::Bang, &[]),
+                    operator => return Err(format!("unknown operator: {}", operator)),
+                };
+            }
+            ast::Expression::InfixExpression(left, token::TokenKind::And, right, _) => {
+                self.compile_logical_expression(left, right, true)?;
+            }
+            ast::Expression::InfixExpression(left, token::TokenKind::Or, right, _) => {
+                self.compile_logical_expression(left, right, false)?;
+            }
+            ast::Expression::InfixExpression(left, operator, right, _) => {
+                self.compile_expression(left)?;
+                self.compile_expression(right)?;
+                let op = infix_opcode(operator).ok_or_else(|| format!("unknown operator: {}", operator))?;
+                self.emit(op, &[]);
+            }
+            ast::Expression::AssignExpression(target, operator, value, _) => {
+                self.compile_assign_expression(target, operator, value)?;
+            }
+            ast::Expression::IfExpression(condition, consequence, alternative, _) => {
+                self.compile_expression(condition)?;
+                let otherwise = self.emit(code::Opcode::JumpNotTruthy, &[0]);
+                let depth = self.scope().depth;
+                self.compile_block_value(&consequence.statements)?;
+                let end = self.emit(code::Opcode::Jump, &[0]);
+                self.patch_here(otherwise)?;
+                self.scope_mut().depth = depth;
+                match alternative {
+                    Some(alternative) => self.compile_block_value(&alternative.statements)?,
+                    None => {
+                        self.emit(code::Opcode::Null, &[]);
+                    }
+                }
+                self.patch_here(end)?;
+            }
+            ast::Expression::FunctionLiteral(parameters, body, _) => {
+                self.compile_function_literal(parameters, body)?;
+            }
+            ast::Expression::CallExpression(function, arguments, _) => {
+                self.compile_expression(function)?;
+                for argument in arguments {
+                    self.compile_expression(argument)?;
+                }
+                let len = check_operand(arguments.len(), u8::MAX as usize, "call arguments")?;
+                self.emit(code::Opcode::Call, &[len]);
+            }
+            ast::Expression::IndexExpression(left, index, _) => {
+                self.compile_expression(left)?;
+                self.compile_expression(index)?;
+                self.emit(code::Opcode::Index, &[]);
+            }
+            ast::Expression::SliceExpression(left, start, end, _) => {
+                self.compile_expression(left)?;
+                let mut flags = 0;
+                if let Some(start) = start {
+                    self.compile_expression(start)?;
+                    flags |= code::SLICE_START;
+                }
+                if let Some(end) = end {
+                    self.compile_expression(end)?;
+                    flags |= code::SLICE_END;
+                }
+                self.emit(code::Opcode::Slice, &[flags]);
+            }
+        }
+        Ok(())
+    }
+
+    /// Compiles `&&` (`and`) or `||` so the right operand only runs when the
+    /// left one does not decide the result. Like the evaluator, the result
+    /// is the truthiness of the deciding operand as a boolean.
+    fn compile_logical_expression(
+        &mut self,
+        left: &ast::Expression,
+        right: &ast::Expression,
+        and: bool,
+    ) -> Result<(), String> {
+        self.compile_expression(left)?;
+        let otherwise = self.emit(code::Opcode::JumpNotTruthy, &[0]);
+        let depth = self.scope().depth;
+        if and {
+            self.compile_truthiness(right)?;
+        } else {
+            self.emit(code::Opcode::True, &[]);
+        }
+        let end = self.emit(code::Opcode::Jump, &[0]);
+        self.patch_here(otherwise)?;
+        self.scope_mut().depth = depth;
+        if and {
+            self.emit(code::Opcode::False, &[]);
+        } else {
+            self.compile_truthiness(right)?;
+        }
+        self.patch_here(end)
+    }
+
+    fn compile_truthiness(&mut self, expr: &ast::Expression) -> Result<(), String> {
+        self.compile_expression(expr)?;
+        self.emit(code::Opcode::Bang, &[]);
+        self.emit(code::Opcode::Bang, &[]);
+        Ok(())
+    }
+
+    /// Compiles an assignment in the evaluator's order: the indices, then
+    /// for a compound assignment the current value, then the right-hand
+    /// side.
+    fn compile_assign_expression(
+        &mut self,
+        target: &ast::Expression,
+        operator: &token::TokenKind,
+        value: &ast::Expression,
+    ) -> Result<(), String> {
+        let mut indices = Vec::new();
+        let mut root = target;
+        while let ast::Expression::IndexExpression(left, index, _) = root {
+            indices.push(index);
+            root = left;
+        }
+        let ident = match root {
+            ast::Expression::Identifier(ident) => ident,
+            _ => return Err("invalid assignment target".to_string()),
+        };
+        let (scope, index) = match self.resolve(&ident.value) {
+            Symbol::Global(index) => (code::SCOPE_GLOBAL, index),
+            Symbol::Local(index) => (code::SCOPE_LOCAL, index),
+            Symbol::Free(index) => (code::SCOPE_FREE, index),
+            Symbol::Builtin(_) => return Err(format!("cannot assign to builtin `{}`", ident.value)),
+        };
+        for index in indices.iter().rev() {
+            self.compile_expression(index)?;
+        }
+        let keys = check_operand(indices.len(), u8::MAX as usize, "nested indices")?;
+
+        match operator.compound_operator() {
+            Some(operator) => {
+                self.emit(code::Opcode::LoadPath, &[scope, index, keys]);
+                self.compile_expression(value)?;
+                let op = infix_opcode(&operator).ok_or_else(|| format!("unknown operator: {}", operator))?;
+                self.emit(op, &[]);
+            }
+            None => self.compile_expression(value)?,
+        }
+        self.emit(code::Opcode::Assign, &[scope, index, keys]);
+        Ok(())
+    }
+
+    fn compile_function_literal(
+        &mut self,
+        parameters: &[ast::Identifier],
+        body: &ast::BlockStatement,
+    ) -> Result<(), String> {
+        let declared = body
+            .statements
+            .iter()
+            .filter_map(|statement| match statement {
+                ast::Statement::LetStatement(stmt) => Some(stmt.name.value.clone()),
+                _ => None,
+            })
+            .collect();
+        self.functions.push(FunctionScope {
+            scopes: vec![HashMap::new()],
+            declared,
+            ..FunctionScope::default()
+        });
+        for parameter in parameters {
+            self.define(&parameter.value)?;
+        }
+        let compiled = self
+            .compile_block_value(&body.statements)
+            .map(|()| self.emit(code::Opcode::ReturnValue, &[]));
+        let function = self.functions.pop().expect("function scope");
+        compiled?;
+
+        let constant = self.add_constant(object::Object::CompiledFunction(Rc::new(object::CompiledFunction {
+            instructions: function.instructions,
+            num_locals: function.num_locals,
+            num_free: function.free.len(),
+            parameters: parameters.iter().map(|p| p.value.clone()).collect(),
+            lines: function.lines,
+        })))?;
+        for (_, capture) in &function.free {
+            match *capture {
+                Capture::Local(index) => self.emit(code::Opcode::CaptureLocal, &[index]),
+                Capture::Free(index) => self.emit(code::Opcode::CaptureFree, &[index]),
+            };
+        }
+        let free = check_operand(function.free.len(), u8::MAX as usize, "captured variables")?;
+        self.emit(code::Opcode::Closure, &[constant, free]);
+        Ok(())
+    }
+
+    /// Binds `name` in the innermost scope, reusing its slot if the scope
+    /// already binds it.
+    fn define(&mut self, name: &str) -> Result<Symbol, String> {
+        let top_level = self.functions.len() == 1;
+        let function = self.scope_mut();
+        let num_locals = function.num_locals;
+        let scope = match function.scopes.last_mut() {
+            Some(scope) => scope,
+            None if top_level => return Ok(Symbol::Global(self.global(name))),
+            None => unreachable!("functions have a scope"),
+        };
+        if let Some(&index) = scope.get(name) {
+            return Ok(Symbol::Local(index));
+        }
+        if num_locals > u8::MAX as usize {
+            return Err("too many local variables in one function".to_string());
+        }
+        scope.insert(name.to_string(), num_locals);
+        function.num_locals += 1;
+        Ok(Symbol::Local(num_locals))
+    }
+
+    fn global(&mut self, name: &str) -> usize {
+        if let Some(&index) = self.globals.get(name) {
+            return index;
+        }
+        let index = self.global_names.len();
+        self.globals.insert(name.to_string(), index);
+        self.global_names.push(name.to_string());
+        index
+    }
+
+    fn resolve(&mut self, name: &str) -> Symbol {
+        let level = self.functions.len() - 1;
+        if !self.is_bound(name) {
+            // Bind it now in the innermost enclosing function that binds it
+            // later; its `let` reuses the slot.
+            if let Some(function) = self.functions[..level].iter_mut().rev().find(|f| f.declared.contains(name)) {
+                if function.num_locals <= u8::MAX as usize {
+                    function.scopes[0].insert(name.to_string(), function.num_locals);
+                    function.num_locals += 1;
+                }
+            }
+        }
+        self.resolve_in(level, name)
+    }
+
+    /// Whether a binding of `name` has been made in any enclosing scope.
+    fn is_bound(&self, name: &str) -> bool {
+        self.globals.contains_key(name)
+            || self.functions.iter().any(|function| function.scopes.iter().any(|scope| scope.contains_key(name)))
+    }
+
+    /// Resolves `name` as seen from the function at `level`. Locals of
+    /// enclosing functions become captured variables of each function in
+    /// between. A name bound nowhere yet is taken to be a global that will
+    /// be bound before it is used, unless it names a builtin.
+    fn resolve_in(&mut self, level: usize, name: &str) -> Symbol {
+        let function = &self.functions[level];
+        for scope in function.scopes.iter().rev() {
+            if let Some(&index) = scope.get(name) {
+                return Symbol::Local(index);
+            }
+        }
+        if let Some(index) = function.free.iter().position(|(free, _)| free == name) {
+            return Symbol::Free(index);
+        }
+        if level == 0 {
+            if let Some(&index) = self.globals.get(name) {
+                return Symbol::Global(index);
+            }
+            if let Some(index) = builtins::BUILTINS.iter().position(|builtin| builtin.name == name) {
+                return Symbol::Builtin(index);
+            }
+            return Symbol::Global(self.global(name));
+        }
+        let capture = match self.resolve_in(level - 1, name) {
+            Symbol::Local(index) => Capture::Local(index),
+            Symbol::Free(index) => Capture::Free(index),
+            symbol => return symbol,
+        };
+        let free = &mut self.functions[level].free;
+        free.push((name.to_string(), capture));
+        Symbol::Free(free.len() - 1)
+    }
+
+    fn scope(&self) -> &FunctionScope {
+        self.functions.last().expect("function scope")
+    }
+
+    fn scope_mut(&mut self) -> &mut FunctionScope {
+        self.functions.last_mut().expect("function scope")
+    }
+
+    fn add_constant(&mut self, constant: object::Object) -> Result<usize, String> {
+        self.constants.push(constant);
+        check_operand(self.constants.len() - 1, u16::MAX as usize, "constants")
+    }
+
+    fn emit_constant(&mut self, constant: object::Object) -> Result<(), String> {
+        let index = self.add_constant(constant)?;
+        self.emit(code::Opcode::Constant, &[index]);
+        Ok(())
+    }
+
+    /// Attributes the instructions emitted from here on to `line`.
+    fn mark_line(&mut self, line: usize) {
+        let function = self.scope_mut();
+        let offset = function.instructions.len();
+        match function.lines.last_mut() {
+            Some(&mut (_, last)) if last == line => {}
+            Some(entry) if entry.0 == offset => entry.1 = line,
+            _ => function.lines.push((offset, line)),
+        }
+    }
+
+    /// Appends an instruction, returning its offset.
+    fn emit(&mut self, op: code::Opcode, operands: &[usize]) -> usize {
+        let function = self.scope_mut();
+        let offset = function.instructions.len();
+        function.instructions.extend(code::make(op, operands));
+        function.depth = (function.depth as isize + stack_effect(op, operands)) as usize;
+        offset
+    }
+
+    fn emit_jump(&mut self, op: code::Opcode, target: usize) -> Result<usize, String> {
+        let target = check_operand(target, u16::MAX as usize, "bytes of code in one function")?;
+        Ok(self.emit(op, &[target]))
+    }
+
+    /// Points the jumps at `offsets` to `target`.
+    fn patch_jumps(&mut self, offsets: &[usize], target: usize) -> Result<(), String> {
+        let target = check_operand(target, u16::MAX as usize, "bytes of code in one function")? as u16;
+        let instructions = &mut self.scope_mut().instructions;
+        for &offset in offsets {
+            instructions[offset + 1..offset + 3].copy_from_slice(&target.to_be_bytes());
+        }
+        Ok(())
+    }
+
+    /// Points the jump at `offset` to the next instruction.
+    fn patch_here(&mut self, offset: usize) -> Result<(), String> {
+        let target = self.scope().instructions.len();
+        self.patch_jumps(&[offset], target)
+    }
+}
+
+fn check_operand(value: usize, max: usize, what: &str) -> Result<usize, String> {
+    if value > max {
+        return Err(format!("too many {}: the limit is {}", what, max));
+    }
+    Ok(value)
+}
+
+fn infix_opcode(operator: &token::TokenKind) -> Option<code::Opcode> {
+    let op = match operator {
+        token::TokenKind::Plus => code::Opcode::Add,
+        token::TokenKind::Minus => code::Opcode::Sub,
+        token::TokenKind::Asterisk => code::Opcode::Mul,
+        token::TokenKind::Slash => code::Opcode::Div,
+        token::TokenKind::Percent => code::Opcode::Mod,
+        token::TokenKind::Power => code::Opcode::Pow,
+        token::TokenKind::Eq => code::Opcode::Equal,
+        token::TokenKind::NotEq => code::Opcode::NotEqual,
+        token::TokenKind::Lt => code::Opcode::Less,
+        token::TokenKind::Gt => code::Opcode::Greater,
+        token::TokenKind::LtEq => code::Opcode::LessEqual,
+        token::TokenKind::GtEq => code::Opcode::GreaterEqual,
+        token::TokenKind::DotDot => code::Opcode::Range,
+        _ => return None,
+    };
+    Some(op)
+}
+
+/// How many values an instruction adds to the stack (or removes, if
+/// negative) when execution continues with the next instruction.
+pub fn stack_effect(op: code::Opcode, operands: &[usize]) -> isize {
+    let operand = |i: usize| operands[i] as isize;
+    match op {
+        code::Opcode::Constant
+        | code::Opcode::Null
+        | code::Opcode::True
+        | code::Opcode::False
+        | code::Opcode::GetGlobal
+        | code::Opcode::GetLocal
+        | code::Opcode::GetFree
+        | code::Opcode::GetBuiltin
+        | code::Opcode::CaptureLocal
+        | code::Opcode::CaptureFree
+        | code::Opcode::LoadPath
+        | code::Opcode::IterStart
+        | code::Opcode::IterNext => 1,
+        code::Opcode::Minus
+        | code::Opcode::Bang
+        | code::Opcode::Jump
+        | code::Opcode::ClearLocal => 0,
+        code::Opcode::Pop
+        | code::Opcode::SetGlobal
+        | code::Opcode::SetLocal
+        | code::Opcode::JumpNotTruthy
+        | code::Opcode::ReturnValue
+        | code::Opcode::Index
+        | code::Opcode::Add
+        | code::Opcode::Sub
+        | code::Opcode::Mul
+        | code::Opcode::Div
+        | code::Opcode::Mod
+        | code::Opcode::Pow
+        | code::Opcode::Equal
+        | code::Opcode::NotEqual
+        | code::Opcode::Less
+        | code::Opcode::Greater
+        | code::Opcode::LessEqual
+        | code::Opcode::GreaterEqual
+        | code::Opcode::Range => -1,
+        code::Opcode::Array => 1 - operand(0),
+        code::Opcode::Hash => 1 - 2 * operand(0),
+        code::Opcode::Slice => -(operands[0].count_ones() as isize),
+        code::Opcode::Closure => 1 - operand(1),
+        code::Opcode::Assign => -operand(2),
+        code::Opcode::Call => -operand(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::*;
+    use crate::evaluator::*;
+    use crate::lexer::*;
+    use crate::object::*;
+    use crate::parser::*;
+
+    use code::Opcode::*;
+
+    fn compile(input: &str) -> compiler::Bytecode {
+        let mut p = parser::Parser::new(lexer::Lexer::new(input.to_string()));
+        let program = p.parse_program();
+        assert!(p.errors().is_empty(), "input: {}, errors: {:?}", input, p.errors());
+        let mut c = compiler::Compiler::new();
+        c.compile(&program).unwrap();
+        c.bytecode()
+    }
+
+    type Instruction<'a> = (code::Opcode, &'a [usize]);
+
+    fn instructions(instructions: &[Instruction]) -> Vec<u8> {
+        instructions.iter().flat_map(|(op, operands)| code::make(*op, operands)).collect()
+    }
+
+    #[test]
+    fn expressions() {
+        let tests: [(&str, &[Instruction]); 6] = [
+            ("1 + 2", &[(Constant, &[0]), (Constant, &[1]), (Add, &[]), (ReturnValue, &[])]),
+            ("1; 2", &[(Constant, &[0]), (Pop, &[]), (Constant, &[1]), (ReturnValue, &[])]),
+            ("-1 < 2.5", &[(Constant, &[0]), (Minus, &[]), (Constant, &[1]), (Less, &[]), (ReturnValue, &[])]),
+            ("[1][0:]", &[(Constant, &[0]), (Array, &[1]), (Constant, &[1]), (Slice, &[1]), (ReturnValue, &[])]),
+            (
+                "true && false",
+                &[
+                    (True, &[]),
+                    (JumpNotTruthy, &[10]),
+                    (False, &[]),
+                    (Bang, &[]),
+                    (Bang, &[]),
+                    (Jump, &[11]),
+                    (False, &[]),
+                    (ReturnValue, &[]),
+                ],
+            ),
+            (
+                "if (true) { 10 }; 3333;",
+                &[
+                    (True, &[]),
+                    (JumpNotTruthy, &[10]),
+                    (Constant, &[0]),
+                    (Jump, &[11]),
+                    (Null, &[]),
+                    (Pop, &[]),
+                    (Constant, &[1]),
+                    (ReturnValue, &[]),
+                ],
+            ),
+        ];
+        for (input, expected) in tests {
+            let bytecode = compile(input);
+            assert_eq!(
+                code::disassemble(&bytecode.main.instructions),
+                code::disassemble(&instructions(expected)),
+                "input: {}",
+                input,
+            );
+        }
+    }
+
+    #[test]
+    fn bindings() {
+        let bytecode = compile("let x = 1; let mut y = x; y += 2; len");
+        assert_eq!(bytecode.globals, ["x", "y"]);
+        let expected = instructions(&[
+            (Constant, &[0]),
+            (SetGlobal, &[0]),
+            (GetGlobal, &[0]),
+            (SetGlobal, &[1]),
+            (LoadPath, &[code::SCOPE_GLOBAL, 1, 0]),
+            (Constant, &[1]),
+            (Add, &[]),
+            (Assign, &[code::SCOPE_GLOBAL, 1, 0]),
+            (Pop, &[]),
+            (GetBuiltin, &[0]),
+            (ReturnValue, &[]),
+        ]);
+        assert_eq!(code::disassemble(&bytecode.main.instructions), code::disassemble(&expected));
+    }
+
+    #[test]
+    fn closures() {
+        let bytecode = compile("fn(a) { fn(b) { a + b } }");
+        let inner = instructions(&[(GetFree, &[0]), (GetLocal, &[0]), (Add, &[]), (ReturnValue, &[])]);
+        let outer = instructions(&[(CaptureLocal, &[0]), (Closure, &[0, 1]), (ReturnValue, &[])]);
+        let functions: Vec<_> = bytecode
+            .constants
+            .iter()
+            .map(|constant| match constant {
+                object::Object::CompiledFunction(function) => function.instructions.clone(),
+                other => panic!("not a function: {:?}", other),
+            })
+            .collect();
+        assert_eq!(functions, [inner, outer]);
+        assert_eq!(bytecode.main.instructions, instructions(&[(Closure, &[1, 0]), (ReturnValue, &[])]));
+    }
+
+    #[test]
+    fn disassemble() {
+        let input = "let greet = fn(name) {
+    \"hi \" + name
+};
+len(greet(\"bob\"))";
+        let expected = "\
+== main ==
+; 1: let greet = fn(name) {
+0000 OpClosure 1 0          ; fn(name) { ... }
+0004 OpSetGlobal 0          ; greet
+; 4: len(greet(\"bob\"))
+0007 OpGetBuiltin 0         ; len
+0009 OpGetGlobal 0          ; greet
+0012 OpConstant 2           ; \"bob\"
+0015 OpCall 1
+0017 OpCall 1
+0019 OpReturnValue
+
+== constant 1: fn(name) { ... } ==
+; 2: \"hi \" + name
+0000 OpConstant 0           ; \"hi \"
+0003 OpGetLocal 0
+0005 OpAdd
+0006 OpReturnValue
+";
+        assert_eq!(compile(input).disassemble(Some(input)), expected);
+        assert!(compile(input).disassemble(None).contains("; line 4\n"));
+    }
+
+    #[test]
+    fn loops() {
+        let bytecode = compile("for x in [1] { if (x) { break; } }");
+        let expected = instructions(&[
+            (Constant, &[0]),
+            (Array, &[1]),
+            (IterStart, &[]),
+            (IterNext, &[33]),
+            (SetLocal, &[0]),
+            (GetLocal, &[0]),
+            (JumpNotTruthy, &[24]),
+            (Jump, &[31]),
+            (Null, &[]),
+            (Jump, &[25]),
+            (Null, &[]),
+            (Pop, &[]),
+            (ClearLocal, &[0]),
+            (Jump, &[7]),
+            (Pop, &[]),
+            (Pop, &[]),
+            (Null, &[]),
+            (ReturnValue, &[]),
+        ]);
+        assert_eq!(code::disassemble(&bytecode.main.instructions), code::disassemble(&expected));
+        assert_eq!(bytecode.main.num_locals, 1);
+    }
+
+    #[test]
+    fn long_chains() {
+        let input = "1".to_string() + &" + 1".repeat(10_000);
+        let mut p = parser::Parser::new(lexer::Lexer::new(input));
+        let program = p.parse_program();
+        assert!(p.errors().is_empty());
+        let limits = limits::Limits {
+            max_stack: Some(256 * 1024),
+            ..limits::Limits::default()
+        };
+        let mut c = compiler::Compiler::new();
+        let (result, _) = limits::with_limits(&limits, || c.compile(&program));
+        assert_eq!(
+            result,
+            Err("expression too long to compile; bind parts of it to names with `let`".to_string())
+        );
+    }
+}
@@ -0,0 +1,512 @@
+use std::fmt;
+use std::rc::Rc;
+
+use crate::compiler::*;
+use crate::evaluator::*;
+use crate::object::*;
+
+/// The first bytes of every `.rkc` file.
+pub const MAGIC: &[u8; 4] = b"RKC\0";
+
+/// The version of the format `encode` writes. `decode` reads only this
+/// version; files from other versions must be compiled again.
+pub const VERSION: u16 = 2;
+
+const TAG_INTEGER: u8 = 0;
+const TAG_FLOAT: u8 = 1;
+const TAG_STRING: u8 = 2;
+const TAG_FUNCTION: u8 = 3;
+
+/// Why bytes could not be read as a compiled program.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+    /// The bytes do not start with `MAGIC`.
+    NotCompiled,
+    UnsupportedVersion(u16),
+    /// The checksum does not match the contents, so the file is damaged.
+    ChecksumMismatch,
+    /// The bytes end in the middle of the program.
+    Truncated,
+    /// The contents are well formed but describe something the virtual
+    /// machine cannot run.
+    Invalid(String),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::NotCompiled => write!(f, "not a compiled Runkey file"),
+            DecodeError::UnsupportedVersion(version) => write!(
+                f,
+                "compiled file has format version {}, but this runkey reads version {}; compile it again",
+                version, VERSION,
+            ),
+            DecodeError::ChecksumMismatch => write!(f, "compiled file is damaged: checksum mismatch"),
+            DecodeError::Truncated => write!(f, "compiled file is truncated"),
+            DecodeError::Invalid(reason) => write!(f, "compiled file is invalid: {}", reason),
+        }
+    }
+}
+
+/// Serializes a program.
+///
+/// The layout is `MAGIC`, the version as a big-endian `u16`, the program,
+/// and a CRC-32 of everything before it. The program is its globals' names,
+/// its constants and its top-level function; a function is its parameter
+/// names, local count, captured variable count, instructions and line
+/// table. Integers are big-endian
+/// `u32`s unless noted, and strings and lists are prefixed with their
+/// length.
+pub fn encode(bytecode: &compiler::Bytecode) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_be_bytes());
+    write_len(&mut out, bytecode.globals.len());
+    for name in &bytecode.globals {
+        write_str(&mut out, name);
+    }
+    write_len(&mut out, bytecode.constants.len());
+    for constant in &bytecode.constants {
+        match constant {
+            object::Object::Integer(value) => {
+                out.push(TAG_INTEGER);
+                out.extend_from_slice(&value.to_be_bytes());
+            }
+            object::Object::Float(value) => {
+                out.push(TAG_FLOAT);
+                out.extend_from_slice(&value.to_bits().to_be_bytes());
+            }
+            object::Object::String(value) => {
+                out.push(TAG_STRING);
+                write_str(&mut out, value);
+            }
+            object::Object::CompiledFunction(function) => {
+                out.push(TAG_FUNCTION);
+                write_function(&mut out, function);
+            }
+            other => unreachable!("the compiler makes no {} constants", other.type_name()),
+        }
+    }
+    write_function(&mut out, &bytecode.main);
+    let checksum = crc32(&out);
+    out.extend_from_slice(&checksum.to_be_bytes());
+    out
+}
+
+/// Reads a program written by `encode`, checking that its instructions
+/// refer only to constants, globals, builtins, locals and captured
+/// variables that exist, and that they keep the stack balanced.
+pub fn decode(bytes: &[u8]) -> Result<compiler::Bytecode, DecodeError> {
+    if !bytes.starts_with(MAGIC) {
+        return Err(DecodeError::NotCompiled);
+    }
+    let mut r = Reader { bytes, pos: MAGIC.len() };
+    let version = u16::from_be_bytes([r.byte()?, r.byte()?]);
+    if version != VERSION {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+    let body_len = bytes.len().checked_sub(4).ok_or(DecodeError::Truncated)?;
+    if body_len < r.pos {
+        return Err(DecodeError::Truncated);
+    }
+    let checksum = u32::from_be_bytes(bytes[body_len..].try_into().expect("four bytes"));
+    if crc32(&bytes[..body_len]) != checksum {
+        return Err(DecodeError::ChecksumMismatch);
+    }
+    r.bytes = &bytes[..body_len];
+
+    let globals = (0..r.len()?).map(|_| r.string()).collect::<Result<Vec<_>, _>>()?;
+    let mut constants = Vec::new();
+    for _ in 0..r.len()? {
+        let constant = match r.byte()? {
+            TAG_INTEGER => object::Object::Integer(i64::from_be_bytes(r.array()?)),
+            TAG_FLOAT => object::Object::Float(f64::from_bits(u64::from_be_bytes(r.array()?))),
+            TAG_STRING => object::Object::String(r.string()?),
+            TAG_FUNCTION => object::Object::CompiledFunction(Rc::new(r.function()?)),
+            tag => return Err(DecodeError::Invalid(format!("unknown constant tag {}", tag))),
+        };
+        constants.push(constant);
+    }
+    let main = r.function()?;
+    if r.pos != r.bytes.len() {
+        return Err(DecodeError::Invalid("trailing bytes after the program".to_string()));
+    }
+
+    if main.num_free != 0 {
+        return Err(DecodeError::Invalid("the top-level function captures variables".to_string()));
+    }
+    let bytecode = compiler::Bytecode {
+        main: Rc::new(main),
+        constants,
+        globals,
+    };
+    verify(&bytecode, &bytecode.main)?;
+    for constant in &bytecode.constants {
+        if let object::Object::CompiledFunction(function) = constant {
+            verify(&bytecode, function)?;
+        }
+    }
+    Ok(bytecode)
+}
+
+fn write_len(out: &mut Vec<u8>, len: usize) {
+    let len = u32::try_from(len).expect("length fits in 32 bits");
+    out.extend_from_slice(&len.to_be_bytes());
+}
+
+fn write_str(out: &mut Vec<u8>, s: &str) {
+    write_len(out, s.len());
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn write_function(out: &mut Vec<u8>, function: &object::CompiledFunction) {
+    write_len(out, function.parameters.len());
+    for parameter in &function.parameters {
+        write_str(out, parameter);
+    }
+    write_len(out, function.num_locals);
+    write_len(out, function.num_free);
+    write_len(out, function.instructions.len());
+    out.extend_from_slice(&function.instructions);
+    write_len(out, function.lines.len());
+    for &(offset, line) in &function.lines {
+        write_len(out, offset);
+        write_len(out, line);
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8], DecodeError> {
+        let end = self.pos.checked_add(n).filter(|&end| end <= self.bytes.len());
+        let end = end.ok_or(DecodeError::Truncated)?;
+        let taken = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        Ok(self.take(N)?.try_into().expect("N bytes"))
+    }
+
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn len(&mut self) -> Result<usize, DecodeError> {
+        Ok(u32::from_be_bytes(self.array()?) as usize)
+    }
+
+    fn string(&mut self) -> Result<String, DecodeError> {
+        let len = self.len()?;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::Invalid("string is not UTF-8".to_string()))
+    }
+
+    fn function(&mut self) -> Result<object::CompiledFunction, DecodeError> {
+        let parameters = (0..self.len()?).map(|_| self.string()).collect::<Result<Vec<_>, _>>()?;
+        let num_locals = self.len()?;
+        let num_free = self.len()?;
+        let len = self.len()?;
+        let instructions = self.take(len)?.to_vec();
+        let mut lines = Vec::new();
+        for _ in 0..self.len()? {
+            lines.push((self.len()?, self.len()?));
+        }
+        Ok(object::CompiledFunction {
+            instructions,
+            num_locals,
+            num_free,
+            parameters,
+            lines,
+        })
+    }
+}
+
+/// Checks that every instruction of `function` is complete, that the
+/// constants, globals, builtins, locals, captured variables and jump targets
+/// it names exist, and that it never takes more off the stack than it has
+/// put there.
+fn verify(bytecode: &compiler::Bytecode, function: &object::CompiledFunction) -> Result<(), DecodeError> {
+    let invalid = |offset: usize, reason: String| Err(DecodeError::Invalid(format!("at {:04}: {}", offset, reason)));
+    let instructions = &function.instructions;
+    if function.parameters.len() > function.num_locals || function.num_locals > u8::MAX as usize + 1 {
+        return Err(DecodeError::Invalid("function has too few or too many locals".to_string()));
+    }
+    // Whether an instruction starts at each offset, for checking jumps.
+    let mut starts = vec![false; instructions.len()];
+    let mut ip = 0;
+    while ip < instructions.len() {
+        starts[ip] = true;
+        let op = match code::Opcode::from_byte(instructions[ip]) {
+            Some(op) => op,
+            None => return invalid(ip, format!("unknown opcode {}", instructions[ip])),
+        };
+        let definition = op.definition();
+        let width: usize = definition.operand_widths.iter().sum();
+        if ip + 1 + width > instructions.len() {
+            return invalid(ip, format!("{} is cut short", definition.name));
+        }
+        let (operands, _) = code::read_operands(&definition, &instructions[ip + 1..]);
+        let in_range = match op {
+            code::Opcode::Constant => operands[0] < bytecode.constants.len(),
+            code::Opcode::Closure => matches!(
+                bytecode.constants.get(operands[0]),
+                Some(object::Object::CompiledFunction(f)) if f.num_free == operands[1],
+            ),
+            code::Opcode::GetGlobal | code::Opcode::SetGlobal => operands[0] < bytecode.globals.len(),
+            code::Opcode::GetBuiltin => operands[0] < builtins::BUILTINS.len(),
+            code::Opcode::GetLocal
+            | code::Opcode::SetLocal
+            | code::Opcode::ClearLocal
+            | code::Opcode::CaptureLocal => operands[0] < function.num_locals,
+            code::Opcode::GetFree | code::Opcode::CaptureFree => operands[0] < function.num_free,
+            code::Opcode::Jump | code::Opcode::JumpNotTruthy | code::Opcode::IterNext => {
+                operands[0] < instructions.len()
+            }
+            code::Opcode::LoadPath | code::Opcode::Assign => match operands[0] {
+                code::SCOPE_GLOBAL => operands[1] < bytecode.globals.len(),
+                code::SCOPE_LOCAL => operands[1] < function.num_locals,
+                code::SCOPE_FREE => operands[1] < function.num_free,
+                _ => false,
+            },
+            _ => true,
+        };
+        if !in_range {
+            return invalid(ip, format!("{} operand out of range", definition.name));
+        }
+        ip += 1 + width;
+    }
+    if instructions.last() != Some(&(code::Opcode::ReturnValue as u8)) {
+        return Err(DecodeError::Invalid("function does not end with a return".to_string()));
+    }
+    verify_stack(instructions, &starts)
+}
+
+/// Follows every path through `instructions`, whose operands have been
+/// checked, tracking how many values each leaves on the stack. Each
+/// instruction must find the values it takes, and paths that meet must
+/// agree on the height.
+fn verify_stack(instructions: &[u8], starts: &[bool]) -> Result<(), DecodeError> {
+    let invalid = |offset: usize, reason: &str| Err(DecodeError::Invalid(format!("at {:04}: {}", offset, reason)));
+    // The height of the stack before each instruction reached so far.
+    let mut heights: Vec<Option<usize>> = vec![None; instructions.len()];
+    let mut pending = vec![(0, 0)];
+    while let Some((ip, height)) = pending.pop() {
+        if !starts.get(ip).copied().unwrap_or(false) {
+            return invalid(ip, "jump into the middle of an instruction");
+        }
+        match heights[ip] {
+            Some(seen) if seen == height => continue,
+            Some(_) => return invalid(ip, "stack height differs between the paths that reach it"),
+            None => heights[ip] = Some(height),
+        }
+        let (op, operands, len) = code::decode_instruction(instructions, ip).expect("checked by verify");
+        if height < needs(op, &operands) {
+            return invalid(ip, &format!("{} takes more values than the stack holds", op.definition().name));
+        }
+        let after = (height as isize + compiler::stack_effect(op, &operands)) as usize;
+        match op {
+            code::Opcode::ReturnValue => {}
+            code::Opcode::Jump => pending.push((operands[0], height)),
+            code::Opcode::JumpNotTruthy => pending.extend([(operands[0], after), (ip + len, after)]),
+            // When the iteration ends, its collection and counter are dropped.
+            code::Opcode::IterNext => pending.extend([(operands[0], height - 2), (ip + len, after)]),
+            _ => pending.push((ip + len, after)),
+        }
+    }
+    Ok(())
+}
+
+/// How many values an instruction needs on the stack to run.
+fn needs(op: code::Opcode, operands: &[usize]) -> usize {
+    match op {
+        code::Opcode::Constant
+        | code::Opcode::Null
+        | code::Opcode::True
+        | code::Opcode::False
+        | code::Opcode::Jump
+        | code::Opcode::GetGlobal
+        | code::Opcode::GetLocal
+        | code::Opcode::ClearLocal
+        | code::Opcode::GetFree
+        | code::Opcode::GetBuiltin
+        | code::Opcode::CaptureLocal
+        | code::Opcode::CaptureFree => 0,
+        code::Opcode::Pop
+        | code::Opcode::Minus
+        | code::Opcode::Bang
+        | code::Opcode::JumpNotTruthy
+        | code::Opcode::SetGlobal
+        | code::Opcode::SetLocal
+        | code::Opcode::IterStart
+        | code::Opcode::ReturnValue => 1,
+        code::Opcode::Add
+        | code::Opcode::Sub
+        | code::Opcode::Mul
+        | code::Opcode::Div
+        | code::Opcode::Mod
+        | code::Opcode::Pow
+        | code::Opcode::Equal
+        | code::Opcode::NotEqual
+        | code::Opcode::Less
+        | code::Opcode::Greater
+        | code::Opcode::LessEqual
+        | code::Opcode::GreaterEqual
+        | code::Opcode::Range
+        | code::Opcode::Index
+        | code::Opcode::IterNext => 2,
+        code::Opcode::Closure => operands[1],
+        code::Opcode::Array => operands[0],
+        code::Opcode::Hash => 2 * operands[0],
+        code::Opcode::Slice => 1 + operands[0].count_ones() as usize,
+        // The keys stay on the stack for the `OpAssign` that follows.
+        code::Opcode::LoadPath => operands[2],
+        code::Opcode::Assign => 1 + operands[2],
+        code::Opcode::Call => 1 + operands[0],
+    }
+}
+
+/// The CRC-32 (IEEE) of `bytes`.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::*;
+    use crate::lexer::*;
+    use crate::parser::*;
+    use crate::vm::*;
+
+    fn compile(input: &str) -> compiler::Bytecode {
+        let mut p = parser::Parser::new(lexer::Lexer::new(input.to_string()));
+        let program = p.parse_program();
+        assert!(p.errors().is_empty(), "input: {}, errors: {:?}", input, p.errors());
+        let mut c = compiler::Compiler::new();
+        c.compile(&program).unwrap();
+        c.bytecode()
+    }
+
+    #[test]
+    fn round_trip() {
+        let input = "let name = \"runkey\";
+let scale = fn(x) {
+    x * 2.5
+};
+var total = 0;
+for i in 1..4 { total += scale(i); }
+[name, total]";
+        let bytecode = compile(input);
+        let bytes = rkc::encode(&bytecode);
+        assert!(bytes.starts_with(rkc::MAGIC));
+        let decoded = rkc::decode(&bytes).unwrap();
+        assert_eq!(decoded, bytecode);
+        assert_eq!(vm::VM::new(decoded).run().to_string(), "[\"runkey\", 15.0]");
+    }
+
+    #[test]
+    fn compiled_programs_verify() {
+        let tests = [
+            "let f = fn(x) { if (x > 1) { return x; } x + 1 }; f(2)",
+            "var s = 0; for x in [1, 2, 3] { if (x == 2) { continue; } s += [10, if (x == 3) { break; } else { x }][1]; } s",
+            "outer: for i in 0..3 { for j in {1: 2} { if (j == i) { break outer; } } }",
+            "var i = 0; while (i < 3) { i += 1; let k = i; } i",
+            "var h = {\"a\": [1, 2]}; h[\"a\"][0] = 5; h[\"a\"][0] += 1; h",
+            "let counter = fn() { var n = 0; fn() { n += 1; fn() { n } } }; counter()()()",
+            "let a = [1, 2, 3]; [a[1:], a[:2], a[:], a[0:1]]",
+            "puts(len(\"abc\"), -1, !true)",
+        ];
+        for input in tests {
+            let bytecode = compile(input);
+            assert_eq!(rkc::decode(&rkc::encode(&bytecode)), Ok(bytecode), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn crc32() {
+        assert_eq!(rkc::crc32(b""), 0);
+        assert_eq!(rkc::crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn decode_errors() {
+        let bytes = rkc::encode(&compile("let f = fn(a) { a + 1 }; f(1)"));
+        let mut damaged = bytes.clone();
+        damaged[10] ^= 1;
+        let mut future = bytes.clone();
+        future[5] = 9;
+
+        let tests = [
+            (b"let x = 1;".to_vec(), rkc::DecodeError::NotCompiled),
+            (future, rkc::DecodeError::UnsupportedVersion(9)),
+            (damaged, rkc::DecodeError::ChecksumMismatch),
+            (bytes[..5].to_vec(), rkc::DecodeError::Truncated),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(rkc::decode(&input), Err(expected));
+        }
+    }
+
+    fn assemble(instructions: &[(code::Opcode, &[usize])]) -> Vec<u8> {
+        instructions.iter().flat_map(|(op, operands)| code::make(*op, operands)).collect()
+    }
+
+    #[test]
+    fn invalid_instructions() {
+        use code::Opcode::*;
+
+        let tests = [
+            (assemble(&[(Constant, &[7]), (ReturnValue, &[])]), "at 0000: OpConstant operand out of range"),
+            (assemble(&[(Pop, &[]), (ReturnValue, &[])]), "at 0000: OpPop takes more values than the stack holds"),
+            (assemble(&[(GetFree, &[0]), (ReturnValue, &[])]), "at 0000: OpGetFree operand out of range"),
+            (
+                assemble(&[(Null, &[]), (Closure, &[0, 1]), (ReturnValue, &[])]),
+                "at 0001: OpClosure operand out of range",
+            ),
+            (
+                assemble(&[(Null, &[]), (Jump, &[2]), (ReturnValue, &[])]),
+                "at 0002: jump into the middle of an instruction",
+            ),
+            (
+                assemble(&[(True, &[]), (JumpNotTruthy, &[6]), (Null, &[]), (Null, &[]), (ReturnValue, &[])]),
+                "at 0006: stack height differs between the paths that reach it",
+            ),
+            (
+                assemble(&[(Null, &[]), (Null, &[]), (Add, &[]), (Add, &[]), (ReturnValue, &[])]),
+                "at 0003: OpAdd takes more values than the stack holds",
+            ),
+        ];
+        for (instructions, expected) in tests {
+            // The constant is a function that captures nothing.
+            let mut bytecode = compile("fn() { 1 }");
+            std::rc::Rc::make_mut(&mut bytecode.main).instructions = instructions;
+            assert_eq!(
+                rkc::decode(&rkc::encode(&bytecode)),
+                Err(rkc::DecodeError::Invalid(expected.to_string())),
+                "expected: {}",
+                expected,
+            );
+        }
+
+        // The stack holds the right number of values but not the right
+        // kinds, which the virtual machine reports rather than panicking on.
+        let mut bytecode = compile("1");
+        std::rc::Rc::make_mut(&mut bytecode.main).instructions =
+            assemble(&[(Null, &[]), (Null, &[]), (IterNext, &[0]), (ReturnValue, &[])]);
+        let decoded = rkc::decode(&rkc::encode(&bytecode)).unwrap();
+        assert_eq!(vm::VM::new(decoded).run().to_string(), "ERROR: OpIterNext without OpIterStart");
+    }
+}
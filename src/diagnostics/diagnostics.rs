@@ -0,0 +1,393 @@
+use std::fmt::Write as _;
+use std::io::{self, IsTerminal};
+
+use crate::parser::*;
+use crate::token::*;
+
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn name(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+
+    fn colour(self) -> &'static str {
+        match self {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+        }
+    }
+}
+
+/// A span of source annotated with a message. Primary labels mark the cause
+/// of the diagnostic (`^^^`), secondary ones give context (`---`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: token::Span,
+    pub message: String,
+    pub primary: bool,
+}
+
+/// A message about a source file, ready to be rendered for a user.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            severity,
+            message: message.into(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+            help: None,
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Diagnostic {
+        Diagnostic::new(Severity::Error, message)
+    }
+
+    pub fn warning(message: impl Into<String>) -> Diagnostic {
+        Diagnostic::new(Severity::Warning, message)
+    }
+
+    pub fn with_label(mut self, span: token::Span, message: impl Into<String>) -> Diagnostic {
+        self.labels.push(Label { span, message: message.into(), primary: true });
+        self
+    }
+
+    pub fn with_secondary_label(mut self, span: token::Span, message: impl Into<String>) -> Diagnostic {
+        self.labels.push(Label { span, message: message.into(), primary: false });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Diagnostic {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Diagnostic {
+        self.help = Some(help.into());
+        self
+    }
+}
+
+impl From<&error::ParseError> for Diagnostic {
+    fn from(err: &error::ParseError) -> Diagnostic {
+        let diagnostic = Diagnostic::error(err.to_string());
+        match err {
+            error::ParseError::UnexpectedToken { expected, span, .. } => {
+                diagnostic.with_label(*span, format!("expected {} here", expected))
+            }
+            error::ParseError::NoPrefixParseFn { span, .. } => {
+                diagnostic.with_label(*span, "this cannot start an expression")
+            }
+            error::ParseError::UnterminatedBlock { span } => diagnostic
+                .with_label(*span, "this block is never closed")
+                .with_help("add a `}` to close the block"),
+            error::ParseError::InvalidInteger { span, .. } => diagnostic
+                .with_label(*span, "integer literal out of range")
+                .with_note("integers are 64-bit signed values"),
+            error::ParseError::InvalidFloat { span, .. } => diagnostic
+                .with_label(*span, "float literal out of range")
+                .with_note("floats are 64-bit IEEE 754 values"),
+            error::ParseError::InvalidAssignmentTarget { span } => {
+                diagnostic.with_label(*span, "cannot assign to this expression")
+            }
+            error::ParseError::ImmutableAssignment { name, span, declared } => {
+                let diagnostic = diagnostic.with_label(*span, "cannot assign to an immutable binding");
+                let diagnostic = match declared {
+                    Some(declared) => diagnostic.with_secondary_label(*declared, format!("`{}` is bound here", name)),
+                    None => diagnostic,
+                };
+                diagnostic.with_help(format!("declare it with `let mut {}` or `var {}` to allow assignment", name, name))
+            }
+            error::ParseError::UndeclaredAssignment { name, span } => diagnostic
+                .with_label(*span, "not bound in this scope")
+                .with_help(format!("bind it first with `let mut {} = ...`", name)),
+            error::ParseError::OutsideLoop { keyword, span } => {
+                diagnostic.with_label(*span, format!("cannot `{}` outside of a loop", keyword))
+            }
+            error::ParseError::UndeclaredLabel { span, .. } => diagnostic
+                .with_label(*span, "undeclared label")
+                .with_help("label a loop with `name: while` or `name: for`"),
+            error::ParseError::NotTopLevel { keyword, span } => diagnostic
+                .with_label(*span, format!("`{}` inside a block", keyword))
+                .with_help(format!("move the `{}` to the top level of the file", keyword)),
+            error::ParseError::TooDeeplyNested { span } => diagnostic
+                .with_label(*span, "nested too deeply")
+                .with_help("bind the inner parts to names with `let`"),
+            error::ParseError::InvalidToken { error, span } => diagnostic.with_label(*span, match error {
+                token::LexError::UnexpectedCharacter(_) => "unrecognised character",
+                token::LexError::UnterminatedString => "this string is never closed",
+                token::LexError::UnterminatedComment => "this comment is never closed",
+                token::LexError::InvalidEscape(_) => "in this string literal",
+                token::LexError::InvalidNumber(_) => "not a valid number",
+            }),
+        }
+    }
+}
+
+/// Renders diagnostics against the source they refer to.
+#[derive(Debug, Clone, Copy)]
+pub struct Renderer {
+    pub colour: bool,
+}
+
+impl Renderer {
+    pub fn new(colour: bool) -> Renderer {
+        Renderer { colour }
+    }
+
+    /// Uses colour only when `stream` is a terminal and `NO_COLOR` is unset.
+    pub fn for_stream(stream: &impl IsTerminal) -> Renderer {
+        Renderer::new(stream.is_terminal() && std::env::var_os("NO_COLOR").is_none())
+    }
+
+    pub fn render(&self, diagnostic: &Diagnostic, file_name: &str, source: &str) -> String {
+        let mut out = String::new();
+        let severity = diagnostic.severity;
+        let _ = writeln!(
+            out,
+            "{}{}{}: {}{}",
+            self.paint(severity.colour()),
+            severity.name(),
+            self.paint(BOLD),
+            diagnostic.message,
+            self.paint(RESET),
+        );
+
+        let mut labels: Vec<&Label> = diagnostic.labels.iter().collect();
+        labels.sort_by_key(|label| (label.span.line, label.span.column));
+        let gutter = labels.iter().map(|label| label.span.line.to_string().len()).max().unwrap_or(1);
+        let pad = " ".repeat(gutter);
+        let bar = format!("{}|{}", self.paint(BLUE), self.paint(RESET));
+
+        match diagnostic.labels.iter().find(|label| label.primary).or(diagnostic.labels.first()) {
+            Some(label) => {
+                let _ = writeln!(out, "{}{}-->{} {}:{}", pad, self.paint(BLUE), self.paint(RESET), file_name, label.span);
+            }
+            None => {
+                let _ = writeln!(out, "{}{}-->{} {}", pad, self.paint(BLUE), self.paint(RESET), file_name);
+            }
+        }
+
+        if !labels.is_empty() {
+            let _ = writeln!(out, "{} {}", pad, bar);
+        }
+        let mut previous_line = None;
+        for label in &labels {
+            let (line, prefix, marked) = line_of(source, label.span);
+            if previous_line != Some(label.span.line) {
+                let _ = writeln!(
+                    out,
+                    "{}{:>width$} |{} {}",
+                    self.paint(BLUE),
+                    label.span.line,
+                    self.paint(RESET),
+                    line,
+                    width = gutter,
+                );
+                previous_line = Some(label.span.line);
+            }
+            let indent: String = prefix.chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+            let (marker, colour) = if label.primary {
+                ("^", severity.colour())
+            } else {
+                ("-", BLUE)
+            };
+            let underline = marker.repeat(marked.chars().count().max(1));
+            let message = if label.message.is_empty() {
+                String::new()
+            } else {
+                format!(" {}", label.message)
+            };
+            let _ = writeln!(
+                out,
+                "{} {} {}{}{}{}{}",
+                pad,
+                bar,
+                indent,
+                self.paint(colour),
+                underline,
+                message,
+                self.paint(RESET),
+            );
+        }
+
+        if !diagnostic.notes.is_empty() || diagnostic.help.is_some() {
+            if !labels.is_empty() {
+                let _ = writeln!(out, "{} {}", pad, bar);
+            }
+            for note in &diagnostic.notes {
+                self.footer(&mut out, &pad, "note", note);
+            }
+            if let Some(help) = &diagnostic.help {
+                self.footer(&mut out, &pad, "help", help);
+            }
+        }
+        out
+    }
+
+    /// Writes each diagnostic to `out`, separated by blank lines.
+    pub fn emit(
+        &self,
+        out: &mut impl io::Write,
+        diagnostics: &[Diagnostic],
+        file_name: &str,
+        source: &str,
+    ) -> io::Result<()> {
+        for (i, diagnostic) in diagnostics.iter().enumerate() {
+            if i > 0 {
+                writeln!(out)?;
+            }
+            write!(out, "{}", self.render(diagnostic, file_name, source))?;
+        }
+        Ok(())
+    }
+
+    fn footer(&self, out: &mut String, pad: &str, kind: &str, text: &str) {
+        let _ = writeln!(
+            out,
+            "{} {}={} {}{}{}: {}",
+            pad,
+            self.paint(BLUE),
+            self.paint(RESET),
+            self.paint(BOLD),
+            kind,
+            self.paint(RESET),
+            text,
+        );
+    }
+
+    fn paint(&self, code: &'static str) -> &'static str {
+        if self.colour {
+            code
+        } else {
+            ""
+        }
+    }
+}
+
+/// Returns the source line containing the start of `span`, the part of it
+/// before the span, and the part of it the span covers.
+fn line_of(source: &str, span: token::Span) -> (&str, &str, &str) {
+    let start = span.start.min(source.len());
+    let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source[start..].find('\n').map_or(source.len(), |i| start + i);
+    let line = source[line_start..line_end].trim_end_matches('\r');
+    let end = span.end.min(line_start + line.len()).max(start);
+    (line, &source[line_start..start], &source[start..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diagnostics::*;
+    use crate::lexer::*;
+    use crate::parser::*;
+    use crate::token::*;
+
+    fn render_parse_errors(source: &str) -> String {
+        let mut p = parser::Parser::new(lexer::Lexer::new(source.to_string()));
+        p.parse_program();
+        let diagnostics: Vec<diagnostics::Diagnostic> = p.errors().iter().map(Into::into).collect();
+        let mut out = Vec::new();
+        diagnostics::Renderer::new(false).emit(&mut out, &diagnostics, "test.rk", source).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn unexpected_token() {
+        assert_eq!(render_parse_errors("let x = 1;\nlet y 5;\n"), "\
+error: expected next token to be =, got INT instead
+ --> test.rk:2:7
+  |
+2 | let y 5;
+  |       ^ expected = here
+");
+    }
+
+    #[test]
+    fn unterminated_block_with_help() {
+        assert_eq!(render_parse_errors("let f = fn(x) {\n  x"), "\
+error: unterminated block: expected }
+ --> test.rk:1:15
+  |
+1 | let f = fn(x) {
+  |               ^ this block is never closed
+  |
+  = help: add a `}` to close the block
+");
+    }
+
+    #[test]
+    fn multiple_errors_and_wide_underline() {
+        assert_eq!(render_parse_errors("let a = 99999999999999999999;\nlet = 1;"), "\
+error: could not parse 99999999999999999999 as integer
+ --> test.rk:1:9
+  |
+1 | let a = 99999999999999999999;
+  |         ^^^^^^^^^^^^^^^^^^^^ integer literal out of range
+  |
+  = note: integers are 64-bit signed values
+
+error: expected next token to be IDENT, got = instead
+ --> test.rk:2:5
+  |
+2 | let = 1;
+  |     ^ expected IDENT here
+");
+    }
+
+    #[test]
+    fn tabs_and_secondary_labels() {
+        let source = "\tlet x = y;";
+        let diagnostic = diagnostics::Diagnostic::error("identifier not found: y")
+            .with_secondary_label(token::Span { start: 5, end: 6, line: 1, column: 6 }, "bound here")
+            .with_label(token::Span { start: 9, end: 10, line: 1, column: 10 }, "not found");
+        assert_eq!(diagnostics::Renderer::new(false).render(&diagnostic, "t.rk", source), "\
+error: identifier not found: y
+ --> t.rk:1:10
+  |
+1 | \tlet x = y;
+  | \t    - bound here
+  | \t        ^ not found
+");
+    }
+
+    #[test]
+    fn without_labels() {
+        let diagnostic = diagnostics::Diagnostic::error("division by zero").with_note("at runtime");
+        assert_eq!(diagnostics::Renderer::new(false).render(&diagnostic, "t.rk", ""), "\
+error: division by zero
+ --> t.rk
+  = note: at runtime
+");
+    }
+
+    #[test]
+    fn colour() {
+        let diagnostic = diagnostics::Diagnostic::warning("unused");
+        let rendered = diagnostics::Renderer::new(true).render(&diagnostic, "t.rk", "");
+        assert!(rendered.starts_with("\x1b[1;33mwarning\x1b[1m: unused\x1b[0m\n"));
+    }
+}
@@ -0,0 +1,303 @@
+use std::fmt;
+use std::rc::Rc;
+
+use crate::evaluator::*;
+use crate::object::*;
+
+/// A value that does not have the type the host asked for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConversionError {
+    pub expected: &'static str,
+    pub found: &'static str,
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "expected {}, got {}", self.expected, self.found)
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+/// A Rust type that can be made from a Runkey value.
+pub trait FromObject: Sized {
+    fn from_object(value: object::Object) -> Result<Self, ConversionError>;
+}
+
+/// A Rust type that can be turned into a Runkey value.
+pub trait IntoObject {
+    fn into_object(self) -> object::Object;
+}
+
+fn mismatch<T>(expected: &'static str, value: &object::Object) -> Result<T, ConversionError> {
+    Err(ConversionError {
+        expected,
+        found: value.type_name(),
+    })
+}
+
+impl FromObject for object::Object {
+    fn from_object(value: object::Object) -> Result<Self, ConversionError> {
+        Ok(value)
+    }
+}
+
+impl FromObject for i64 {
+    fn from_object(value: object::Object) -> Result<Self, ConversionError> {
+        match value {
+            object::Object::Integer(value) => Ok(value),
+            other => mismatch("INTEGER", &other),
+        }
+    }
+}
+
+impl FromObject for f64 {
+    /// Integers are accepted too, as mixed arithmetic promotes them.
+    fn from_object(value: object::Object) -> Result<Self, ConversionError> {
+        match value {
+            object::Object::Float(value) => Ok(value),
+            object::Object::Integer(value) => Ok(value as f64),
+            other => mismatch("FLOAT", &other),
+        }
+    }
+}
+
+impl FromObject for bool {
+    fn from_object(value: object::Object) -> Result<Self, ConversionError> {
+        match value {
+            object::Object::Boolean(value) => Ok(value),
+            other => mismatch("BOOLEAN", &other),
+        }
+    }
+}
+
+impl FromObject for String {
+    fn from_object(value: object::Object) -> Result<Self, ConversionError> {
+        match value {
+            object::Object::String(value) => Ok(value),
+            other => mismatch("STRING", &other),
+        }
+    }
+}
+
+impl<T: FromObject> FromObject for Vec<T> {
+    fn from_object(value: object::Object) -> Result<Self, ConversionError> {
+        match value {
+            object::Object::Array(elements) => {
+                let elements = Rc::try_unwrap(elements).unwrap_or_else(|shared| shared.as_ref().clone());
+                elements.into_iter().map(T::from_object).collect()
+            }
+            other => mismatch("ARRAY", &other),
+        }
+    }
+}
+
+/// `null` becomes `None`.
+impl<T: FromObject> FromObject for Option<T> {
+    fn from_object(value: object::Object) -> Result<Self, ConversionError> {
+        match value {
+            object::Object::Null => Ok(None),
+            value => T::from_object(value).map(Some),
+        }
+    }
+}
+
+impl IntoObject for object::Object {
+    fn into_object(self) -> object::Object {
+        self
+    }
+}
+
+impl IntoObject for () {
+    fn into_object(self) -> object::Object {
+        object::Object::Null
+    }
+}
+
+impl IntoObject for i64 {
+    fn into_object(self) -> object::Object {
+        object::Object::Integer(self)
+    }
+}
+
+impl IntoObject for f64 {
+    fn into_object(self) -> object::Object {
+        object::Object::Float(self)
+    }
+}
+
+impl IntoObject for bool {
+    fn into_object(self) -> object::Object {
+        object::Object::Boolean(self)
+    }
+}
+
+impl IntoObject for String {
+    fn into_object(self) -> object::Object {
+        object::Object::String(self)
+    }
+}
+
+impl IntoObject for &str {
+    fn into_object(self) -> object::Object {
+        object::Object::String(self.to_string())
+    }
+}
+
+impl<T: IntoObject> IntoObject for Vec<T> {
+    fn into_object(self) -> object::Object {
+        object::Object::Array(Rc::new(self.into_iter().map(IntoObject::into_object).collect()))
+    }
+}
+
+/// `None` becomes `null`.
+impl<T: IntoObject> IntoObject for Option<T> {
+    fn into_object(self) -> object::Object {
+        match self {
+            Some(value) => value.into_object(),
+            None => object::Object::Null,
+        }
+    }
+}
+
+/// An `Err` becomes a runtime error with the error's message, so a native
+/// function can fail the script that called it.
+impl<T: IntoObject, E: fmt::Display> IntoObject for Result<T, E> {
+    fn into_object(self) -> object::Object {
+        match self {
+            Ok(value) => value.into_object(),
+            Err(err) => object::Object::Error(err.to_string()),
+        }
+    }
+}
+
+/// A function value passed from a script to the host, such as a callback.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Callable(object::Object);
+
+impl Callable {
+    /// Calls the function, returning its result or the message of the error
+    /// it failed with.
+    pub fn call(&self, args: Vec<object::Object>) -> Result<object::Object, String> {
+        match evaluator::apply_function(self.0.clone(), args) {
+            object::Object::Error(message) => Err(message),
+            value => Ok(value),
+        }
+    }
+}
+
+impl FromObject for Callable {
+    fn from_object(value: object::Object) -> Result<Self, ConversionError> {
+        match value {
+            object::Object::Function(_) | object::Object::Builtin(_) | object::Object::Native(_) => Ok(Callable(value)),
+            other => mismatch("FUNCTION", &other),
+        }
+    }
+}
+
+impl IntoObject for Callable {
+    fn into_object(self) -> object::Object {
+        self.0
+    }
+}
+
+/// A Rust closure that can be registered as a Runkey function, taking
+/// arguments of the types `Args`. It is implemented for closures of up to
+/// six arguments that are all `FromObject`, returning an `IntoObject`.
+pub trait IntoNative<Args> {
+    fn into_native(self, name: &str) -> object::Native;
+}
+
+macro_rules! impl_into_native {
+    ($($arg:ident),*) => {
+        impl<F, R, $($arg),*> IntoNative<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + 'static,
+            R: IntoObject,
+            $($arg: FromObject,)*
+        {
+            #[allow(non_snake_case, unused_mut, unused_variables)]
+            fn into_native(self, name: &str) -> object::Native {
+                let function_name = name.to_string();
+                let func = move |args: Vec<object::Object>| {
+                    let want = <[&str]>::len(&[$(stringify!($arg)),*]);
+                    if args.len() != want {
+                        return object::Object::Error(format!(
+                            "wrong number of arguments: want={}, got={}",
+                            want,
+                            args.len(),
+                        ));
+                    }
+                    let mut args = args.into_iter().enumerate();
+                    $(
+                        let (i, arg) = args.next().expect("arity checked");
+                        let $arg = match $arg::from_object(arg) {
+                            Ok(arg) => arg,
+                            Err(err) => {
+                                return object::Object::Error(format!(
+                                    "argument {} to `{}`: {}",
+                                    i + 1,
+                                    function_name,
+                                    err,
+                                ));
+                            }
+                        };
+                    )*
+                    self($($arg),*).into_object()
+                };
+                object::Native {
+                    name: name.to_string(),
+                    func: Box::new(func),
+                }
+            }
+        }
+    };
+}
+
+impl_into_native!();
+impl_into_native!(A);
+impl_into_native!(A, B);
+impl_into_native!(A, B, C);
+impl_into_native!(A, B, C, D);
+impl_into_native!(A, B, C, D, E);
+impl_into_native!(A, B, C, D, E, G);
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::convert::{FromObject, IntoNative, IntoObject};
+    use crate::engine::*;
+    use crate::object::*;
+
+    #[test]
+    fn from_object() {
+        assert_eq!(i64::from_object(object::Object::Integer(3)), Ok(3));
+        assert_eq!(f64::from_object(object::Object::Integer(3)), Ok(3.0));
+        assert_eq!(
+            <Vec<Option<String>>>::from_object(vec![Some("a"), None].into_object()),
+            Ok(vec![Some("a".to_string()), None]),
+        );
+        assert_eq!(
+            <Vec<i64>>::from_object(vec![object::Object::Integer(1), object::Object::Boolean(true)].into_object()),
+            Err(convert::ConversionError { expected: "INTEGER", found: "BOOLEAN" }),
+        );
+        assert_eq!(
+            bool::from_object(object::Object::Null).unwrap_err().to_string(),
+            "expected BOOLEAN, got NULL",
+        );
+    }
+
+    #[test]
+    fn into_native() {
+        let native = (|a: i64, b: String| format!("{}{}", b, a)).into_native("join");
+        let call = |args: Vec<object::Object>| (native.func)(args).to_string();
+        assert_eq!(call(vec![1i64.into_object(), "x".into_object()]), "x1");
+        assert_eq!(call(vec![1i64.into_object()]), "ERROR: wrong number of arguments: want=2, got=1");
+        assert_eq!(
+            call(vec![1i64.into_object(), 2i64.into_object()]),
+            "ERROR: argument 2 to `join`: expected STRING, got INTEGER",
+        );
+
+        let failing = (|| -> Result<i64, String> { Err("no".to_string()) }).into_native("fail");
+        assert_eq!((failing.func)(vec![]), object::Object::Error("no".to_string()));
+    }
+}
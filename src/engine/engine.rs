@@ -0,0 +1,402 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use crate::engine::*;
+use crate::evaluator::*;
+use crate::lexer::*;
+use crate::object::*;
+use crate::parser::*;
+
+/// Why the engine could not do what the host asked.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The source has syntax errors.
+    Parse(Vec<error::ParseError>),
+    /// The program failed while running; the message is the runtime error's.
+    Runtime(String),
+    /// A value does not have the Rust type the host asked for.
+    Conversion(convert::ConversionError),
+    /// No global has the name the host asked for.
+    UndefinedGlobal(String),
+    /// The program ran into one of the engine's limits and was stopped.
+    LimitExceeded(limits::Limit),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Parse(errors) => {
+                for (i, err) in errors.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    let span = err.span();
+                    write!(f, "{}:{}: {}", span.line, span.column, err)?;
+                }
+                Ok(())
+            }
+            Error::Runtime(message) => write!(f, "{}", message),
+            Error::Conversion(err) => write!(f, "{}", err),
+            Error::UndefinedGlobal(name) => write!(f, "identifier not found: {}", name),
+            Error::LimitExceeded(limit) => write!(f, "{}", limit),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<convert::ConversionError> for Error {
+    fn from(err: convert::ConversionError) -> Error {
+        Error::Conversion(err)
+    }
+}
+
+/// An interpreter for a host program to run Runkey source in.
+///
+/// Each `eval` runs in the same global scope, as lines typed into the REPL
+/// do, so bindings made by one are visible to the next. The host can add
+/// globals of its own, including native functions written as Rust closures:
+///
+/// ```
+/// use runkey::engine::*;
+///
+/// let mut e = engine::Engine::new();
+/// e.register("shout", |s: String| s.to_uppercase());
+/// e.set_global("name", "world");
+/// let greeting: String = e.eval_as("shout(\"hello \" + name)").unwrap();
+/// assert_eq!(greeting, "HELLO WORLD");
+/// ```
+///
+/// To run scripts that are not trusted, bound what each `eval` or `call`
+/// may use with `with_limits`; one that goes over fails with
+/// `Error::LimitExceeded`.
+///
+/// Source passed to `eval` cannot import modules until `set_search_path`
+/// gives the directories to find them in. Each module is run once per
+/// engine.
+#[derive(Default)]
+pub struct Engine {
+    env: environment::Environment,
+    /// The names bound so far and whether each is mutable, so the parser
+    /// can check assignments to them.
+    globals: HashMap<String, bool>,
+    limits: limits::Limits,
+    loader: modules::Loader,
+}
+
+impl Engine {
+    pub fn new() -> Engine {
+        Engine::default()
+    }
+
+    /// An engine whose every `eval` and `call` is held to `limits`.
+    pub fn with_limits(limits: limits::Limits) -> Engine {
+        Engine {
+            limits,
+            ..Engine::default()
+        }
+    }
+
+    /// Allows `import`, finding modules in the directories of `search_path`.
+    /// Modules imported so far are forgotten, and run again if imported
+    /// again.
+    pub fn set_search_path(&mut self, search_path: Vec<PathBuf>) {
+        self.loader = modules::Loader::new(search_path);
+    }
+
+    /// Runs `source`, returning the value of its last statement.
+    pub fn eval(&mut self, source: &str) -> Result<object::Object, Error> {
+        let mut p = parser::Parser::new(lexer::Lexer::new(source.to_string()));
+        p.declare_globals(self.globals.iter().map(|(name, mutable)| (name.as_str(), *mutable)));
+        let program = p.parse_program();
+        if !p.errors().is_empty() {
+            return Err(Error::Parse(p.errors().to_vec()));
+        }
+        self.globals.extend(p.globals().map(|(name, mutable)| (name.to_string(), mutable)));
+        let (env, limits) = (&mut self.env, &self.limits);
+        let result = modules::with_loader(&mut self.loader, None, || {
+            limits::with_limits(limits, || evaluator::eval(&program, env))
+        });
+        finish(result)
+    }
+
+    /// Runs `source` and converts its value to `T`.
+    pub fn eval_as<T: convert::FromObject>(&mut self, source: &str) -> Result<T, Error> {
+        let value = self.eval(source)?;
+        Ok(T::from_object(value)?)
+    }
+
+    /// Binds `name` to a native function. Scripts cannot reassign it.
+    pub fn register<Args>(&mut self, name: &str, function: impl convert::IntoNative<Args>) {
+        let native = object::Object::Native(Rc::new(function.into_native(name)));
+        self.env.set(name.to_string(), native);
+        self.globals.insert(name.to_string(), false);
+    }
+
+    /// Binds `name` to a value. Scripts can reassign it, as if it had been
+    /// bound with `var`.
+    pub fn set_global(&mut self, name: &str, value: impl convert::IntoObject) {
+        self.env.set(name.to_string(), value.into_object());
+        self.globals.insert(name.to_string(), true);
+    }
+
+    /// The value of the global `name`, if it is bound.
+    pub fn global(&self, name: &str) -> Option<object::Object> {
+        self.env.get(name)
+    }
+
+    /// The value of the global `name`, converted to `T`.
+    pub fn global_as<T: convert::FromObject>(&self, name: &str) -> Result<T, Error> {
+        let value = self.global(name).ok_or_else(|| Error::UndefinedGlobal(name.to_string()))?;
+        Ok(T::from_object(value)?)
+    }
+
+    /// Calls the function bound to the global `name`, which may be a script
+    /// function or a builtin.
+    pub fn call(&self, name: &str, args: Vec<object::Object>) -> Result<object::Object, Error> {
+        let function = self.global(name).or_else(|| builtins::lookup(name).map(object::Object::Builtin));
+        let function = function.ok_or_else(|| Error::UndefinedGlobal(name.to_string()))?;
+        finish(limits::with_limits(&self.limits, || evaluator::apply_function(function, args)))
+    }
+}
+
+fn finish((value, exceeded): (object::Object, Option<limits::Limit>)) -> Result<object::Object, Error> {
+    match (value, exceeded) {
+        (_, Some(limit)) => Err(Error::LimitExceeded(limit)),
+        (object::Object::Error(message), None) => Err(Error::Runtime(message)),
+        (value, None) => Ok(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::{Duration, Instant};
+
+    use crate::engine::convert::{IntoObject};
+    use crate::engine::*;
+    use crate::evaluator::*;
+    use crate::object::*;
+    use crate::parser::*;
+
+    #[test]
+    fn globals_persist_between_evals() {
+        let mut e = engine::Engine::new();
+        e.eval("let double = fn(x) { x * 2 }; var total = 1;").unwrap();
+        e.eval("total += double(20);").unwrap();
+        assert_eq!(e.global_as::<i64>("total"), Ok(41));
+
+        e.set_global("limit", 10i64);
+        e.eval("limit = limit + 1;").unwrap();
+        assert_eq!(e.eval_as::<i64>("limit"), Ok(11));
+        assert_eq!(e.global("missing"), None);
+        assert_eq!(e.global_as::<i64>("missing"), Err(engine::Error::UndefinedGlobal("missing".to_string())));
+    }
+
+    #[test]
+    fn native_functions() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut e = engine::Engine::new();
+        let sink = log.clone();
+        e.register("log", move |line: String| sink.borrow_mut().push(line));
+        e.register("sum", |xs: Vec<i64>| xs.iter().sum::<i64>());
+        e.register("scale", |xs: Vec<f64>, by: f64| xs.into_iter().map(|x| x * by).collect::<Vec<_>>());
+        e.register("checked", |ok: bool| if ok { Ok(1i64) } else { Err("not ok") });
+        e.register("apply", |f: convert::Callable, x: i64| f.call(vec![x.into_object()]));
+
+        assert_eq!(e.eval_as::<i64>("sum([1, 2, 3])"), Ok(6));
+        assert_eq!(e.eval_as::<Vec<f64>>("scale([1, 2.5], 2)"), Ok(vec![2.0, 5.0]));
+        assert_eq!(e.eval_as::<i64>("apply(fn(x) { x + 1 }, 41)"), Ok(42));
+        e.eval("for x in 1..3 { log(\"tick\"); }").unwrap();
+        assert_eq!(*log.borrow(), ["tick", "tick"]);
+
+        let tests = [
+            ("checked(false)", engine::Error::Runtime("not ok".to_string())),
+            ("sum(1)", engine::Error::Runtime("argument 1 to `sum`: expected ARRAY, got INTEGER".to_string())),
+            ("sum()", engine::Error::Runtime("wrong number of arguments: want=1, got=0".to_string())),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(e.eval(input), Err(expected), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn call() {
+        let mut e = engine::Engine::new();
+        e.eval("let add = fn(a, b) { a + b };").unwrap();
+        assert_eq!(
+            e.call("add", vec![1i64.into_object(), 2i64.into_object()]),
+            Ok(object::Object::Integer(3)),
+        );
+        assert_eq!(e.call("len", vec!["abc".into_object()]), Ok(object::Object::Integer(3)));
+        assert_eq!(e.call("nope", vec![]), Err(engine::Error::UndefinedGlobal("nope".to_string())));
+    }
+
+    #[test]
+    fn errors() {
+        let mut e = engine::Engine::new();
+        e.register("f", || 1i64);
+        match e.eval("let x = ;\nf = 2;") {
+            Err(engine::Error::Parse(errors)) => assert_eq!(errors.len(), 2),
+            other => panic!("expected parse errors, got {:?}", other),
+        }
+        assert_eq!(e.eval("1 + true"), Err(engine::Error::Runtime("type mismatch: INTEGER + BOOLEAN".to_string())));
+        assert_eq!(
+            e.eval_as::<bool>("1").unwrap_err().to_string(),
+            "expected BOOLEAN, got INTEGER",
+        );
+        assert_eq!(e.eval_as::<i64>("f()"), Ok(1));
+    }
+
+    #[test]
+    fn infinite_loops() {
+        let mut e = engine::Engine::with_limits(limits::Limits {
+            max_steps: Some(10_000),
+            ..limits::Limits::default()
+        });
+        let tests = ["while (true) {}", "var i = 0; while (true) { i += 1; }", "for x in 0..1000000000 {}"];
+        for input in tests {
+            assert_eq!(e.eval(input), Err(engine::Error::LimitExceeded(limits::Limit::Steps(10_000))), "input: {}", input);
+        }
+        // Each eval starts with a fresh budget.
+        assert_eq!(e.eval_as::<i64>("1 + 1"), Ok(2));
+
+        let timeout = Duration::from_millis(50);
+        let mut e = engine::Engine::with_limits(limits::Limits {
+            timeout: Some(timeout),
+            ..limits::Limits::default()
+        });
+        let started = Instant::now();
+        assert_eq!(e.eval("while (true) {}"), Err(engine::Error::LimitExceeded(limits::Limit::Time(timeout))));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn runaway_recursion() {
+        let mut e = engine::Engine::with_limits(limits::Limits {
+            max_depth: Some(20),
+            ..limits::Limits::default()
+        });
+        e.eval("let down = fn(n) { if (n == 0) { 0 } else { down(n - 1) } };").unwrap();
+        assert_eq!(e.eval_as::<i64>("down(19)"), Ok(0));
+        assert_eq!(e.eval("down(20)"), Err(engine::Error::LimitExceeded(limits::Limit::Depth(20))));
+        assert_eq!(
+            e.call("down", vec![100i64.into_object()]),
+            Err(engine::Error::LimitExceeded(limits::Limit::Depth(20))),
+        );
+
+        // Without a depth limit, the stack limit stops it before the test
+        // thread's stack overflows.
+        let mut e = engine::Engine::with_limits(limits::Limits {
+            max_stack: Some(256 * 1024),
+            ..limits::Limits::default()
+        });
+        let err = e.eval("let f = fn(n) { f(n + 1) }; f(0)").unwrap_err();
+        assert_eq!(err, engine::Error::LimitExceeded(limits::Limit::Stack(256 * 1024)));
+        assert_eq!(err.to_string(), "stack limit of 262144 bytes exceeded; is the recursion unbounded?");
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let nested = |open: &str, inner: &str, close: &str, n: usize| open.repeat(n) + inner + &close.repeat(n);
+        let mut e = engine::Engine::new();
+        for input in [nested("(", "1", ")", 100_000), nested("-", "1", "", 100_000)] {
+            match e.eval(&input) {
+                Err(engine::Error::Parse(errors)) => {
+                    assert!(matches!(errors[..], [error::ParseError::TooDeeplyNested { .. }]), "{:?}", errors)
+                }
+                other => panic!("expected a nesting error, got {:?}", other),
+            }
+        }
+
+        // Nesting within the parser's limit still runs.
+        assert_eq!(e.eval_as::<i64>(&nested("(", "1", ")", 250)), Ok(1));
+        assert_eq!(e.eval_as::<i64>(&nested("-", "1", "", 250)), Ok(1));
+        assert_eq!(e.eval_as::<i64>(&nested("", "0", " + 1", 250)), Ok(250));
+        assert_eq!(e.eval_as::<i64>(&nested("if (true) { ", "1", " }", 120)), Ok(1));
+
+        // The stack limit covers nested expressions, not only calls.
+        let mut e = engine::Engine::with_limits(limits::Limits {
+            max_stack: Some(1024),
+            ..limits::Limits::default()
+        });
+        assert_eq!(
+            e.eval(&nested("(", "1", ")", 250)),
+            Err(engine::Error::LimitExceeded(limits::Limit::Stack(1024))),
+        );
+    }
+
+    #[test]
+    fn allocations() {
+        let limited = |limits| {
+            let mut e = engine::Engine::with_limits(limits);
+            e.register("repeat", |s: String, n: i64| s.repeat(n as usize));
+            e
+        };
+        let tests = [
+            (
+                limits::Limits { max_string_len: Some(1000), ..limits::Limits::default() },
+                "var s = \"x\"; while (true) { s = s + s; }",
+                limits::Limit::StringLen(1000),
+            ),
+            (
+                limits::Limits { max_string_len: Some(1000), ..limits::Limits::default() },
+                "var s = \"x\"; while (true) { s += s; }",
+                limits::Limit::StringLen(1000),
+            ),
+            (
+                limits::Limits { max_memory: Some(10_000), ..limits::Limits::default() },
+                "var s = \"x\"; while (true) { s += s; }",
+                limits::Limit::Memory(10_000),
+            ),
+            (
+                limits::Limits { max_string_len: Some(1000), ..limits::Limits::default() },
+                "repeat(\"ab\", 1000)",
+                limits::Limit::StringLen(1000),
+            ),
+            (
+                limits::Limits { max_array_len: Some(100), ..limits::Limits::default() },
+                "var a = []; for i in 0..1000 { a = push(a, i); }",
+                limits::Limit::ArrayLen(100),
+            ),
+            (
+                limits::Limits { max_array_len: Some(2), ..limits::Limits::default() },
+                "{1: 1, 2: 2, 3: 3}",
+                limits::Limit::ArrayLen(2),
+            ),
+            (
+                limits::Limits { max_memory: Some(1 << 20), ..limits::Limits::default() },
+                "var s = \"\"; while (true) { s = s + \"0123456789\"; }",
+                limits::Limit::Memory(1 << 20),
+            ),
+        ];
+        for (limits, input, expected) in tests {
+            assert_eq!(limited(limits).eval(input), Err(engine::Error::LimitExceeded(expected)), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn imports() {
+        let dir = std::env::temp_dir().join(format!("runkey-engine-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("lib.rk"), "export let answer = 42;").unwrap();
+        let unavailable = engine::Error::Runtime("import is not available here".to_string());
+
+        // Imports are off unless the embedder turns them on, even for files
+        // in the working directory.
+        let mut e = engine::Engine::new();
+        assert_eq!(e.eval("import { answer } from \"lib.rk\";"), Err(unavailable.clone()));
+        assert_eq!(e.eval("import \"Cargo.toml\" as c;"), Err(unavailable));
+
+        e.set_search_path(vec![dir.clone()]);
+        assert_eq!(e.eval_as::<i64>("import { answer } from \"lib.rk\"; answer"), Ok(42));
+        assert_eq!(
+            e.eval("import \"Cargo.toml\" as c;"),
+            Err(engine::Error::Runtime("module not found: \"Cargo.toml\"".to_string())),
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
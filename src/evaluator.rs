@@ -0,0 +1,4 @@
+pub mod evaluator;
+pub mod builtins;
+pub mod limits;
+pub mod modules;
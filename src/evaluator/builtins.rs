@@ -0,0 +1,105 @@
+use std::rc::Rc;
+
+use crate::object::*;
+
+/// The functions available in every program without a binding.
+pub const BUILTINS: &[object::Builtin] = &[
+    object::Builtin { name: "len", func: len },
+    object::Builtin { name: "first", func: first },
+    object::Builtin { name: "last", func: last },
+    object::Builtin { name: "rest", func: rest },
+    object::Builtin { name: "push", func: push },
+    object::Builtin { name: "puts", func: puts },
+];
+
+/// Looks up the builtin named `name`. User bindings shadow builtins, so this
+/// is consulted only when the environment has no binding.
+pub fn lookup(name: &str) -> Option<object::Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == name).copied()
+}
+
+fn len(args: Vec<object::Object>) -> object::Object {
+    let [arg] = match <[object::Object; 1]>::try_from(args) {
+        Ok(args) => args,
+        Err(args) => return wrong_arguments(1, args.len()),
+    };
+    let len = match &arg {
+        object::Object::String(value) => value.chars().count(),
+        object::Object::Array(elements) => elements.len(),
+        object::Object::Hash(hash) => hash.len(),
+        _ => return unsupported("len", &arg),
+    };
+    match i64::try_from(len) {
+        Ok(len) => object::Object::Integer(len),
+        Err(_) => object::Object::Error("integer overflow".to_string()),
+    }
+}
+
+fn first(args: Vec<object::Object>) -> object::Object {
+    let elements = match array_argument("first", args) {
+        Ok(elements) => elements,
+        Err(err) => return err,
+    };
+    elements.first().cloned().unwrap_or(object::Object::Null)
+}
+
+fn last(args: Vec<object::Object>) -> object::Object {
+    let elements = match array_argument("last", args) {
+        Ok(elements) => elements,
+        Err(err) => return err,
+    };
+    elements.last().cloned().unwrap_or(object::Object::Null)
+}
+
+/// Returns a new array of all but the first element, or null for an empty
+/// array.
+fn rest(args: Vec<object::Object>) -> object::Object {
+    let elements = match array_argument("rest", args) {
+        Ok(elements) => elements,
+        Err(err) => return err,
+    };
+    match elements.split_first() {
+        Some((_, rest)) => object::Object::Array(Rc::new(rest.to_vec())),
+        None => object::Object::Null,
+    }
+}
+
+/// Returns a new array with the value appended; the argument is unchanged.
+fn push(args: Vec<object::Object>) -> object::Object {
+    let [array, value] = match <[object::Object; 2]>::try_from(args) {
+        Ok(args) => args,
+        Err(args) => return wrong_arguments(2, args.len()),
+    };
+    match array {
+        object::Object::Array(mut elements) => {
+            Rc::make_mut(&mut elements).push(value);
+            object::Object::Array(elements)
+        }
+        other => unsupported("push", &other),
+    }
+}
+
+/// Prints each argument on its own line.
+fn puts(args: Vec<object::Object>) -> object::Object {
+    for arg in args {
+        println!("{}", arg);
+    }
+    object::Object::Null
+}
+
+/// Unpacks the single array argument of `name`.
+fn array_argument(name: &str, args: Vec<object::Object>) -> Result<Rc<Vec<object::Object>>, object::Object> {
+    let [arg] = <[object::Object; 1]>::try_from(args).map_err(|args| wrong_arguments(1, args.len()))?;
+    match arg {
+        object::Object::Array(elements) => Ok(elements),
+        other => Err(unsupported(name, &other)),
+    }
+}
+
+fn wrong_arguments(want: usize, got: usize) -> object::Object {
+    object::Object::Error(format!("wrong number of arguments: want={}, got={}", want, got))
+}
+
+fn unsupported(name: &str, arg: &object::Object) -> object::Object {
+    object::Object::Error(format!("argument to `{}` not supported, got {}", name, arg.type_name()))
+}
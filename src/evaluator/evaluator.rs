@@ -0,0 +1,1172 @@
+use crate::ast::*;
+use crate::evaluator::*;
+use crate::object::*;
+use crate::token::*;
+
+use std::rc::Rc;
+
+pub fn eval(program: &ast::Program, env: &mut environment::Environment) -> object::Object {
+    let mut result = object::Object::Null;
+    for stmt in &program.statements {
+        result = eval_statement(stmt, env);
+        match result {
+            object::Object::ReturnValue(value) => return *value,
+            object::Object::Error(_) => return result,
+            object::Object::Break(_) | object::Object::Continue(_) => return stray_loop_control(&result),
+            _ => {}
+        }
+    }
+    result
+}
+
+fn eval_statement(stmt: &ast::Statement, env: &mut environment::Environment) -> object::Object {
+    if let Err(err) = limits::step() {
+        return err;
+    }
+    match stmt {
+        ast::Statement::LetStatement(stmt) | ast::Statement::ExportStatement(stmt, _) => {
+            let value = eval_expression(&stmt.value, env);
+            if value.is_error() {
+                return value;
+            }
+            env.set(stmt.name.value.clone(), value);
+            object::Object::Null
+        }
+        ast::Statement::ReturnStatement(value, _) => {
+            let value = eval_expression(value, env);
+            if value.is_error() {
+                return value;
+            }
+            object::Object::ReturnValue(Box::new(value))
+        }
+        ast::Statement::ExpressionStatement(expr, _) => eval_expression(expr, env),
+        ast::Statement::WhileStatement(label, condition, body, _) => {
+            eval_while_statement(label.as_ref(), condition, body, env)
+        }
+        ast::Statement::ForStatement(label, variable, iterable, body, _) => {
+            eval_for_statement(label.as_ref(), variable, iterable, body, env)
+        }
+        ast::Statement::BreakStatement(label, _) => {
+            object::Object::Break(label.as_ref().map(|label| label.value.clone()))
+        }
+        ast::Statement::ContinueStatement(label, _) => {
+            object::Object::Continue(label.as_ref().map(|label| label.value.clone()))
+        }
+        ast::Statement::ImportStatement(path, import, _) => eval_import_statement(path, import, env),
+    }
+}
+
+/// Binds the module `path` names, or the exports of it `import` asks for.
+fn eval_import_statement(path: &str, import: &ast::Import, env: &mut environment::Environment) -> object::Object {
+    let exports = match modules::import(path) {
+        Ok(exports) => exports,
+        Err(err) => return err,
+    };
+    match import {
+        ast::Import::Module(name) => env.set(name.value.clone(), object::Object::Hash(exports)),
+        ast::Import::Names(names) => {
+            for (name, binding) in names {
+                let key = object::Object::String(name.value.clone()).hash_key().expect("strings are hashable");
+                match exports.get(&key) {
+                    Some(value) => env.set(binding.value.clone(), value.clone()),
+                    None => return new_error(format!("module \"{}\" has no export named `{}`", path, name)),
+                }
+            }
+        }
+    }
+    object::Object::Null
+}
+
+fn eval_while_statement(
+    label: Option<&ast::Identifier>,
+    condition: &ast::Expression,
+    body: &ast::BlockStatement,
+    env: &mut environment::Environment,
+) -> object::Object {
+    loop {
+        if let Err(err) = limits::step() {
+            return err;
+        }
+        let condition = eval_expression(condition, env);
+        if condition.is_error() {
+            return condition;
+        }
+        if !condition.is_truthy() {
+            return object::Object::Null;
+        }
+        if let Some(exit) = loop_exit(eval_block_statement(body, env), label) {
+            return exit;
+        }
+    }
+}
+
+/// Runs the body once per element of an array, key of a hash or integer of
+/// a range. Each iteration binds the variable in a fresh scope, so closures
+/// made in the body capture that iteration's value.
+fn eval_for_statement(
+    label: Option<&ast::Identifier>,
+    variable: &ast::Identifier,
+    iterable: &ast::Expression,
+    body: &ast::BlockStatement,
+    env: &mut environment::Environment,
+) -> object::Object {
+    let iterable = eval_expression(iterable, env);
+    let items: Box<dyn Iterator<Item = object::Object>> = match iterable {
+        object::Object::Array(elements) => Box::new((0..elements.len()).map(move |i| elements[i].clone())),
+        object::Object::Hash(hash) => Box::new(hash.iter().map(|(key, _)| key.clone()).collect::<Vec<_>>().into_iter()),
+        object::Object::Range(start, end) => Box::new((start..end).map(object::Object::Integer)),
+        object::Object::Error(_) => return iterable,
+        other => return new_error(format!("not iterable: {}", other.type_name())),
+    };
+    for item in items {
+        if let Err(err) = limits::step() {
+            return err;
+        }
+        let mut scope = environment::Environment::new_enclosed(env);
+        scope.set(variable.value.clone(), item);
+        if let Some(exit) = loop_exit(eval_block_statement(body, &mut scope), label) {
+            return exit;
+        }
+    }
+    object::Object::Null
+}
+
+/// Decides what a loop labelled `label` does with the result of one run of
+/// its body: `None` to carry on with the next iteration, or the value the
+/// loop statement should produce. A `break` or `continue` aimed at an outer
+/// loop, a `return` and an error all stop the loop and keep unwinding.
+fn loop_exit(result: object::Object, label: Option<&ast::Identifier>) -> Option<object::Object> {
+    let targets_this_loop = |target: &Option<String>| match target {
+        None => true,
+        Some(target) => label.is_some_and(|label| label.value == *target),
+    };
+    match result {
+        object::Object::Break(ref target) if targets_this_loop(target) => Some(object::Object::Null),
+        object::Object::Continue(ref target) if targets_this_loop(target) => None,
+        object::Object::ReturnValue(_)
+        | object::Object::Error(_)
+        | object::Object::Break(_)
+        | object::Object::Continue(_) => Some(result),
+        _ => None,
+    }
+}
+
+/// The parser rejects `break` and `continue` outside a loop, but a
+/// hand-built program could still let one escape a function or program.
+fn stray_loop_control(result: &object::Object) -> object::Object {
+    new_error(format!("`{}` outside of a loop", result))
+}
+
+fn eval_expression(expr: &ast::Expression, env: &mut environment::Environment) -> object::Object {
+    if let Err(err) = limits::check_stack() {
+        return err;
+    }
+    match expr {
+        ast::Expression::Identifier(ident) => eval_identifier(ident, env),
+        ast::Expression::IntegerLiteral(value, _) => object::Object::Integer(*value),
+        ast::Expression::FloatLiteral(value, _) => object::Object::Float(*value),
+        ast::Expression::Boolean(value, _) => object::Object::Boolean(*value),
+        ast::Expression::StringLiteral(value, _) => object::Object::String(value.clone()),
+        ast::Expression::ArrayLiteral(elements, _) => match eval_expressions(elements, env) {
+            Ok(elements) => allocated(object::Object::Array(Rc::new(elements))),
+            Err(err) => err,
+        },
+        ast::Expression::HashLiteral(pairs, _) => eval_hash_literal(pairs, env),
+        ast::Expression::PrefixExpression(operator, right, _) => {
+            let right = eval_expression(right, env);
+            if right.is_error() {
+                return right;
+            }
+            eval_prefix_expression(operator, right)
+        }
+        ast::Expression::InfixExpression(left, operator @ (token::TokenKind::And | token::TokenKind::Or), right, _) => {
+            eval_logical_expression(operator, left, right, env)
+        }
+        ast::Expression::InfixExpression(left, operator, right, _) => {
+            let left = eval_expression(left, env);
+            if left.is_error() {
+                return left;
+            }
+            let right = eval_expression(right, env);
+            if right.is_error() {
+                return right;
+            }
+            allocated(eval_infix_expression(operator, left, right))
+        }
+        ast::Expression::AssignExpression(target, operator, value, _) => {
+            eval_assign_expression(target, operator, value, env)
+        }
+        ast::Expression::IfExpression(condition, consequence, alternative, _) => {
+            let condition = eval_expression(condition, env);
+            if condition.is_error() {
+                return condition;
+            }
+            if condition.is_truthy() {
+                eval_block_statement(consequence, env)
+            } else if let Some(alternative) = alternative {
+                eval_block_statement(alternative, env)
+            } else {
+                object::Object::Null
+            }
+        }
+        ast::Expression::FunctionLiteral(parameters, body, _) => {
+            object::Object::Function(Rc::new(object::Function {
+                parameters: parameters.clone(),
+                body: body.clone(),
+                env: env.clone(),
+            }))
+        }
+        ast::Expression::CallExpression(function, arguments, _) => {
+            let function = eval_expression(function, env);
+            if function.is_error() {
+                return function;
+            }
+            let args = match eval_expressions(arguments, env) {
+                Ok(args) => args,
+                Err(err) => return err,
+            };
+            apply_function(function, args)
+        }
+        ast::Expression::IndexExpression(left, index, _) => {
+            let left = eval_expression(left, env);
+            if left.is_error() {
+                return left;
+            }
+            let index = eval_expression(index, env);
+            if index.is_error() {
+                return index;
+            }
+            eval_index_expression(left, index)
+        }
+        ast::Expression::SliceExpression(left, start, end, _) => {
+            let left = eval_expression(left, env);
+            if left.is_error() {
+                return left;
+            }
+            let mut bounds = [None, None];
+            for (bound, expr) in bounds.iter_mut().zip([start, end]) {
+                if let Some(expr) = expr {
+                    let value = eval_expression(expr, env);
+                    if value.is_error() {
+                        return value;
+                    }
+                    *bound = Some(value);
+                }
+            }
+            let [start, end] = bounds;
+            allocated(eval_slice_expression(left, start, end))
+        }
+    }
+}
+
+fn eval_block_statement(block: &ast::BlockStatement, env: &mut environment::Environment) -> object::Object {
+    if let Err(err) = limits::check_stack() {
+        return err;
+    }
+    let mut result = object::Object::Null;
+    for stmt in &block.statements {
+        result = eval_statement(stmt, env);
+        if matches!(
+            result,
+            object::Object::ReturnValue(_)
+                | object::Object::Error(_)
+                | object::Object::Break(_)
+                | object::Object::Continue(_)
+        ) {
+            return result;
+        }
+    }
+    result
+}
+
+/// Evaluates expressions left to right, stopping at the first error.
+fn eval_expressions(
+    exprs: &[ast::Expression],
+    env: &mut environment::Environment,
+) -> Result<Vec<object::Object>, object::Object> {
+    let mut result = Vec::with_capacity(exprs.len());
+    for expr in exprs {
+        let evaluated = eval_expression(expr, env);
+        if evaluated.is_error() {
+            return Err(evaluated);
+        }
+        result.push(evaluated);
+    }
+    Ok(result)
+}
+
+/// Calls a function or builtin with evaluated arguments, returning its
+/// result or an error value.
+pub fn apply_function(function: object::Object, args: Vec<object::Object>) -> object::Object {
+    let function = match function {
+        object::Object::Function(function) => function,
+        object::Object::Builtin(builtin) => return allocated((builtin.func)(args)),
+        object::Object::Native(native) => return allocated((native.func)(args)),
+        other => return new_error(format!("not a function: {}", other.type_name())),
+    };
+    let _depth = match limits::enter() {
+        Ok(depth) => depth,
+        Err(err) => return err,
+    };
+    if function.parameters.len() != args.len() {
+        return new_error(format!(
+            "wrong number of arguments: want={}, got={}",
+            function.parameters.len(),
+            args.len(),
+        ));
+    }
+
+    let mut env = environment::Environment::new_enclosed(&function.env);
+    for (param, arg) in function.parameters.iter().zip(args) {
+        env.set(param.value.clone(), arg);
+    }
+    match eval_block_statement(&function.body, &mut env) {
+        object::Object::ReturnValue(value) => *value,
+        result @ (object::Object::Break(_) | object::Object::Continue(_)) => stray_loop_control(&result),
+        evaluated => evaluated,
+    }
+}
+
+fn eval_identifier(ident: &ast::Identifier, env: &environment::Environment) -> object::Object {
+    if let Some(value) = env.get(&ident.value) {
+        return value;
+    }
+    match builtins::lookup(&ident.value) {
+        Some(builtin) => object::Object::Builtin(builtin),
+        None => new_error(format!("identifier not found: {}", ident.value)),
+    }
+}
+
+fn eval_hash_literal(pairs: &[(ast::Expression, ast::Expression)], env: &mut environment::Environment) -> object::Object {
+    let mut hash = object::Hash::new();
+    for (key, value) in pairs {
+        let key = eval_expression(key, env);
+        if key.is_error() {
+            return key;
+        }
+        let hash_key = match key.hash_key() {
+            Some(hash_key) => hash_key,
+            None => return new_error(format!("unusable as hash key: {}", key.type_name())),
+        };
+        let value = eval_expression(value, env);
+        if value.is_error() {
+            return value;
+        }
+        hash.insert(hash_key, key, value);
+    }
+    allocated(object::Object::Hash(Rc::new(hash)))
+}
+
+/// Indexes an array by position or a hash by key. A missing hash key yields
+/// null; an array index out of range is an error.
+pub fn eval_index_expression(left: object::Object, index: object::Object) -> object::Object {
+    match (&left, &index) {
+        (object::Object::Hash(hash), index) => match index.hash_key() {
+            Some(key) => hash.get(&key).cloned().unwrap_or(object::Object::Null),
+            None => new_error(format!("unusable as hash key: {}", index.type_name())),
+        },
+        (object::Object::Array(elements), object::Object::Integer(i)) => {
+            match resolve_index(*i, elements.len()).filter(|&i| i < elements.len()) {
+                Some(i) => elements[i].clone(),
+                None => new_error(format!("index out of bounds: index {}, length {}", i, elements.len())),
+            }
+        }
+        _ => new_error(format!("index operator not supported: {}[{}]", left.type_name(), index.type_name())),
+    }
+}
+
+/// Evaluates `left[start:end]`, where a missing bound means the start or end
+/// of the array. The result is a new array.
+pub fn eval_slice_expression(
+    left: object::Object,
+    start: Option<object::Object>,
+    end: Option<object::Object>,
+) -> object::Object {
+    let elements = match &left {
+        object::Object::Array(elements) => elements,
+        _ => return new_error(format!("slice operator not supported: {}[:]", left.type_name())),
+    };
+    let len = elements.len();
+    let (start, end) = match (slice_bound(start, 0), slice_bound(end, len as i64)) {
+        (Ok(start), Ok(end)) => (start, end),
+        (Err(err), _) | (_, Err(err)) => return err,
+    };
+    match (resolve_index(start, len), resolve_index(end, len)) {
+        (Some(from), Some(to)) if from <= to => object::Object::Array(Rc::new(elements[from..to].to_vec())),
+        _ => new_error(format!("slice out of bounds: [{}:{}], length {}", start, end, len)),
+    }
+}
+
+fn slice_bound(bound: Option<object::Object>, default: i64) -> Result<i64, object::Object> {
+    match bound {
+        None => Ok(default),
+        Some(object::Object::Integer(i)) => Ok(i),
+        Some(other) => Err(new_error(format!("slice bound must be INTEGER, got {}", other.type_name()))),
+    }
+}
+
+/// Maps a possibly negative index, counted from the end, to a position in
+/// `0..=len`.
+fn resolve_index(index: i64, len: usize) -> Option<usize> {
+    let len = i64::try_from(len).ok()?;
+    let index = if index < 0 { index + len } else { index };
+    usize::try_from(index).ok().filter(|&i| i as i64 <= len)
+}
+
+pub fn eval_prefix_expression(operator: &token::TokenKind, right: object::Object) -> object::Object {
+    match (operator, right) {
+        (token::TokenKind::Bang, right) => object::Object::Boolean(!right.is_truthy()),
+        (token::TokenKind::Minus, object::Object::Integer(value)) => match value.checked_neg() {
+            Some(value) => object::Object::Integer(value),
+            None => new_error("integer overflow".to_string()),
+        },
+        (token::TokenKind::Minus, object::Object::Float(value)) => object::Object::Float(-value),
+        (operator, right) => new_error(format!("unknown operator: {}{}", operator, right.type_name())),
+    }
+}
+
+pub fn eval_infix_expression(operator: &token::TokenKind, left: object::Object, right: object::Object) -> object::Object {
+    match (left, right) {
+        (object::Object::Integer(left), object::Object::Integer(right)) => {
+            eval_integer_infix_expression(operator, left, right)
+        }
+        (object::Object::Float(left), object::Object::Float(right)) => eval_float_infix_expression(operator, left, right),
+        // Mixed arithmetic promotes the integer operand to a float.
+        (object::Object::Integer(left), object::Object::Float(right)) => {
+            eval_float_infix_expression(operator, left as f64, right)
+        }
+        (object::Object::Float(left), object::Object::Integer(right)) => {
+            eval_float_infix_expression(operator, left, right as f64)
+        }
+        (object::Object::Boolean(left), object::Object::Boolean(right)) => match operator {
+            token::TokenKind::Eq => object::Object::Boolean(left == right),
+            token::TokenKind::NotEq => object::Object::Boolean(left != right),
+            _ => new_error(format!("unknown operator: BOOLEAN {} BOOLEAN", operator)),
+        },
+        (object::Object::String(left), object::Object::String(right)) => match operator {
+            token::TokenKind::Plus => object::Object::String(left + &right),
+            token::TokenKind::Eq => object::Object::Boolean(left == right),
+            token::TokenKind::NotEq => object::Object::Boolean(left != right),
+            _ => new_error(format!("unknown operator: STRING {} STRING", operator)),
+        },
+        (left, right) if left.type_name() != right.type_name() => new_error(format!(
+            "type mismatch: {} {} {}",
+            left.type_name(),
+            operator,
+            right.type_name(),
+        )),
+        (left, right) => new_error(format!(
+            "unknown operator: {} {} {}",
+            left.type_name(),
+            operator,
+            right.type_name(),
+        )),
+    }
+}
+
+/// Evaluates `&&` and `||`, skipping the right operand when the left one
+/// decides the result.
+fn eval_logical_expression(
+    operator: &token::TokenKind,
+    left: &ast::Expression,
+    right: &ast::Expression,
+    env: &mut environment::Environment,
+) -> object::Object {
+    let left = eval_expression(left, env);
+    if left.is_error() {
+        return left;
+    }
+    match (operator, left.is_truthy()) {
+        (token::TokenKind::And, false) => return object::Object::Boolean(false),
+        (token::TokenKind::Or, true) => return object::Object::Boolean(true),
+        _ => {}
+    }
+    let right = eval_expression(right, env);
+    if right.is_error() {
+        return right;
+    }
+    object::Object::Boolean(right.is_truthy())
+}
+
+/// Evaluates an assignment such as `x = 1`, `a[i][j] = v` or `h["k"] += 1`,
+/// updating the binding in place and yielding the assigned value. The
+/// indices are evaluated once, before the right-hand side.
+fn eval_assign_expression(
+    target: &ast::Expression,
+    operator: &token::TokenKind,
+    value: &ast::Expression,
+    env: &mut environment::Environment,
+) -> object::Object {
+    let mut indices = Vec::new();
+    let mut root = target;
+    while let ast::Expression::IndexExpression(left, index, _) = root {
+        indices.push(index);
+        root = left;
+    }
+    let ident = match root {
+        ast::Expression::Identifier(ident) => ident,
+        _ => return new_error("invalid assignment target".to_string()),
+    };
+    let mut keys = Vec::with_capacity(indices.len());
+    for index in indices.into_iter().rev() {
+        let key = eval_expression(index, env);
+        if key.is_error() {
+            return key;
+        }
+        keys.push(key);
+    }
+
+    let current = match operator {
+        token::TokenKind::Assign => None,
+        _ => {
+            let mut current = eval_identifier(ident, env);
+            for key in &keys {
+                if current.is_error() {
+                    break;
+                }
+                current = eval_index_expression(current, key.clone());
+            }
+            if current.is_error() {
+                return current;
+            }
+            Some(current)
+        }
+    };
+    let mut value = eval_expression(value, env);
+    if value.is_error() {
+        return value;
+    }
+    if let Some(current) = current {
+        value = match operator.compound_operator() {
+            Some(operator) => allocated(eval_infix_expression(&operator, current, value)),
+            None => new_error(format!("unknown operator: {}", operator)),
+        };
+        if value.is_error() {
+            return value;
+        }
+    }
+
+    match env.update(&ident.value, |slot| assign_path(slot, &keys, value.clone())) {
+        Some(Ok(())) => value,
+        Some(Err(err)) => err,
+        None => new_error(format!("identifier not found: {}", ident.value)),
+    }
+}
+
+/// Stores `value` at the position `keys` lead to within `target`. Arrays and
+/// hashes are copied first if other values share them, so the assignment is
+/// not visible through those.
+pub fn assign_path(target: &mut object::Object, keys: &[object::Object], value: object::Object) -> Result<(), object::Object> {
+    let (key, rest) = match keys.split_first() {
+        Some(split) => split,
+        None => {
+            *target = value;
+            return Ok(());
+        }
+    };
+    match (target, key) {
+        (object::Object::Array(elements), object::Object::Integer(i)) => {
+            match resolve_index(*i, elements.len()).filter(|&i| i < elements.len()) {
+                Some(i) => assign_path(&mut Rc::make_mut(elements)[i], rest, value),
+                None => Err(new_error(format!("index out of bounds: index {}, length {}", i, elements.len()))),
+            }
+        }
+        (object::Object::Hash(hash), key) => {
+            let hash_key = match key.hash_key() {
+                Some(hash_key) => hash_key,
+                None => return Err(new_error(format!("unusable as hash key: {}", key.type_name()))),
+            };
+            if rest.is_empty() {
+                Rc::make_mut(hash).insert(hash_key, key.clone(), value);
+                return Ok(());
+            }
+            match Rc::make_mut(hash).get_mut(&hash_key) {
+                Some(entry) => assign_path(entry, rest, value),
+                None => Err(new_error(format!("index assignment not supported: NULL[{}]", rest[0].type_name()))),
+            }
+        }
+        (target, key) => Err(new_error(format!(
+            "index assignment not supported: {}[{}]",
+            target.type_name(),
+            key.type_name(),
+        ))),
+    }
+}
+
+fn eval_integer_infix_expression(operator: &token::TokenKind, left: i64, right: i64) -> object::Object {
+    let value = match operator {
+        token::TokenKind::Plus => left.checked_add(right),
+        token::TokenKind::Minus => left.checked_sub(right),
+        token::TokenKind::Asterisk => left.checked_mul(right),
+        token::TokenKind::Slash => {
+            if right == 0 {
+                return new_error("division by zero".to_string());
+            }
+            left.checked_div(right)
+        }
+        token::TokenKind::Percent => {
+            if right == 0 {
+                return new_error("division by zero".to_string());
+            }
+            left.checked_rem(right)
+        }
+        token::TokenKind::Power => match u32::try_from(right) {
+            Ok(exponent) => left.checked_pow(exponent),
+            Err(_) if right < 0 => return new_error("negative integer exponent".to_string()),
+            Err(_) => None,
+        },
+        token::TokenKind::Lt => return object::Object::Boolean(left < right),
+        token::TokenKind::Gt => return object::Object::Boolean(left > right),
+        token::TokenKind::LtEq => return object::Object::Boolean(left <= right),
+        token::TokenKind::GtEq => return object::Object::Boolean(left >= right),
+        token::TokenKind::Eq => return object::Object::Boolean(left == right),
+        token::TokenKind::NotEq => return object::Object::Boolean(left != right),
+        token::TokenKind::DotDot => return object::Object::Range(left, right),
+        _ => return new_error(format!("unknown operator: INTEGER {} INTEGER", operator)),
+    };
+    match value {
+        Some(value) => object::Object::Integer(value),
+        None => new_error("integer overflow".to_string()),
+    }
+}
+
+fn eval_float_infix_expression(operator: &token::TokenKind, left: f64, right: f64) -> object::Object {
+    match operator {
+        token::TokenKind::Plus => object::Object::Float(left + right),
+        token::TokenKind::Minus => object::Object::Float(left - right),
+        token::TokenKind::Asterisk => object::Object::Float(left * right),
+        token::TokenKind::Slash if right == 0.0 => new_error("division by zero".to_string()),
+        token::TokenKind::Slash => object::Object::Float(left / right),
+        token::TokenKind::Percent if right == 0.0 => new_error("division by zero".to_string()),
+        token::TokenKind::Percent => object::Object::Float(left % right),
+        token::TokenKind::Power => object::Object::Float(left.powf(right)),
+        token::TokenKind::Lt => object::Object::Boolean(left < right),
+        token::TokenKind::Gt => object::Object::Boolean(left > right),
+        token::TokenKind::LtEq => object::Object::Boolean(left <= right),
+        token::TokenKind::GtEq => object::Object::Boolean(left >= right),
+        token::TokenKind::Eq => object::Object::Boolean(left == right),
+        token::TokenKind::NotEq => object::Object::Boolean(left != right),
+        _ => new_error(format!("unknown operator: FLOAT {} FLOAT", operator)),
+    }
+}
+
+/// Counts a value the program just created against the memory and size
+/// limits, yielding an error instead if it goes over one.
+fn allocated(value: object::Object) -> object::Object {
+    match limits::allocate(&value) {
+        Ok(()) => value,
+        Err(err) => err,
+    }
+}
+
+fn new_error(message: String) -> object::Object {
+    object::Object::Error(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::*;
+    use crate::lexer::*;
+    use crate::object::*;
+    use crate::parser::*;
+
+    #[test]
+    fn integer_expressions() {
+        let tests = [
+            ("5", 5),
+            ("10", 10),
+            ("-5", -5),
+            ("-10", -10),
+            ("5 + 5 + 5 + 5 - 10", 10),
+            ("2 * 2 * 2 * 2 * 2", 32),
+            ("-50 + 100 + -50", 0),
+            ("5 * 2 + 10", 20),
+            ("5 + 2 * 10", 25),
+            ("20 + 2 * -10", 0),
+            ("50 / 2 * 2 + 10", 60),
+            ("2 * (5 + 10)", 30),
+            ("3 * 3 * 3 + 10", 37),
+            ("3 * (3 * 3) + 10", 37),
+            ("(5 + 10 * 2 + 15 / 3) * 2 + -10", 50),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), object::Object::Integer(expected), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn float_expressions() {
+        let tests = [
+            ("2.75", 2.75),
+            ("-2.5", -2.5),
+            ("1e-9", 1e-9),
+            ("0.1 + 0.2", 0.1 + 0.2),
+            ("1.5 * 4", 6.0),
+            ("1 + 0.5", 1.5),
+            ("7 / 2.0", 3.5),
+            ("0xFF - 0.5", 254.5),
+            ("1_000 * 1.5", 1500.0),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), object::Object::Float(expected), "input: {}", input);
+        }
+
+        let tests = [
+            ("1 < 1.5", true),
+            ("2.5 > 3", false),
+            ("1.0 == 1", true),
+            ("0.5 != 0.5", false),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), object::Object::Boolean(expected), "input: {}", input);
+        }
+        assert_eq!(test_eval("2.0").to_string(), "2.0");
+    }
+
+    #[test]
+    fn extended_operators() {
+        let tests = [
+            ("7 % 3", object::Object::Integer(1)),
+            ("-7 % 3", object::Object::Integer(-1)),
+            ("2 ** 10", object::Object::Integer(1024)),
+            ("2 ** 3 ** 2", object::Object::Integer(512)),
+            ("2 ** 0.5 * 2 ** 0.5", object::Object::Float(2.0000000000000004)),
+            ("7.5 % 2", object::Object::Float(1.5)),
+            ("1 <= 1", object::Object::Boolean(true)),
+            ("2 <= 1", object::Object::Boolean(false)),
+            ("1 >= 2", object::Object::Boolean(false)),
+            ("1.5 >= 1", object::Object::Boolean(true)),
+            ("true && 1", object::Object::Boolean(true)),
+            ("false || undefined", object::Object::Error("identifier not found: undefined".to_string())),
+            ("false && undefined", object::Object::Boolean(false)),
+            ("true || undefined", object::Object::Boolean(true)),
+            ("1 < 2 && 2 < 3", object::Object::Boolean(true)),
+            ("let mut x = 1; x += 2; x", object::Object::Integer(3)),
+            ("let mut x = 10; x -= 4", object::Object::Integer(6)),
+            ("let mut x = 3; x *= x", object::Object::Integer(9)),
+            ("let mut x = 9; x /= 2; x", object::Object::Integer(4)),
+            ("let mut s = \"a\"; s += \"b\"; s", object::Object::String("ab".to_string())),
+            ("let mut x = 1; let mut y = 2; x += y += 3; x", object::Object::Integer(6)),
+            ("let mut x = 1; let f = fn() { x += 1 }; f(); f(); x", object::Object::Integer(3)),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), expected, "input: {}", input);
+        }
+    }
+
+    fn array(elements: &[i64]) -> object::Object {
+        object::Object::Array(std::rc::Rc::new(elements.iter().map(|&e| object::Object::Integer(e)).collect()))
+    }
+
+    #[test]
+    fn array_literals() {
+        assert_eq!(test_eval("[1, 2 * 2, 3 + 3]"), array(&[1, 4, 6]));
+        assert_eq!(test_eval(r#"[1, "two", [3], fn(x) { x }]"#).to_string(), r#"[1, "two", [3], fn(x) { ... }]"#);
+    }
+
+    #[test]
+    fn index_expressions() {
+        let tests = [
+            ("[1, 2, 3][0]", object::Object::Integer(1)),
+            ("[1, 2, 3][2]", object::Object::Integer(3)),
+            ("let i = 0; [1][i]", object::Object::Integer(1)),
+            ("[1, 2, 3][1 + 1]", object::Object::Integer(3)),
+            ("let a = [1, 2, 3]; a[0] + a[1] + a[2]", object::Object::Integer(6)),
+            ("[1, 2, 3][-1]", object::Object::Integer(3)),
+            ("[1, 2, 3][-3]", object::Object::Integer(1)),
+            ("let f = fn() { [fn(x) { x * 2 }] }; f()[0](21)", object::Object::Integer(42)),
+            ("[1, 2, 3][3]", object::Object::Error("index out of bounds: index 3, length 3".to_string())),
+            ("[1, 2, 3][-4]", object::Object::Error("index out of bounds: index -4, length 3".to_string())),
+            ("[][0]", object::Object::Error("index out of bounds: index 0, length 0".to_string())),
+            ("[1][true]", object::Object::Error("index operator not supported: ARRAY[BOOLEAN]".to_string())),
+            ("1[0]", object::Object::Error("index operator not supported: INTEGER[INTEGER]".to_string())),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn slice_expressions() {
+        let tests = [
+            ("[1, 2, 3, 4][1:3]", array(&[2, 3])),
+            ("[1, 2, 3, 4][:2]", array(&[1, 2])),
+            ("[1, 2, 3, 4][2:]", array(&[3, 4])),
+            ("[1, 2, 3, 4][:]", array(&[1, 2, 3, 4])),
+            ("[1, 2, 3, 4][-2:]", array(&[3, 4])),
+            ("[1, 2, 3, 4][:-1]", array(&[1, 2, 3])),
+            ("[1, 2, 3, 4][2:2]", array(&[])),
+            ("[1, 2, 3, 4][4:]", array(&[])),
+            ("[1, 2, 3][2:1]", object::Object::Error("slice out of bounds: [2:1], length 3".to_string())),
+            ("[1, 2, 3][0:4]", object::Object::Error("slice out of bounds: [0:4], length 3".to_string())),
+            ("[1, 2, 3][:-4]", object::Object::Error("slice out of bounds: [0:-4], length 3".to_string())),
+            ("[1][\"a\":]", object::Object::Error("slice bound must be INTEGER, got STRING".to_string())),
+            ("\"abc\"[1:]", object::Object::Error("slice operator not supported: STRING[:]".to_string())),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn hash_literals() {
+        let input = r#"let two = "two";
+{
+    "one": 10 - 9,
+    two: 1 + 1,
+    "thr" + "ee": 6 / 2,
+    4: 4,
+    true: 5,
+    false: 6,
+    "one": 7
+}"#;
+        let hash = match test_eval(input) {
+            object::Object::Hash(hash) => hash,
+            other => panic!("not a Hash: {:?}", other),
+        };
+        let expected = [
+            (object::Object::String("one".to_string()), object::Object::Integer(7)),
+            (object::Object::String("two".to_string()), object::Object::Integer(2)),
+            (object::Object::String("three".to_string()), object::Object::Integer(3)),
+            (object::Object::Integer(4), object::Object::Integer(4)),
+            (object::Object::Boolean(true), object::Object::Integer(5)),
+            (object::Object::Boolean(false), object::Object::Integer(6)),
+        ];
+        let pairs: Vec<_> = hash.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        assert_eq!(pairs, expected);
+        assert_eq!(
+            test_eval(r#"{"b": 1, "a": [2], 3: {"c": "d"}}"#).to_string(),
+            r#"{"b": 1, "a": [2], 3: {"c": "d"}}"#,
+        );
+    }
+
+    #[test]
+    fn hash_index_expressions() {
+        let tests = [
+            (r#"{"foo": 5}["foo"]"#, object::Object::Integer(5)),
+            (r#"{"foo": 5}["bar"]"#, object::Object::Null),
+            (r#"let key = "foo"; {"foo": 5}[key]"#, object::Object::Integer(5)),
+            (r#"{}["foo"]"#, object::Object::Null),
+            ("{5: 5}[5]", object::Object::Integer(5)),
+            (r#"{5: 5}["5"]"#, object::Object::Null),
+            ("{true: 5}[true]", object::Object::Integer(5)),
+            ("{false: 5}[false]", object::Object::Integer(5)),
+            (r#"len({"a": 1, "b": 2, "a": 3})"#, object::Object::Integer(2)),
+            (r#"{"name": "x"}[fn(x) { x }]"#, object::Object::Error("unusable as hash key: FUNCTION".to_string())),
+            ("{fn(x) { x }: 1}", object::Object::Error("unusable as hash key: FUNCTION".to_string())),
+            ("{[1]: 1}", object::Object::Error("unusable as hash key: ARRAY".to_string())),
+            ("{1.5: 1}", object::Object::Error("unusable as hash key: FLOAT".to_string())),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn loops() {
+        let tests = [
+            ("let mut i = 0; while (i < 5) { i += 1 }; i", object::Object::Integer(5)),
+            ("while (false) { 1 }", object::Object::Null),
+            ("let mut sum = 0; for x in [1, 2, 3] { sum += x }; sum", object::Object::Integer(6)),
+            ("let mut sum = 0; for x in 0..5 { sum += x }; sum", object::Object::Integer(10)),
+            ("let mut sum = 0; for x in 5..0 { sum += x }; sum", object::Object::Integer(0)),
+            (r#"let mut s = ""; for k in {"b": 1, "a": 2} { s += k }; s"#, object::Object::String("ba".to_string())),
+            ("let mut n = 0; for x in 0..10 { if (x == 3) { break; } n += 1 }; n", object::Object::Integer(3)),
+            ("let mut n = 0; for x in 0..10 { if (x % 2 == 0) { continue; } n += x }; n", object::Object::Integer(25)),
+            (
+                "let mut n = 0; let mut i = 0; while (true) { i += 1; if (i > 10) { break } if (i % 3 != 0) { continue } n += i }; n",
+                object::Object::Integer(18),
+            ),
+            (
+                "let mut n = 0; outer: for i in 0..3 { for j in 0..3 { if (j == 2) { continue outer; } if (i == 2) { break outer; } n += 1 } }; n",
+                object::Object::Integer(4),
+            ),
+            (
+                "let mut n = 0; outer: while (true) { inner: while (true) { break outer; } n += 1 }; n",
+                object::Object::Integer(0),
+            ),
+            (
+                "let find = fn(xs, y) { for x in xs { if (x == y) { return true; } } false }; [find([1, 2], 2), find([1], 2)]",
+                object::Object::Array(std::rc::Rc::new(vec![object::Object::Boolean(true), object::Object::Boolean(false)])),
+            ),
+            (
+                "let f = fn() { while (true) { for x in 0..3 { return x + 10; } } }; f()",
+                object::Object::Integer(10),
+            ),
+            ("for x in 1 { x }", object::Object::Error("not iterable: INTEGER".to_string())),
+            ("for x in [1] { x + true }", object::Object::Error("type mismatch: INTEGER + BOOLEAN".to_string())),
+            ("while (y) { 1 }", object::Object::Error("identifier not found: y".to_string())),
+            ("1.5..2", object::Object::Error("unknown operator: FLOAT .. FLOAT".to_string())),
+            ("0..3", object::Object::Range(0, 3)),
+            ("let x = 5; for x in 0..2 { x }; x", object::Object::Integer(5)),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn assignments() {
+        let tests = [
+            ("var x = 1; x = 2; x", object::Object::Integer(2)),
+            ("let mut x = 1; x = x + 1", object::Object::Integer(2)),
+            ("var x = 1; var y = 2; x = y = 3; x + y", object::Object::Integer(6)),
+            ("var x = 1; let f = fn() { x = 5 }; f(); x", object::Object::Integer(5)),
+            ("var a = [1, 2, 3]; a[0] = 10; a[-1] = 30; a", array(&[10, 2, 30])),
+            ("var a = [1, 2]; let b = a; a[0] = 5; b", array(&[1, 2])),
+            ("var a = [[1, 2], [3]]; a[0][1] = 7; a[0]", array(&[1, 7])),
+            ("var a = [1]; a[0] += 4; a[0]", object::Object::Integer(5)),
+            (r#"var h = {"a": 1}; h["b"] = 2; h["a"] = 3; h"#, test_eval(r#"{"a": 3, "b": 2}"#)),
+            (r#"var h = {"n": [0]}; h["n"][0] += 1; h["n"][0]"#, object::Object::Integer(1)),
+            (r#"var h = {}; h["k"] = [1]; len(h)"#, object::Object::Integer(1)),
+            (
+                "var xs = []; for i in 0..3 { xs = push(xs, i * i) }; xs",
+                array(&[0, 1, 4]),
+            ),
+            ("var a = [1]; a[1] = 2", object::Object::Error("index out of bounds: index 1, length 1".to_string())),
+            ("var a = [1]; a[true] = 2", object::Object::Error("index assignment not supported: ARRAY[BOOLEAN]".to_string())),
+            ("var s = \"ab\"; s[0] = 1", object::Object::Error("index assignment not supported: STRING[INTEGER]".to_string())),
+            ("var h = {}; h[[1]] = 1", object::Object::Error("unusable as hash key: ARRAY".to_string())),
+            (r#"var h = {}; h["a"]["b"] = 1"#, object::Object::Error("index assignment not supported: NULL[STRING]".to_string())),
+            (r#"var h = {}; h["a"] += 1"#, object::Object::Error("type mismatch: NULL + INTEGER".to_string())),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), expected, "input: {}", input);
+        }
+
+        // A failed assignment leaves the target untouched.
+        let mut env = environment::Environment::new();
+        let program = parser::Parser::new(lexer::Lexer::new("var a = [[1], 2]; a[0][5] = 3;".to_string())).parse_program();
+        let expected = object::Object::Error("index out of bounds: index 5, length 1".to_string());
+        assert_eq!(evaluator::eval(&program, &mut env), expected);
+        assert_eq!(env.get("a").map(|a| a.to_string()), Some("[[1], 2]".to_string()));
+    }
+
+    #[test]
+    fn builtin_functions() {
+        let tests = [
+            (r#"len("")"#, object::Object::Integer(0)),
+            (r#"len("four")"#, object::Object::Integer(4)),
+            (r#"len("héllo")"#, object::Object::Integer(5)),
+            ("len([1, 2, 3])", object::Object::Integer(3)),
+            ("len(1)", object::Object::Error("argument to `len` not supported, got INTEGER".to_string())),
+            (r#"len("one", "two")"#, object::Object::Error("wrong number of arguments: want=1, got=2".to_string())),
+            ("first([1, 2, 3])", object::Object::Integer(1)),
+            ("first([])", object::Object::Null),
+            ("last([1, 2, 3])", object::Object::Integer(3)),
+            ("last([])", object::Object::Null),
+            ("last(1)", object::Object::Error("argument to `last` not supported, got INTEGER".to_string())),
+            ("rest([1, 2, 3])", array(&[2, 3])),
+            ("rest([1])", array(&[])),
+            ("rest([])", object::Object::Null),
+            ("push([], 1)", array(&[1])),
+            ("let a = [1]; let b = push(a, 2); a", array(&[1])),
+            ("let a = [1]; let b = push(a, 2); b", array(&[1, 2])),
+            ("push(1, 1)", object::Object::Error("argument to `push` not supported, got INTEGER".to_string())),
+            ("puts()", object::Object::Null),
+            ("let len = fn(x) { 42 }; len([])", object::Object::Integer(42)),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn boolean_expressions() {
+        let tests = [
+            ("true", true),
+            ("false", false),
+            ("1 < 2", true),
+            ("1 > 2", false),
+            ("1 == 1", true),
+            ("1 != 1", false),
+            ("true == true", true),
+            ("true != false", true),
+            ("(1 < 2) == true", true),
+            ("(1 > 2) == true", false),
+            ("!true", false),
+            ("!!true", true),
+            ("!5", false),
+            ("!!5", true),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), object::Object::Boolean(expected), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn string_expressions() {
+        let tests = [
+            (r#""Hello World!""#, object::Object::String("Hello World!".to_string())),
+            (r#""Hello" + " " + "World!""#, object::Object::String("Hello World!".to_string())),
+            (r#"let greet = fn(name) { "hi, " + name }; greet("you")"#, object::Object::String("hi, you".to_string())),
+            (r#""a" == "a""#, object::Object::Boolean(true)),
+            (r#""a" == "b""#, object::Object::Boolean(false)),
+            (r#""a" != "b""#, object::Object::Boolean(true)),
+            (r#""a" + "b" == "ab""#, object::Object::Boolean(true)),
+            (r#"if ("") { 1 } else { 2 }"#, object::Object::Integer(1)),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn return_statements() {
+        let tests = [
+            ("return 10;", 10),
+            ("return 10; 9;", 10),
+            ("return 2 * 5; 9;", 10),
+            ("9; return 2 * 5; 9;", 10),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), object::Object::Integer(expected), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn if_else_expressions() {
+        let tests = [
+            ("if (true) { 10 }", object::Object::Integer(10)),
+            ("if (false) { 10 }", object::Object::Null),
+            ("if (1) { 10 }", object::Object::Integer(10)),
+            ("if (1 < 2) { 10 }", object::Object::Integer(10)),
+            ("if (1 > 2) { 10 }", object::Object::Null),
+            ("if (1 > 2) { 10 } else { 20 }", object::Object::Integer(20)),
+            ("if (1 < 2) { 10 } else { 20 }", object::Object::Integer(10)),
+            ("let x = if (1 < 2) { 10 } else { 20 }; x * 2", object::Object::Integer(20)),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn nested_return() {
+        let tests = [
+            ("if (10 > 1) { if (10 > 1) { return 10; } return 1; }", 10),
+            ("if (10 > 1) { return 10; 9; }", 10),
+            ("let f = fn(x) { if (x > 1) { return x; } 0 }; f(5) + f(0);", 5),
+            ("let f = fn() { if (true) { if (true) { return 1; } } 2 }; f() + 10;", 11),
+            ("let max = fn(a, b) { if (a > b) { a } else { b } }; max(3, 7);", 7),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), object::Object::Integer(expected), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn error_handling() {
+        let tests = [
+            ("5 + true;", "type mismatch: INTEGER + BOOLEAN"),
+            ("5 + true; 5;", "type mismatch: INTEGER + BOOLEAN"),
+            ("-true", "unknown operator: -BOOLEAN"),
+            ("true + false;", "unknown operator: BOOLEAN + BOOLEAN"),
+            ("5; true + false; 5", "unknown operator: BOOLEAN + BOOLEAN"),
+            ("foobar", "identifier not found: foobar"),
+            (r#""Hello" - "World""#, "unknown operator: STRING - STRING"),
+            (r#""1" + 1"#, "type mismatch: STRING + INTEGER"),
+            (r#""1" == 1"#, "type mismatch: STRING == INTEGER"),
+            ("if (10 > 1) { true + false; }", "unknown operator: BOOLEAN + BOOLEAN"),
+            ("if (10 > 1) { if (10 > 1) { return true + false; } return 1; }", "unknown operator: BOOLEAN + BOOLEAN"),
+            ("1 / 0", "division by zero"),
+            ("9223372036854775807 + 1", "integer overflow"),
+            ("1.5 / 0", "division by zero"),
+            ("1.5 + true", "type mismatch: FLOAT + BOOLEAN"),
+            ("5 % 0", "division by zero"),
+            ("2 ** -1", "negative integer exponent"),
+            ("2 ** 63", "integer overflow"),
+            ("let mut x = 1; x += true", "type mismatch: INTEGER + BOOLEAN"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), object::Object::Error(expected.to_string()), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn let_statements() {
+        let tests = [
+            ("let a = 5; a;", 5),
+            ("let a = 5 * 5; a;", 25),
+            ("let a = 5; let b = a; b;", 5),
+            ("let a = 5; let b = a; let c = a + b + 5; c;", 15),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), object::Object::Integer(expected), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn function_object() {
+        match test_eval("fn(x) { x + 2; };") {
+            object::Object::Function(function) => {
+                assert_eq!(function.parameters.len(), 1);
+                assert_eq!(function.parameters[0].value, "x");
+                assert_eq!(function.body.statements.len(), 1);
+            }
+            other => panic!("object is not Function. got={:?}", other),
+        }
+    }
+
+    #[test]
+    fn function_application() {
+        let tests = [
+            ("let identity = fn(x) { x; }; identity(5);", 5),
+            ("let identity = fn(x) { return x; }; identity(5);", 5),
+            ("let double = fn(x) { x * 2; }; double(5);", 10),
+            ("let add = fn(x, y) { x + y; }; add(5, 5);", 10),
+            ("let add = fn(x, y) { x + y; }; add(5 + 5, add(5, 5));", 20),
+            ("fn(x) { x; }(5)", 5),
+            ("let f = fn() { return 1; 2; }; f() + 10;", 11),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), object::Object::Integer(expected), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn closures() {
+        let tests = [
+            ("let newAdder = fn(x) { fn(y) { x + y }; }; let addTwo = newAdder(2); addTwo(2);", 4),
+            ("let adder = fn(x) { fn(y) { x + y } }; adder(3)(4);", 7),
+            ("let apply = fn(f, x) { f(x) }; apply(fn(n) { n * n }, 6);", 36),
+            ("let x = 1; let f = fn() { x }; let x = 2; f();", 2),
+            ("let fact = fn(n) { return n * 1; }; fact(5);", 5),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), object::Object::Integer(expected), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn call_errors() {
+        let tests = [
+            ("5(1)", "not a function: INTEGER"),
+            ("fn(x) { x }()", "wrong number of arguments: want=1, got=0"),
+            ("fn(x) { x }(1, y)", "identifier not found: y"),
+            ("fn() { return true + 1; 2 }()", "type mismatch: BOOLEAN + INTEGER"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), object::Object::Error(expected.to_string()), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn bindings_persist_across_programs() {
+        let mut env = environment::Environment::new();
+        for (input, expected) in [("let x = 5;", object::Object::Null), ("x * 2", object::Object::Integer(10))] {
+            let mut p = parser::Parser::new(lexer::Lexer::new(input.to_string()));
+            let program = p.parse_program();
+            assert_eq!(evaluator::eval(&program, &mut env), expected);
+        }
+    }
+
+    fn test_eval(input: &str) -> object::Object {
+        let l = lexer::Lexer::new(input.to_string());
+        let mut p = parser::Parser::new(l);
+        let program = p.parse_program();
+        assert!(p.errors().is_empty(), "parser errors: {:?}", p.errors());
+        let mut env = environment::Environment::new();
+        evaluator::eval(&program, &mut env)
+    }
+}
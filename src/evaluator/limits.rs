@@ -0,0 +1,237 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::object::*;
+
+/// How much Rust stack `Limits::default()` lets evaluation use. It leaves
+/// room to spare on a main thread's usual 8 MiB stack; a host running
+/// scripts on a thread with a smaller stack should lower it.
+pub const DEFAULT_MAX_STACK: usize = 4 * 1024 * 1024;
+
+/// How often, in steps, the clock is read to enforce `Limits::timeout`.
+const CLOCK_INTERVAL: u64 = 1024;
+
+/// Bounds on what one evaluation may use. `None` means unbounded.
+///
+/// Memory is counted approximately, as the bytes of the strings, arrays and
+/// hashes the program creates; values freed along the way still count.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limits {
+    /// Statements, loop iterations and function calls. The VM counts only
+    /// loop iterations and calls.
+    pub max_steps: Option<u64>,
+    pub timeout: Option<Duration>,
+    /// How deeply function calls may nest.
+    pub max_depth: Option<usize>,
+    /// How many bytes of the Rust stack evaluation may use. The evaluator
+    /// recurses as the program does, so this is what keeps runaway
+    /// recursion from overflowing the stack.
+    pub max_stack: Option<usize>,
+    pub max_memory: Option<usize>,
+    /// The longest string, in bytes.
+    pub max_string_len: Option<usize>,
+    /// The most elements of an array or entries of a hash.
+    pub max_array_len: Option<usize>,
+}
+
+impl Default for Limits {
+    /// No limits but `DEFAULT_MAX_STACK`.
+    fn default() -> Limits {
+        Limits {
+            max_steps: None,
+            timeout: None,
+            max_depth: None,
+            max_stack: Some(DEFAULT_MAX_STACK),
+            max_memory: None,
+            max_string_len: None,
+            max_array_len: None,
+        }
+    }
+}
+
+/// The limit an evaluation ran into, with its configured value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    Steps(u64),
+    Time(Duration),
+    Depth(usize),
+    Stack(usize),
+    Memory(usize),
+    StringLen(usize),
+    ArrayLen(usize),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Limit::Steps(max) => write!(f, "step limit of {} exceeded", max),
+            Limit::Time(max) => write!(f, "time limit of {:?} exceeded", max),
+            Limit::Depth(max) => write!(f, "call depth limit of {} exceeded", max),
+            Limit::Stack(max) => write!(f, "stack limit of {} bytes exceeded; is the recursion unbounded?", max),
+            Limit::Memory(max) => write!(f, "memory limit of {} bytes exceeded", max),
+            Limit::StringLen(max) => write!(f, "string length limit of {} bytes exceeded", max),
+            Limit::ArrayLen(max) => write!(f, "array length limit of {} exceeded", max),
+        }
+    }
+}
+
+/// The usage of the evaluation running on this thread.
+struct Meter {
+    limits: Limits,
+    steps: u64,
+    deadline: Option<Instant>,
+    depth: usize,
+    stack_base: usize,
+    memory: usize,
+    /// The first limit exceeded. Once set, every check fails, so the
+    /// evaluation stops even if a native function ignores the error.
+    exceeded: Option<Limit>,
+}
+
+thread_local! {
+    static METER: RefCell<Option<Meter>> = const { RefCell::new(None) };
+}
+
+/// Runs `f`, which evaluates a program, under `limits`, returning its
+/// result and the limit it ran into, if any. When a limit is exceeded, the
+/// evaluator fails with an error value whose message is the limit's.
+pub fn with_limits<T>(limits: &Limits, f: impl FnOnce() -> T) -> (T, Option<Limit>) {
+    let meter = Meter {
+        limits: limits.clone(),
+        steps: 0,
+        deadline: limits.timeout.map(|timeout| Instant::now() + timeout),
+        depth: 0,
+        stack_base: stack_address(),
+        memory: 0,
+        exceeded: None,
+    };
+    let outer = METER.with(|m| m.borrow_mut().replace(meter));
+    let result = f();
+    let meter = METER.with(|m| std::mem::replace(&mut *m.borrow_mut(), outer));
+    (result, meter.and_then(|meter| meter.exceeded))
+}
+
+/// Checks `check` against the current evaluation's meter, if there is one,
+/// recording and reporting the limit it finds exceeded.
+fn with_meter(check: impl FnOnce(&mut Meter) -> Option<Limit>) -> Result<(), object::Object> {
+    METER.with(|m| {
+        let mut m = m.borrow_mut();
+        let meter = match m.as_mut() {
+            Some(meter) => meter,
+            None => return Ok(()),
+        };
+        if meter.exceeded.is_none() {
+            meter.exceeded = check(meter);
+        }
+        match meter.exceeded {
+            Some(limit) => Err(object::Object::Error(limit.to_string())),
+            None => Ok(()),
+        }
+    })
+}
+
+/// Counts one step of evaluation.
+pub fn step() -> Result<(), object::Object> {
+    with_meter(|meter| {
+        meter.steps += 1;
+        if let Some(max) = meter.limits.max_steps.filter(|&max| meter.steps > max) {
+            return Some(Limit::Steps(max));
+        }
+        match (meter.deadline, meter.limits.timeout) {
+            (Some(deadline), Some(timeout)) if meter.steps % CLOCK_INTERVAL == 0 && Instant::now() > deadline => {
+                Some(Limit::Time(timeout))
+            }
+            _ => None,
+        }
+    })
+}
+
+/// Marks a function call in progress until the returned guard is dropped.
+pub fn enter() -> Result<Depth, object::Object> {
+    let address = stack_address();
+    METER.with(|m| {
+        if let Some(meter) = m.borrow_mut().as_mut() {
+            meter.depth += 1;
+        }
+    });
+    let depth = Depth;
+    with_meter(|meter| {
+        if let Some(max) = meter.limits.max_depth.filter(|&max| meter.depth > max) {
+            return Some(Limit::Depth(max));
+        }
+        stack_exceeded(meter, address)
+    })?;
+    Ok(depth)
+}
+
+/// Checks the stack evaluation uses where it recurses other than by a call,
+/// as into nested expressions and blocks.
+pub fn check_stack() -> Result<(), object::Object> {
+    let address = stack_address();
+    with_meter(|meter| stack_exceeded(meter, address))
+}
+
+fn stack_exceeded(meter: &Meter, address: usize) -> Option<Limit> {
+    let used = meter.stack_base.abs_diff(address);
+    meter.limits.max_stack.filter(|&max| used > max).map(Limit::Stack)
+}
+
+/// Checks a call `depth` calls deep, for an engine that tracks the depth
+/// itself rather than recursing. `ceiling` is the deepest it can go,
+/// whatever the limits are, and is enforced even outside `with_limits`.
+pub fn check_depth(depth: usize, ceiling: usize) -> Result<(), object::Object> {
+    with_meter(|meter| {
+        let max = meter.limits.max_depth.map_or(ceiling, |max| max.min(ceiling));
+        (depth > max).then_some(Limit::Depth(max))
+    })?;
+    match depth > ceiling {
+        true => Err(object::Object::Error(Limit::Depth(ceiling).to_string())),
+        false => Ok(()),
+    }
+}
+
+/// A function call in progress, counted until dropped.
+pub struct Depth;
+
+impl Drop for Depth {
+    fn drop(&mut self) {
+        METER.with(|m| {
+            if let Some(meter) = m.borrow_mut().as_mut() {
+                meter.depth -= 1;
+            }
+        });
+    }
+}
+
+/// Checks the size of a value the program just created and counts the
+/// memory it takes.
+pub fn allocate(value: &object::Object) -> Result<(), object::Object> {
+    let size = std::mem::size_of::<object::Object>();
+    let (bytes, len, is_string) = match value {
+        object::Object::String(s) => (s.len(), s.len(), true),
+        object::Object::Array(elements) => (elements.len() * size, elements.len(), false),
+        object::Object::Hash(hash) => (hash.len() * 2 * size, hash.len(), false),
+        _ => return Ok(()),
+    };
+    with_meter(|meter| {
+        let limits = &meter.limits;
+        let too_long = match is_string {
+            true => limits.max_string_len.filter(|&max| len > max).map(Limit::StringLen),
+            false => limits.max_array_len.filter(|&max| len > max).map(Limit::ArrayLen),
+        };
+        if too_long.is_some() {
+            return too_long;
+        }
+        meter.memory = meter.memory.saturating_add(bytes);
+        meter.limits.max_memory.filter(|&max| meter.memory > max).map(Limit::Memory)
+    })
+}
+
+/// The address of a local of this function's frame, to measure how much
+/// stack is in use.
+#[inline(never)]
+fn stack_address() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
@@ -0,0 +1,286 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::ast::*;
+use crate::evaluator::*;
+use crate::lexer::*;
+use crate::object::*;
+use crate::parser::*;
+
+/// Finds, runs and caches the modules programs import.
+///
+/// A module is found relative to the file importing it or, failing that, in
+/// each directory of the search path in turn. Each is run once, however many
+/// times it is imported; later imports get the same exports.
+///
+/// The default loader has no search path and imports nothing, so that
+/// scripts can only read files when whoever runs them allows it.
+///
+/// Only the tree evaluator loads modules. The compiler rejects `import`, as
+/// bytecode holds a single file, so a program that imports modules cannot
+/// run on the VM or be saved as a `.rkc` file.
+#[derive(Debug, Default)]
+pub struct Loader {
+    /// `None` if imports are not allowed.
+    search_path: Option<Vec<PathBuf>>,
+    /// The exports of each module run so far, by canonical path.
+    cache: HashMap<PathBuf, Rc<object::Hash>>,
+    /// The files being run, outermost first.
+    running: Vec<File>,
+}
+
+#[derive(Debug)]
+struct File {
+    /// The canonical path, to tell whether two imports are the same file.
+    canonical: PathBuf,
+    /// The path as it was found, to show in messages.
+    path: PathBuf,
+}
+
+/// The environment variable `Loader::from_env` reads the search path from,
+/// a list of directories in the platform's `PATH` format.
+pub const SEARCH_PATH_VAR: &str = "RUNKEY_PATH";
+
+impl Loader {
+    pub fn new(search_path: Vec<PathBuf>) -> Loader {
+        Loader {
+            search_path: Some(search_path),
+            ..Loader::default()
+        }
+    }
+
+    /// A loader searching the directories listed in `SEARCH_PATH_VAR`.
+    pub fn from_env() -> Loader {
+        let search_path = std::env::var_os(SEARCH_PATH_VAR).map(|paths| std::env::split_paths(&paths).collect());
+        Loader::new(search_path.unwrap_or_default())
+    }
+
+    /// This loader, also searching the working directory ahead of its search
+    /// path, as the command line does for source that is not from a file.
+    pub fn with_working_directory(mut self) -> Loader {
+        self.search_path.get_or_insert_with(Vec::new).insert(0, PathBuf::new());
+        self
+    }
+
+    /// Finds the module `path` names, relative to the innermost file being
+    /// run, if any, and then in the search path.
+    fn resolve(&self, path: &str) -> Result<File, object::Object> {
+        let Some(search_path) = &self.search_path else {
+            return Err(unavailable());
+        };
+        let base = self.running.last().map(|file| file.path.parent().unwrap_or(Path::new("")));
+        let candidates = base.into_iter().chain(search_path.iter().map(PathBuf::as_path));
+        for dir in candidates {
+            let candidate = dir.join(path);
+            if let Ok(canonical) = candidate.canonicalize() {
+                if canonical.is_file() {
+                    return Ok(File { canonical, path: candidate });
+                }
+            }
+        }
+        Err(new_error(format!("module not found: \"{}\"", path)))
+    }
+
+    /// The error for importing `file` while it is already being run, listing
+    /// the imports that lead back to it.
+    fn cycle_error(&self, file: &File) -> Option<object::Object> {
+        let start = self.running.iter().position(|running| running.canonical == file.canonical)?;
+        let mut cycle: Vec<String> = self.running[start..].iter().map(|running| running.path.display().to_string()).collect();
+        cycle.push(file.path.display().to_string());
+        Some(new_error(format!("circular import: {}", cycle.join(" -> "))))
+    }
+}
+
+thread_local! {
+    static LOADER: RefCell<Option<Loader>> = const { RefCell::new(None) };
+}
+
+/// Runs `f`, which evaluates the program in `file`, with `loader` resolving
+/// its imports. `file` is `None` for source that is not from a file, whose
+/// imports are found in the search path alone.
+pub fn with_loader<T>(loader: &mut Loader, file: Option<&Path>, f: impl FnOnce() -> T) -> T {
+    let mut installed = std::mem::take(loader);
+    if let Some(path) = file {
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        installed.running.push(File { canonical, path: path.to_path_buf() });
+    }
+    let outer = LOADER.with(|l| l.borrow_mut().replace(installed));
+    let result = f();
+    let installed = LOADER.with(|l| std::mem::replace(&mut *l.borrow_mut(), outer));
+    *loader = installed.expect("the loader is still installed");
+    if file.is_some() {
+        loader.running.pop();
+    }
+    result
+}
+
+/// Runs the module `path` names, unless it has already been, and returns
+/// its exports.
+pub fn import(path: &str) -> Result<Rc<object::Hash>, object::Object> {
+    let file = with_loader_ref(|loader| {
+        let file = loader.resolve(path)?;
+        if let Some(err) = loader.cycle_error(&file) {
+            return Err(err);
+        }
+        Ok(file)
+    })?;
+    if let Some(exports) = with_loader_ref(|loader| Ok(loader.cache.get(&file.canonical).cloned()))? {
+        return Ok(exports);
+    }
+
+    let name = file.path.display().to_string();
+    let source = std::fs::read_to_string(&file.path)
+        .map_err(|err| new_error(format!("cannot read module {}: {}", name, err)))?;
+    let mut p = parser::Parser::new(lexer::Lexer::new(source));
+    let program = p.parse_program();
+    if let Some(err) = p.errors().first() {
+        return Err(new_error(format!("{}:{}: {}", name, err.span(), err)));
+    }
+
+    let canonical = file.canonical.clone();
+    LOADER.with(|l| l.borrow_mut().as_mut().expect("checked above").running.push(file));
+    let mut env = environment::Environment::new();
+    let result = evaluator::eval(&program, &mut env);
+    LOADER.with(|l| l.borrow_mut().as_mut().expect("checked above").running.pop());
+    if let object::Object::Error(message) = result {
+        return Err(new_error(format!("in module {}: {}", name, message)));
+    }
+
+    let exports = Rc::new(exports(&program, &env));
+    LOADER.with(|l| l.borrow_mut().as_mut().expect("checked above").cache.insert(canonical, exports.clone()));
+    Ok(exports)
+}
+
+/// Calls `f` with the installed loader, without holding on to it, so that
+/// running a module can import others.
+fn with_loader_ref<T>(f: impl FnOnce(&Loader) -> Result<T, object::Object>) -> Result<T, object::Object> {
+    LOADER.with(|l| match l.borrow().as_ref() {
+        Some(loader) => f(loader),
+        None => Err(unavailable()),
+    })
+}
+
+fn unavailable() -> object::Object {
+    new_error("import is not available here".to_string())
+}
+
+/// The values of the bindings a module's `export` statements made, as they
+/// are after it has run.
+fn exports(program: &ast::Program, env: &environment::Environment) -> object::Hash {
+    let mut exports = object::Hash::new();
+    for statement in &program.statements {
+        if let ast::Statement::ExportStatement(stmt, _) = statement {
+            let key = object::Object::String(stmt.name.value.clone());
+            let value = env.get(&stmt.name.value).unwrap_or(object::Object::Null);
+            exports.insert(key.hash_key().expect("strings are hashable"), key, value);
+        }
+    }
+    exports
+}
+
+fn new_error(message: String) -> object::Object {
+    object::Object::Error(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use crate::evaluator::*;
+    use crate::lexer::*;
+    use crate::object::*;
+    use crate::parser::*;
+
+    /// A fresh directory holding `files`, each a relative path and its source.
+    fn write_files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("runkey-modules-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        for (path, source) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+        dir
+    }
+
+    fn run(loader: &mut modules::Loader, file: &Path) -> object::Object {
+        let source = fs::read_to_string(file).unwrap();
+        let mut p = parser::Parser::new(lexer::Lexer::new(source));
+        let program = p.parse_program();
+        assert!(p.errors().is_empty(), "file: {}, errors: {:?}", file.display(), p.errors());
+        modules::with_loader(loader, Some(file), || evaluator::eval(&program, &mut environment::Environment::new()))
+    }
+
+    #[test]
+    fn imports() {
+        let dir = write_files("imports", &[
+            ("lib/math.rk", "export let double = fn(x) { x * 2 }; export var count = 1; count += 1; let hidden = 0;"),
+            ("lib/greet.rk", "import { double } from \"math.rk\"; export let twice = fn(s) { s + s };"),
+            ("shared/strings.rk", "export let shout = fn(s) { s + \"!\" };"),
+            ("main.rk", "
+                import \"lib/math.rk\" as math;
+                import { double, count as n } from \"lib/math.rk\";
+                import { twice } from \"lib/greet.rk\";
+                import { shout } from \"strings.rk\";
+                [math[\"double\"](1), double(2), n, math[\"hidden\"], twice(shout(\"a\"))]
+            "),
+            ("missing.rk", "import { hidden } from \"lib/math.rk\";"),
+            ("unknown.rk", "import \"nowhere.rk\" as x;"),
+            ("failing.rk", "import \"lib/bad.rk\" as bad;"),
+            ("lib/bad.rk", "export let x = 1 / 0;"),
+        ]);
+        let mut loader = modules::Loader::new(vec![dir.join("shared")]);
+        let tests = [
+            ("main.rk", "[2, 4, 2, null, \"a!a!\"]".to_string()),
+            ("missing.rk", "ERROR: module \"lib/math.rk\" has no export named `hidden`".to_string()),
+            ("unknown.rk", "ERROR: module not found: \"nowhere.rk\"".to_string()),
+            ("failing.rk", format!("ERROR: in module {}: division by zero", dir.join("lib/bad.rk").display())),
+        ];
+        for (file, expected) in tests {
+            assert_eq!(run(&mut loader, &dir.join(file)).to_string(), expected, "file: {}", file);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn caching() {
+        let dir = write_files("caching", &[
+            ("a.rk", "export let x = 1;"),
+            ("lib/b.rk", "import { x } from \"../a.rk\"; export let y = x + 1;"),
+            ("main.rk", "import { x } from \"a.rk\"; import { y } from \"lib/b.rk\"; import \"./lib/../a.rk\" as a; x + y"),
+        ]);
+        let mut loader = modules::Loader::new(Vec::new());
+        assert_eq!(run(&mut loader, &dir.join("main.rk")), object::Object::Integer(3));
+        assert_eq!(loader.cache.len(), 2);
+        assert!(loader.running.is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn circular_imports() {
+        let dir = write_files("circular", &[
+            ("a.rk", "import { y } from \"lib/b.rk\"; export let x = 1;"),
+            ("lib/b.rk", "import { z } from \"c.rk\"; export let y = 2;"),
+            ("lib/c.rk", "import { x } from \"../a.rk\"; export let z = 3;"),
+            ("self.rk", "import \"self.rk\" as me;"),
+        ]);
+        let mut loader = modules::Loader::new(Vec::new());
+        let (a, b, c) = (dir.join("a.rk"), dir.join("lib/b.rk"), dir.join("lib/c.rk"));
+        let cycle = format!("{} -> {} -> {} -> {}", a.display(), b.display(), c.display(), dir.join("lib/../a.rk").display());
+        let expected = format!(
+            "ERROR: in module {}: in module {}: circular import: {}",
+            b.display(),
+            c.display(),
+            cycle,
+        );
+        assert_eq!(run(&mut loader, &a).to_string(), expected);
+
+        let me = dir.join("self.rk");
+        let expected = format!("ERROR: circular import: {} -> {}", me.display(), me.display());
+        assert_eq!(run(&mut loader, &me).to_string(), expected);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
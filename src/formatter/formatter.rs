@@ -0,0 +1,741 @@
+use crate::ast::*;
+use crate::lexer::*;
+use crate::parser::*;
+use crate::token::*;
+
+/// The line width `format` wraps call argument lists at unless told
+/// otherwise.
+pub const DEFAULT_WIDTH: usize = 100;
+
+const INDENT: &str = "    ";
+
+/// Formats Runkey source in the canonical style: one statement per line,
+/// four-space indentation, blocks broken over lines, single spaces around
+/// binary operators and only the parentheses the grouping needs.
+///
+/// Comments are kept, as are single blank lines between statements. A call
+/// whose arguments would run past `width` columns gets one argument per line.
+/// Formatting already formatted source leaves it unchanged.
+///
+/// Source with syntax errors is not formatted; the errors are returned.
+pub fn format(source: &str, width: usize) -> Result<String, Vec<error::ParseError>> {
+    let mut p = parser::Parser::new(lexer::Lexer::new(source.to_string()));
+    let program = p.parse_program();
+    if !p.errors().is_empty() {
+        return Err(p.errors().to_vec());
+    }
+    let mut line_starts = vec![0];
+    line_starts.extend(source.match_indices('\n').map(|(i, _)| i + 1));
+    let mut f = Formatter {
+        source,
+        comments: p.l.comments(),
+        next_comment: 0,
+        line_starts,
+        width,
+        wrap: true,
+    };
+    Ok(f.program(&program))
+}
+
+struct Formatter<'a> {
+    source: &'a str,
+    comments: &'a [token::Span],
+    /// The first comment not yet written out.
+    next_comment: usize,
+    /// The byte offset each source line starts at.
+    line_starts: Vec<usize>,
+    width: usize,
+    /// Whether long argument lists may be broken over lines. Off while
+    /// measuring how wide an expression is on one line.
+    wrap: bool,
+}
+
+impl Formatter<'_> {
+    fn program(&mut self, program: &ast::Program) -> String {
+        let mut out = String::new();
+        let mut last_line = None;
+        if self.source.starts_with("#!") {
+            out.push_str(self.source.lines().next().unwrap_or_default().trim_end());
+            out.push('\n');
+            last_line = Some(1);
+        }
+        self.statements(&program.statements, 0, self.source.len(), &mut out, last_line);
+        out
+    }
+
+    /// Writes `statements`, one per line at `depth`, along with the comments
+    /// before `end` that have not been written yet. `last_line` is the
+    /// source line of whatever precedes the first statement, if anything.
+    fn statements(
+        &mut self,
+        statements: &[ast::Statement],
+        depth: usize,
+        end: usize,
+        out: &mut String,
+        mut last_line: Option<usize>,
+    ) {
+        for (i, statement) in statements.iter().enumerate() {
+            let span = statement.span();
+            let next = statements.get(i + 1);
+            self.comments_before(span.start, depth, out, &mut last_line);
+            let mut text = self.statement(statement, depth, next);
+            // Comments inside the statement that nothing inside it took, such
+            // as one before its `;`, follow it.
+            let (behind, ends_line) = self.comments_behind(span.end);
+            text.push_str(&behind);
+
+            blank_line(out, last_line, span.line);
+            out.push_str(&INDENT.repeat(depth));
+            out.push_str(&text);
+            last_line = Some(self.line(span.end - 1));
+
+            let limit = next.map_or(end, |next| next.span().start);
+            if let Some(comment) = self.comments.get(self.next_comment).filter(|_| !ends_line) {
+                if Some(comment.line) == last_line && comment.start < limit {
+                    out.push(' ');
+                    out.push_str(self.comment_text(*comment));
+                    last_line = Some(self.line(comment.end - 1));
+                    self.next_comment += 1;
+                }
+            }
+            out.push('\n');
+        }
+        self.comments_before(end, depth, out, &mut last_line);
+    }
+
+    /// Writes the unwritten comments that start before `offset`, each on its
+    /// own line.
+    fn comments_before(&mut self, offset: usize, depth: usize, out: &mut String, last_line: &mut Option<usize>) {
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            if comment.start >= offset {
+                break;
+            }
+            blank_line(out, *last_line, comment.line);
+            out.push_str(&INDENT.repeat(depth));
+            out.push_str(self.comment_text(*comment));
+            out.push('\n');
+            *last_line = Some(self.line(comment.end - 1));
+            self.next_comment += 1;
+        }
+    }
+
+    /// Takes the unwritten comments that start before `offset`, to write
+    /// inside a line ahead of the token at `offset`. Each is followed by a
+    /// space or, if it is a `//` comment, by a line break and `indent`
+    /// columns of indentation.
+    fn comments_ahead(&mut self, offset: usize, indent: usize) -> String {
+        let mut out = String::new();
+        for comment in self.take_comments(offset) {
+            out.push_str(self.comment_text(comment));
+            if self.is_line_comment(comment) {
+                out.push('\n');
+                out.push_str(&" ".repeat(indent));
+            } else {
+                out.push(' ');
+            }
+        }
+        out
+    }
+
+    /// Takes the unwritten comments that start before `offset`, to write
+    /// just after the token they follow, each preceded by a space. Also
+    /// returns whether the last is a `//` comment, which ends the line.
+    fn comments_behind(&mut self, offset: usize) -> (String, bool) {
+        let mut out = String::new();
+        let mut ends_line = false;
+        for comment in self.take_comments(offset) {
+            if ends_line {
+                out.push('\n');
+            } else {
+                out.push(' ');
+            }
+            out.push_str(self.comment_text(comment));
+            ends_line = self.is_line_comment(comment);
+        }
+        (out, ends_line)
+    }
+
+    fn take_comments(&mut self, offset: usize) -> Vec<token::Span> {
+        let start = self.next_comment;
+        while self.comments.get(self.next_comment).is_some_and(|comment| comment.start < offset) {
+            self.next_comment += 1;
+        }
+        self.comments[start..self.next_comment].to_vec()
+    }
+
+    fn comment_text(&self, span: token::Span) -> &str {
+        self.source[span.start..span.end].trim_end()
+    }
+
+    fn is_line_comment(&self, span: token::Span) -> bool {
+        self.source[span.start..].starts_with("//")
+    }
+
+    /// The offset of the first token at or after `offset`, past whitespace
+    /// and comments.
+    fn token_after(&self, mut offset: usize) -> usize {
+        loop {
+            offset += self.source[offset..].len() - self.source[offset..].trim_start().len();
+            match self.comments.iter().find(|comment| comment.start == offset) {
+                Some(comment) => offset = comment.end,
+                None => return offset,
+            }
+        }
+    }
+
+    /// The 1-based line of the byte at `offset`.
+    fn line(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|&start| start <= offset)
+    }
+
+    fn statement(&mut self, statement: &ast::Statement, depth: usize, next: Option<&ast::Statement>) -> String {
+        let column = depth * INDENT.len();
+        match statement {
+            ast::Statement::LetStatement(stmt) => self.let_statement("", stmt, depth),
+            ast::Statement::ReturnStatement(value, _) => {
+                format!("return {};", self.expression(value, depth, column + "return ".len()))
+            }
+            ast::Statement::ExpressionStatement(expr, _) => {
+                let text = self.expression(expr, depth, column);
+                // An `if` reads as a statement without a `;`, but one is still
+                // needed when the next statement would otherwise continue it,
+                // as `if (a) { f } (g)` is a call.
+                let continued = match next {
+                    Some(ast::Statement::ExpressionStatement(next, _)) => starts_with_operator(next),
+                    _ => false,
+                };
+                match expr {
+                    ast::Expression::IfExpression(..) if !continued => text,
+                    _ => text + ";",
+                }
+            }
+            ast::Statement::WhileStatement(label, condition, body, _) => {
+                let head = format!("{}while (", label_prefix(label));
+                let condition_end = self.token_after(condition.span().end);
+                let condition = self.expression(condition, depth, column + head.len());
+                let condition = self.close(condition, condition_end, depth);
+                format!("{}{}) {}", head, condition, self.block(body, depth))
+            }
+            ast::Statement::ForStatement(label, variable, iterable, body, _) => {
+                let head = format!("{}for {} in ", label_prefix(label), variable);
+                let iterable = self.expression(iterable, depth, column + head.len());
+                format!("{}{} {}", head, iterable, self.block(body, depth))
+            }
+            ast::Statement::BreakStatement(label, _) => format!("break{};", label_suffix(label)),
+            ast::Statement::ContinueStatement(label, _) => format!("continue{};", label_suffix(label)),
+            ast::Statement::ImportStatement(path, import, _) => {
+                let path = format!("\"{}\"", token::escape(path));
+                match import {
+                    ast::Import::Module(name) => format!("import {} as {};", path, name),
+                    ast::Import::Names(names) => {
+                        let names: Vec<String> = names
+                            .iter()
+                            .map(|(name, binding)| match name.value == binding.value {
+                                true => name.value.clone(),
+                                false => format!("{} as {}", name, binding),
+                            })
+                            .collect();
+                        format!("import {{ {} }} from {};", names.join(", "), path)
+                    }
+                }
+            }
+            ast::Statement::ExportStatement(stmt, _) => self.let_statement("export ", stmt, depth),
+        }
+    }
+
+    fn let_statement(&mut self, prefix: &str, stmt: &ast::LetStatement, depth: usize) -> String {
+        let keyword = if self.source[stmt.span.start..].starts_with("var") {
+            "var"
+        } else if stmt.mutable {
+            "let mut"
+        } else {
+            "let"
+        };
+        let head = format!("{}{} {} = ", prefix, keyword, stmt.name);
+        let value = self.expression(&stmt.value, depth, depth * INDENT.len() + head.len());
+        format!("{}{};", head, value)
+    }
+
+    fn block(&mut self, block: &ast::BlockStatement, depth: usize) -> String {
+        let mut inner = String::new();
+        self.statements(&block.statements, depth + 1, block.span.end, &mut inner, None);
+        if inner.is_empty() {
+            "{}".to_string()
+        } else {
+            format!("{{\n{}{}}}", inner, INDENT.repeat(depth))
+        }
+    }
+
+    /// Formats `expr` where the parser reads a whole expression, such as a
+    /// statement or an argument. `column` is where it starts on its line.
+    ///
+    /// Comments before the expression go ahead of it, so that comments
+    /// inside a statement stay where they are among its tokens.
+    fn expression(&mut self, expr: &ast::Expression, depth: usize, column: usize) -> String {
+        let indent = match column == depth * INDENT.len() {
+            true => column,
+            false => (depth + 1) * INDENT.len(),
+        };
+        let ahead = self.comments_ahead(expr.span().start, indent);
+        let text = self.bare_expression(expr, depth, end_column(column, &ahead));
+        ahead + &text
+    }
+
+    /// Writes the comments before the closing bracket at `offset` after
+    /// `text`, the last thing inside the brackets, moving the bracket to a
+    /// line of its own after a `//` comment.
+    fn close(&mut self, text: String, offset: usize, depth: usize) -> String {
+        match self.comments_behind(offset) {
+            (behind, true) => format!("{}{}\n{}", text, behind, INDENT.repeat(depth)),
+            (behind, false) => text + &behind,
+        }
+    }
+
+    fn bare_expression(&mut self, expr: &ast::Expression, depth: usize, column: usize) -> String {
+        match expr {
+            ast::Expression::Identifier(ident) => ident.value.clone(),
+            // Literals are written as in the source, so `0xFF` or `"\u{41}"`
+            // keep their spelling.
+            ast::Expression::IntegerLiteral(_, span)
+            | ast::Expression::FloatLiteral(_, span)
+            | ast::Expression::StringLiteral(_, span) => self.source[span.start..span.end].to_string(),
+            ast::Expression::Boolean(value, _) => value.to_string(),
+            ast::Expression::ArrayLiteral(elements, span) => {
+                let elements = self.list(elements, depth, column + 1);
+                format!("[{}]", self.close(elements, span.end - 1, depth))
+            }
+            ast::Expression::HashLiteral(pairs, span) => {
+                let mut out = "{".to_string();
+                for (i, (key, value)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    let key = self.expression(key, depth, end_column(column, &out));
+                    out.push_str(&key);
+                    out.push_str(": ");
+                    let value = self.expression(value, depth, end_column(column, &out));
+                    out.push_str(&value);
+                }
+                self.close(out, span.end - 1, depth) + "}"
+            }
+            ast::Expression::PrefixExpression(op, right, _) => {
+                let right = self.operand(right, parser::Precedence::Prefix, depth, column + 1);
+                format!("{}{}", op, right)
+            }
+            ast::Expression::InfixExpression(left, op, right, _) => {
+                let left = self.left_operand(left, parser::Precedence::of(op), depth, column);
+                let head = match op {
+                    token::TokenKind::DotDot => format!("{}{}", left, op),
+                    _ => format!("{} {} ", left, op),
+                };
+                let right = self.operand(right, right_precedence(op), depth, end_column(column, &head));
+                head + &right
+            }
+            ast::Expression::AssignExpression(target, op, value, _) => {
+                let head = format!("{} {} ", self.expression(target, depth, column), op);
+                let value = self.expression(value, depth, end_column(column, &head));
+                head + &value
+            }
+            ast::Expression::IfExpression(condition, consequence, alternative, _) => {
+                let condition_end = self.token_after(condition.span().end);
+                let condition = self.expression(condition, depth, column + "if (".len());
+                let condition = self.close(condition, condition_end, depth);
+                let mut out = format!("if ({}) {}", condition, self.block(consequence, depth));
+                if let Some(alternative) = alternative {
+                    // A comment after the `}` stays there rather than moving
+                    // into the `else` block.
+                    let (behind, ends_line) = self.comments_behind(self.token_after(consequence.span.end));
+                    out.push_str(&behind);
+                    match ends_line {
+                        true => out.push_str(&format!("\n{}else ", INDENT.repeat(depth))),
+                        false => out.push_str(" else "),
+                    }
+                    out.push_str(&self.comments_ahead(alternative.span.start, depth * INDENT.len()));
+                    out.push_str(&self.block(alternative, depth));
+                }
+                out
+            }
+            ast::Expression::FunctionLiteral(params, body, span) => {
+                // Comments among the parameters stay among them, placed as
+                // `list` places comments among items.
+                let mut out = String::new();
+                let mut end = self.token_after(span.start + "fn".len()) + 1;
+                for (i, param) in params.iter().enumerate() {
+                    if i > 0 {
+                        match self.comments_behind(self.token_after(end)) {
+                            (behind, true) => out.push_str(&format!(",{}
+{}", behind, INDENT.repeat(depth + 1))),
+                            (behind, false) => out.push_str(&format!("{}, ", behind)),
+                        }
+                    }
+                    out.push_str(&self.comments_ahead(param.span.start, (depth + 1) * INDENT.len()));
+                    out.push_str(&param.value);
+                    end = param.span.end;
+                }
+                let closed = self.close(out, self.token_after(end), depth);
+                let params = match params.is_empty() {
+                    true => closed.trim_start(),
+                    false => &closed,
+                };
+                format!("fn({}) {}", params, self.block(body, depth))
+            }
+            ast::Expression::CallExpression(function, args, span) => {
+                let function = self.left_operand(function, parser::Precedence::Call, depth, column);
+                let column = end_column(column, &function) + 1;
+                format!("{}({})", function, self.arguments(args, span.end - 1, depth, column))
+            }
+            ast::Expression::IndexExpression(left, index, span) => {
+                let left = self.left_operand(left, parser::Precedence::Index, depth, column);
+                let index = self.expression(index, depth, end_column(column, &left) + 1);
+                format!("{}[{}]", left, self.close(index, span.end - 1, depth))
+            }
+            ast::Expression::SliceExpression(left, start, end, span) => {
+                let mut out = self.left_operand(left, parser::Precedence::Index, depth, column) + "[";
+                if let Some(start) = start {
+                    let start = self.expression(start, depth, end_column(column, &out));
+                    out.push_str(&start);
+                }
+                out.push(':');
+                if let Some(end) = end {
+                    let end = self.expression(end, depth, end_column(column, &out));
+                    out.push_str(&end);
+                }
+                self.close(out, span.end - 1, depth) + "]"
+            }
+        }
+    }
+
+    /// Formats an operand the parser reads at `precedence`, such as the
+    /// right-hand side of an operator, adding parentheses if it would
+    /// otherwise group differently.
+    fn operand(
+        &mut self,
+        expr: &ast::Expression,
+        precedence: parser::Precedence,
+        depth: usize,
+        column: usize,
+    ) -> String {
+        match operator_precedence(expr) {
+            Some(own) if own <= precedence => self.parenthesized(expr, depth, column),
+            _ => self.expression(expr, depth, column),
+        }
+    }
+
+    /// Formats the operand to the left of an operator of `precedence`, adding
+    /// parentheses if the operator would otherwise apply to only part of it.
+    fn left_operand(
+        &mut self,
+        expr: &ast::Expression,
+        precedence: parser::Precedence,
+        depth: usize,
+        column: usize,
+    ) -> String {
+        if needs_parentheses_on_left(expr, precedence) {
+            self.parenthesized(expr, depth, column)
+        } else {
+            self.expression(expr, depth, column)
+        }
+    }
+
+    fn parenthesized(&mut self, expr: &ast::Expression, depth: usize, column: usize) -> String {
+        format!("({})", self.expression(expr, depth, column + 1))
+    }
+
+    fn list(&mut self, exprs: &[ast::Expression], depth: usize, column: usize) -> String {
+        let mut out = String::new();
+        for (i, expr) in exprs.iter().enumerate() {
+            let item = self.expression(expr, depth, end_column(column, &out));
+            out.push_str(&item);
+            if i + 1 == exprs.len() {
+                break;
+            }
+            // A comment before the comma stays behind the item, unless it
+            // ends the line, which moves it past the comma.
+            match self.comments_behind(self.token_after(expr.span().end)) {
+                (behind, true) => out.push_str(&format!(",{}\n{}", behind, INDENT.repeat(depth + 1))),
+                (behind, false) => out.push_str(&format!("{}, ", behind)),
+            }
+        }
+        out
+    }
+
+    /// Formats call arguments that start at `column` and end before the
+    /// `)` at `close`. They stay on the line of the call if its opening line
+    /// fits within the width; otherwise each goes on a line of its own.
+    fn arguments(&mut self, args: &[ast::Expression], close: usize, depth: usize, column: usize) -> String {
+        if !self.wrap || args.is_empty() {
+            let list = self.list(args, depth, column);
+            return self.close(list, close, depth);
+        }
+
+        let mark = self.next_comment;
+        self.wrap = false;
+        let flat = self.list(args, depth, column);
+        let flat = self.close(flat, close, depth);
+        self.wrap = true;
+        self.next_comment = mark;
+        let first_line = match flat.split_once('\n') {
+            Some((first, _)) => first.chars().count(),
+            None => flat.chars().count() + ")".len(),
+        };
+        if column + first_line <= self.width {
+            let list = self.list(args, depth, column);
+            return self.close(list, close, depth);
+        }
+
+        let inner = INDENT.repeat(depth + 1);
+        let mut out = String::new();
+        for (i, arg) in args.iter().enumerate() {
+            out.push('\n');
+            out.push_str(&inner);
+            out.push_str(&self.expression(arg, depth + 1, inner.len()));
+            // A comment after an argument on its line stays on that line.
+            let next = args.get(i + 1).map_or(close, |next| next.span().start);
+            if i + 1 < args.len() {
+                out.push(',');
+            }
+            let arg_line = self.line(arg.span().end - 1);
+            let same_line = self.comments[self.next_comment..]
+                .iter()
+                .take_while(|comment| comment.start < next && comment.line == arg_line)
+                .last()
+                .map_or(0, |comment| comment.end);
+            out.push_str(&self.comments_behind(same_line).0);
+        }
+        out.push_str(&self.comments_ahead_on_lines(close, depth + 1));
+        format!("{}\n{}", out, INDENT.repeat(depth))
+    }
+
+    /// Writes the unwritten comments before `offset` each on a line of its
+    /// own at `depth`, starting with a line break.
+    fn comments_ahead_on_lines(&mut self, offset: usize, depth: usize) -> String {
+        let mut out = String::new();
+        for comment in self.take_comments(offset) {
+            out.push('\n');
+            out.push_str(&INDENT.repeat(depth));
+            out.push_str(self.comment_text(comment));
+        }
+        out
+    }
+}
+
+fn blank_line(out: &mut String, last_line: Option<usize>, line: usize) {
+    if last_line.is_some_and(|last| line > last + 1) {
+        out.push('\n');
+    }
+}
+
+fn label_prefix(label: &Option<ast::Identifier>) -> String {
+    label.as_ref().map(|label| format!("{}: ", label)).unwrap_or_default()
+}
+
+fn label_suffix(label: &Option<ast::Identifier>) -> String {
+    label.as_ref().map(|label| format!(" {}", label)).unwrap_or_default()
+}
+
+/// The column just past `text` when it is written starting at `column`.
+fn end_column(column: usize, text: &str) -> usize {
+    match text.rsplit_once('\n') {
+        Some((_, last)) => last.chars().count(),
+        None => column + text.chars().count(),
+    }
+}
+
+/// The precedence the parser reads the right operand of `op` at.
+fn right_precedence(op: &token::TokenKind) -> parser::Precedence {
+    match op {
+        // `**` is right-associative.
+        token::TokenKind::Power => parser::Precedence::Prefix,
+        _ => parser::Precedence::of(op),
+    }
+}
+
+/// The precedence of the operator at the top of `expr`, for expressions the
+/// parser builds with an infix parse function.
+fn operator_precedence(expr: &ast::Expression) -> Option<parser::Precedence> {
+    match expr {
+        ast::Expression::InfixExpression(_, op, _, _) => Some(parser::Precedence::of(op)),
+        ast::Expression::AssignExpression(..) => Some(parser::Precedence::Assign),
+        ast::Expression::CallExpression(..) => Some(parser::Precedence::Call),
+        ast::Expression::IndexExpression(..) | ast::Expression::SliceExpression(..) => {
+            Some(parser::Precedence::Index)
+        }
+        _ => None,
+    }
+}
+
+/// Whether an operator of `precedence` written after `expr` would be taken
+/// into its last operand, as `*` is in `-a * b` if `a` were `a * b`.
+fn needs_parentheses_on_left(expr: &ast::Expression, precedence: parser::Precedence) -> bool {
+    let trailing = match expr {
+        ast::Expression::InfixExpression(_, op, _, _) => right_precedence(op),
+        ast::Expression::AssignExpression(..) => parser::Precedence::Lowest,
+        ast::Expression::PrefixExpression(..) => parser::Precedence::Prefix,
+        _ => return false,
+    };
+    trailing < precedence
+}
+
+/// Whether `expr` formatted at the start of a statement begins with a token
+/// that could also continue the expression before it: `(`, `[` or `-`.
+fn starts_with_operator(expr: &ast::Expression) -> bool {
+    match expr {
+        ast::Expression::PrefixExpression(token::TokenKind::Minus, _, _) | ast::Expression::ArrayLiteral(..) => true,
+        ast::Expression::InfixExpression(left, op, _, _) => {
+            needs_parentheses_on_left(left, parser::Precedence::of(op)) || starts_with_operator(left)
+        }
+        ast::Expression::CallExpression(left, _, _) => {
+            needs_parentheses_on_left(left, parser::Precedence::Call) || starts_with_operator(left)
+        }
+        ast::Expression::IndexExpression(left, _, _) | ast::Expression::SliceExpression(left, _, _, _) => {
+            needs_parentheses_on_left(left, parser::Precedence::Index) || starts_with_operator(left)
+        }
+        ast::Expression::AssignExpression(target, _, _, _) => starts_with_operator(target),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::formatter::*;
+
+    #[test]
+    fn format() {
+        let tests = [
+            ("let   x=1+2*3", "let x = 1 + 2 * 3;\n"),
+            ("var i = 0x1F;let mut j=1_000;", "var i = 0x1F;\nlet mut j = 1_000;\n"),
+            ("let s = \"a\\tb\\u{41}\";", "let s = \"a\\tb\\u{41}\";\n"),
+            ("(1 + 2) * 3; 1 + (2 * 3); (1 - 2) - 3; 1 - (2 - 3);", "(1 + 2) * 3;\n1 + 2 * 3;\n1 - 2 - 3;\n1 - (2 - 3);\n"),
+            ("2 ** 3 ** 2; (2 ** 3) ** 2; -2 ** 2; (-2) ** 2; 2 ** -1;", "2 ** 3 ** 2;\n(2 ** 3) ** 2;\n-2 ** 2;\n(-2) ** 2;\n2 ** -1;\n"),
+            ("-(a + b); !(a && b); (a + b)[0]; (-f)(1); a[1:][ : 2]; 0 .. n", "-(a + b);\n!(a && b);\n(a + b)[0];\n(-f)(1);\na[1:][:2];\n0..n;\n"),
+            ("var x = 0; x = 1 + (x += 2); (x = 1) == 1;", "var x = 0;\nx = 1 + (x += 2);\n(x = 1) == 1;\n"),
+            ("[1,2 , 3]; {\"a\" :1,\"b\":[ ]}; {}", "[1, 2, 3];\n{\"a\": 1, \"b\": []};\n{};\n"),
+            (
+                "let add = fn(a,b){a+b};",
+                "let add = fn(a, b) {\n    a + b;\n};\n",
+            ),
+            (
+                "if(x<y){x}else{y;}",
+                "if (x < y) {\n    x;\n} else {\n    y;\n}\n",
+            ),
+            (
+                "if (a) { 1 }; (a + b) * 2; if (b) {}; -1; if (c) {} f(1)",
+                "if (a) {\n    1;\n};\n(a + b) * 2;\nif (b) {};\n-1;\nif (c) {}\nf(1);\n",
+            ),
+            (
+                "outer: while (true) { for x in [1] { if (x) { break outer; } continue; } };",
+                "outer: while (true) {\n    for x in [1] {\n        if (x) {\n            break outer;\n        }\n        continue;\n    }\n}\n",
+            ),
+            ("fn() {}; let f = fn(x) { return x; }(1);", "fn() {};\nlet f = fn(x) {\n    return x;\n}(1);\n"),
+            (
+                "import \"lib/a.rk\" as a\nimport {x,y as z} from \"b.rk\"\nexport var n=x+z",
+                "import \"lib/a.rk\" as a;\nimport { x, y as z } from \"b.rk\";\nexport var n = x + z;\n",
+            ),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(formatter::format(input, formatter::DEFAULT_WIDTH).unwrap(), expected, "input: {}", input);
+            assert_eq!(formatter::format(expected, formatter::DEFAULT_WIDTH).unwrap(), expected, "input: {}", expected);
+        }
+    }
+
+    #[test]
+    fn comments_and_blank_lines() {
+        let input = "#!/usr/bin/env runkey
+// Leading comment.
+let a = 1;   // trailing
+
+
+/// Documented.
+///
+let f = fn(x) { // on the brace
+    /* block
+       comment */
+    x
+
+    // last
+};
+puts(a, /* inside */ f(1));
+// final
+";
+        let expected = "#!/usr/bin/env runkey
+// Leading comment.
+let a = 1; // trailing
+
+/// Documented.
+///
+let f = fn(x) {
+    // on the brace
+    /* block
+       comment */
+    x;
+
+    // last
+};
+puts(a, /* inside */ f(1));
+// final
+";
+        assert_eq!(formatter::format(input, formatter::DEFAULT_WIDTH).unwrap(), expected);
+        assert_eq!(formatter::format(expected, formatter::DEFAULT_WIDTH).unwrap(), expected);
+        assert_eq!(formatter::format("// only\n\n\n// comments", 80).unwrap(), "// only\n\n// comments\n");
+    }
+
+    #[test]
+    fn comments_inside_expressions() {
+        let tests = [
+            ("f(a, /* b */ b);", 80, "f(a, /* b */ b);\n"),
+            ("f(a /* a */, b);", 80, "f(a /* a */, b);\n"),
+            ("f(a, b /* b */);", 80, "f(a, b /* b */);\n"),
+            ("f(a, // a\n b);", 80, "f(a, // a\n    b);\n"),
+            ("f(a, b // b\n);", 80, "f(a, b // b\n);\n"),
+            ("[1, /* one */ 2][0 /* zero */];", 80, "[1, /* one */ 2][0 /* zero */];\n"),
+            ("let x = /* x */ 1 /* end */;", 80, "let x = /* x */ 1; /* end */\n"),
+            (
+                "let x = f(aaaa, // first\n bbbbb // second\n);",
+                20,
+                "let x = f(\n    aaaa, // first\n    bbbbb // second\n);\n",
+            ),
+            (
+                "let x = f(\n    aaaa,\n    // before b\n    bbbbb\n);",
+                20,
+                "let x = f(\n    aaaa,\n    // before b\n    bbbbb\n);\n",
+            ),
+            ("if (x) { a } // t\nelse { b }", 80, "if (x) {\n    a;\n} // t\nelse {\n    b;\n}\n"),
+            ("if (x) { a } /* t */ else { b }", 80, "if (x) {\n    a;\n} /* t */ else {\n    b;\n}\n"),
+            ("if (x) { a } else /* e */ { b }", 80, "if (x) {\n    a;\n} else /* e */ {\n    b;\n}\n"),
+            ("if (x /* x */) { a }", 80, "if (x /* x */) {\n    a;\n}\n"),
+            ("while (/* w */ x) { a }", 80, "while (/* w */ x) {\n    a;\n}\n"),
+            ("fn(x, /* mid */ y) { x + y }", 80, "fn(x, /* mid */ y) {\n    x + y;\n};\n"),
+            ("fn(x /* x */, y /* y */) { x }", 80, "fn(x /* x */, y /* y */) {\n    x;\n};\n"),
+            ("fn(/* none */) { 1 }", 80, "fn(/* none */) {\n    1;\n};\n"),
+            ("fn(x, // x\n y) { x }", 80, "fn(x, // x\n    y) {\n    x;\n};\n"),
+        ];
+        for (input, width, expected) in tests {
+            assert_eq!(formatter::format(input, width).unwrap(), expected, "input: {}", input);
+            assert_eq!(formatter::format(expected, width).unwrap(), expected, "input: {}", expected);
+        }
+    }
+
+    #[test]
+    fn wrap_long_arguments() {
+        let tests = [
+            ("f(aaaa, bbbb);", "f(aaaa, bbbb);\n"),
+            ("let x = f(aaaa, bbbbb);", "let x = f(\n    aaaa,\n    bbbbb\n);\n"),
+            ("f(g(aaaa, bbbb), cccc);", "f(\n    g(aaaa, bbbb),\n    cccc\n);\n"),
+            ("map(a, fn(x) { x });", "map(a, fn(x) {\n    x;\n});\n"),
+            (
+                "if (x) { f(aaaa, bbbb, cccc) }",
+                "if (x) {\n    f(\n        aaaa,\n        bbbb,\n        cccc\n    );\n}\n",
+            ),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(formatter::format(input, 20).unwrap(), expected, "input: {}", input);
+            assert_eq!(formatter::format(expected, 20).unwrap(), expected, "input: {}", expected);
+        }
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let errors = formatter::format("let = 1;", formatter::DEFAULT_WIDTH).unwrap_err();
+        assert_eq!(errors.len(), 1);
+    }
+}
@@ -1,11 +1,59 @@
 use crate::token::*;
 
+/// Operators and delimiters. Where one is a prefix of another the longer one
+/// is listed first, so the first match in the table is the longest (maximal
+/// munch): `<=` lexes as one token, never as `<` then `=`.
+const OPERATORS: &[(&str, token::TokenKind)] = &[
+    ("==", token::TokenKind::Eq),
+    ("!=", token::TokenKind::NotEq),
+    ("<=", token::TokenKind::LtEq),
+    (">=", token::TokenKind::GtEq),
+    ("&&", token::TokenKind::And),
+    ("||", token::TokenKind::Or),
+    ("**", token::TokenKind::Power),
+    ("..", token::TokenKind::DotDot),
+    ("+=", token::TokenKind::PlusAssign),
+    ("-=", token::TokenKind::MinusAssign),
+    ("*=", token::TokenKind::AsteriskAssign),
+    ("/=", token::TokenKind::SlashAssign),
+    ("=", token::TokenKind::Assign),
+    ("!", token::TokenKind::Bang),
+    ("<", token::TokenKind::Lt),
+    (">", token::TokenKind::Gt),
+    ("+", token::TokenKind::Plus),
+    ("-", token::TokenKind::Minus),
+    ("*", token::TokenKind::Asterisk),
+    ("/", token::TokenKind::Slash),
+    ("%", token::TokenKind::Percent),
+    (";", token::TokenKind::Semicolon),
+    (",", token::TokenKind::Comma),
+    (":", token::TokenKind::Colon),
+    ("(", token::TokenKind::LParen),
+    (")", token::TokenKind::RParen),
+    ("{", token::TokenKind::LBrace),
+    ("}", token::TokenKind::RBrace),
+    ("[", token::TokenKind::LBracket),
+    ("]", token::TokenKind::RBracket),
+];
+
+/// Converts source text into tokens.
+///
+/// The lexer walks the input with a byte-offset cursor: `position` is the byte
+/// offset of `ch` and `read_position` the offset of the character after it, so
+/// each step is constant time and slices always fall on `char` boundaries.
+/// `column` counts characters, not bytes.
 #[derive(Debug, Clone)]
 pub struct Lexer {
-	pub input: String,
-	pub position: usize,
-	pub read_position: usize,
-	pub ch: char,
+    pub input: String,
+    pub position: usize,
+    pub read_position: usize,
+    pub ch: char,
+    pub line: usize,
+    pub column: usize,
+    start: usize,
+    start_line: usize,
+    start_column: usize,
+    comments: Vec<token::Span>,
 }
 
 impl Lexer {
@@ -15,103 +63,132 @@ impl Lexer {
             position: 0,
             read_position: 0,
             ch: '\0',
+            line: 1,
+            column: 0,
+            start: 0,
+            start_line: 1,
+            start_column: 1,
+            comments: vec![],
         };
         l.read_char();
+        l.skip_shebang();
         l
     }
 
-	fn read_char(&mut self) {
-		if self.read_position >= self.input.len() {
-			self.ch = '\0';
-		} else {
-			self.ch = self.input.chars().nth(self.read_position).unwrap();
-		}
-		self.position = self.read_position;
-		self.read_position += 1;
-	}
+    /// Skips a `#!` interpreter line at the very start of the input, so
+    /// scripts can be made executable.
+    fn skip_shebang(&mut self) {
+        if self.ch == '#' && self.peek_char() == '!' {
+            while self.ch != '\n' && !self.is_at_end() {
+                self.read_char();
+            }
+        }
+    }
 
-    fn peek_char(&self) -> char {
-        if self.read_position >= self.input.len() {
-            '\0'
+    fn read_char(&mut self) {
+        if self.ch == '\n' {
+            self.line += 1;
+            self.column = 1;
         } else {
-            self.input.chars().nth(self.read_position).unwrap()
+            self.column += 1;
+        }
+        self.position = self.read_position;
+        match self.input[self.read_position..].chars().next() {
+            Some(ch) => {
+                self.ch = ch;
+                self.read_position += ch.len_utf8();
+            }
+            None => self.ch = '\0',
         }
     }
 
-	pub fn new_token (&self, token_type: String, ch: char) -> token::Token {
-		token::Token {
-			r#type: token_type,
-			literal: ch.to_string(),
-		}
-	}
+    fn peek_char(&self) -> char {
+        self.input[self.read_position..].chars().next().unwrap_or('\0')
+    }
 
-	pub fn next_token(&mut self) -> token::Token {
-        self.skip_whitespace();
-		let tok: token::Token = match self.ch {
-            '=' => {
-                if self.peek_char() == '=' {
-                    let ch = self.ch;
-                    self.read_char();
-                    let literal = format!("{}{}", ch, self.ch);
-                    token::Token {
-                        r#type: token::EQ.to_string(),
-                        literal,
-                    }
-                } else {
-                    self.new_token(token::ASSIGN.to_string(), self.ch)
-                }
-            },
-            ';' => self.new_token(token::SEMICOLON.to_string(), self.ch),
-            '(' => self.new_token(token::LPAREN.to_string(), self.ch),
-            ')' => self.new_token(token::RPAREN.to_string(), self.ch),
-            ',' => self.new_token(token::COMMA.to_string(), self.ch),
-            '+' => self.new_token(token::PLUS.to_string(), self.ch),
-            '-' => self.new_token(token::MINUS.to_string(), self.ch),
-            '!' => {
-                if self.peek_char() == '=' {
-                    let ch = self.ch;
-                    self.read_char();
-                    let literal = format!("{}{}", ch, self.ch);
-                    token::Token {
-                        r#type: token::NOT_EQ.to_string(),
-                        literal,
+    fn peek_second_char(&self) -> char {
+        self.input[self.read_position..].chars().nth(1).unwrap_or('\0')
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.position >= self.input.len()
+    }
+
+    /// Builds a token spanning from the start of the current lexeme up to the
+    /// lexer's position, so it must be called once the lexeme is consumed.
+    pub fn new_token(&self, kind: token::TokenKind) -> token::Token {
+        token::Token::new(kind, self.lexeme_span())
+    }
+
+    fn lexeme_span(&self) -> token::Span {
+        token::Span {
+            start: self.start,
+            end: self.position,
+            line: self.start_line,
+            column: self.start_column,
+        }
+    }
+
+    /// The spans of the comments read so far, doc comments included, in
+    /// source order.
+    pub fn comments(&self) -> &[token::Span] {
+        &self.comments
+    }
+
+    pub fn next_token(&mut self) -> token::Token {
+        loop {
+            self.skip_whitespace();
+            self.start = self.position;
+            self.start_line = self.line;
+            self.start_column = self.column;
+            match (self.ch, self.peek_char()) {
+                ('/', '/') if !self.at_doc_comment() => self.skip_line_comment(),
+                ('/', '*') => {
+                    if !self.skip_block_comment() {
+                        return self.new_token(token::TokenKind::Illegal(token::LexError::UnterminatedComment));
                     }
-                } else {
-                    self.new_token(token::BANG.to_string(), self.ch)
                 }
+                _ => break,
             }
-            '*' => self.new_token(token::ASTERISK.to_string(), self.ch),
-            '/' => self.new_token(token::SLASH.to_string(), self.ch),
-            '<' => self.new_token(token::LT.to_string(), self.ch),
-            '>' => self.new_token(token::GT.to_string(), self.ch),
-            '{' => self.new_token(token::LBRACE.to_string(), self.ch),
-            '}' => self.new_token(token::RBRACE.to_string(), self.ch),
-            '\0' => token::Token{
-                r#type: token::EOF.to_string(),
-                literal: "".to_string(),
-            },
+            self.comments.push(self.lexeme_span());
+        }
+
+        if self.at_doc_comment() {
+            return self.read_doc_comment();
+        }
+        if let Some(kind) = self.read_operator() {
+            return self.new_token(kind);
+        }
+
+        let kind = match self.ch {
+            '"' => return self.read_string(),
+            '\0' if self.is_at_end() => return self.new_token(token::TokenKind::Eof),
             _ => {
                 if self.is_letter(self.ch) {
-                    let literal = self.read_identifier(); 
-                    return token::Token {
-                        literal: literal.clone(),
-                        r#type: token::lookup_ident(literal),
-                    }
+                    let literal = self.read_identifier();
+                    return self.new_token(token::lookup_ident(literal));
                 } else if self.is_digit(self.ch) {
-                    let literal = self.read_number();
-                    return token::Token {
-                        r#type: token::INT.to_string(),
-                        literal: literal.clone(),
-                    }
+                    let kind = self.read_number();
+                    return self.new_token(kind);
                 } else {
-                    self.new_token(token::ILLEGAL.to_string(), self.ch)
+                    token::TokenKind::Illegal(token::LexError::UnexpectedCharacter(self.ch))
                 }
             }
         };
 
-		self.read_char();
-		tok
-	}
+        self.read_char();
+        self.new_token(kind)
+    }
+
+    /// Consumes the longest operator or delimiter at the cursor, if any.
+    fn read_operator(&mut self) -> Option<token::TokenKind> {
+        let rest = &self.input[self.position..];
+        let (text, kind) = OPERATORS.iter().find(|(text, _)| rest.starts_with(text))?;
+        for _ in 0..text.len() {
+            self.read_char();
+        }
+        Some(kind.clone())
+    }
 
     fn skip_whitespace(&mut self) {
         while self.ch == ' ' || self.ch == '\t' || self.ch == '\n' || self.ch == '\r' {
@@ -119,6 +196,54 @@ impl Lexer {
         }
     }
 
+    /// `///` starts a doc comment, but `////` is an ordinary line comment.
+    fn at_doc_comment(&self) -> bool {
+        let rest = &self.input[self.position..];
+        rest.starts_with("///") && !rest.starts_with("////")
+    }
+
+    /// Skips a `//` comment, leaving `ch` on the newline that ends it.
+    fn skip_line_comment(&mut self) {
+        while self.ch != '\n' && !self.is_at_end() {
+            self.read_char();
+        }
+    }
+
+    /// Skips a `/* */` comment starting at its `/*`. Block comments nest, so
+    /// `/* a /* b */ c */` is one comment. Returns false if the input ends
+    /// before the comment is closed.
+    fn skip_block_comment(&mut self) -> bool {
+        let mut depth = 0;
+        loop {
+            match (self.ch, self.peek_char()) {
+                ('/', '*') => {
+                    depth += 1;
+                    self.read_char();
+                }
+                ('*', '/') => {
+                    depth -= 1;
+                    self.read_char();
+                    if depth == 0 {
+                        self.read_char();
+                        return true;
+                    }
+                }
+                ('\0', _) if self.is_at_end() => return false,
+                _ => {}
+            }
+            self.read_char();
+        }
+    }
+
+    /// Reads a `///` doc comment up to the end of its line. The token holds
+    /// the text after the slashes.
+    fn read_doc_comment(&mut self) -> token::Token {
+        self.skip_line_comment();
+        let text = self.input[self.start + 3..self.position].trim_end_matches('\r');
+        self.comments.push(self.lexeme_span());
+        self.new_token(token::TokenKind::DocComment(text.to_string()))
+    }
+
     fn read_identifier(&mut self) -> String {
         let position = self.position;
         while self.is_letter(self.ch) {
@@ -127,23 +252,159 @@ impl Lexer {
         self.input[position..self.position].to_string()
     }
 
-    fn read_number(&mut self) -> String {
+    /// Reads a string literal starting at its opening quote. An invalid escape
+    /// does not end the literal; the first one found is reported once the
+    /// closing quote is reached.
+    fn read_string(&mut self) -> token::Token {
+        let mut value = String::new();
+        let mut invalid = None;
+        loop {
+            self.read_char();
+            match self.ch {
+                '"' => break,
+                '\\' => match self.read_escape() {
+                    Ok(ch) => value.push(ch),
+                    Err(err) => {
+                        invalid.get_or_insert(err);
+                    }
+                },
+                '\0' if self.is_at_end() => {
+                    return self.new_token(token::TokenKind::Illegal(token::LexError::UnterminatedString));
+                }
+                ch => value.push(ch),
+            }
+        }
+        self.read_char();
+        match invalid {
+            Some(err) => self.new_token(token::TokenKind::Illegal(err)),
+            None => self.new_token(token::TokenKind::Str(value)),
+        }
+    }
+
+    /// Reads the escape sequence starting at a backslash, leaving `ch` on its
+    /// last character.
+    fn read_escape(&mut self) -> Result<char, token::LexError> {
+        let start = self.position;
+        self.read_char();
+        let ch = match self.ch {
+            'n' => '\n',
+            't' => '\t',
+            '"' => '"',
+            '\\' => '\\',
+            'u' if self.peek_char() == '{' => {
+                self.read_char();
+                while self.peek_char().is_ascii_hexdigit() {
+                    self.read_char();
+                }
+                if self.peek_char() != '}' {
+                    return Err(token::LexError::InvalidEscape(self.input[start..self.read_position].to_string()));
+                }
+                self.read_char();
+                let digits = &self.input[start + 3..self.position];
+                let code = u32::from_str_radix(digits, 16).ok().filter(|_| digits.len() <= 6);
+                match code.and_then(char::from_u32) {
+                    Some(ch) => ch,
+                    None => {
+                        return Err(token::LexError::InvalidEscape(self.input[start..self.read_position].to_string()));
+                    }
+                }
+            }
+            '\0' if self.is_at_end() => return Err(token::LexError::UnterminatedString),
+            _ => return Err(token::LexError::InvalidEscape(self.input[start..self.read_position].to_string())),
+        };
+        Ok(ch)
+    }
+
+    /// Reads an integer or float literal. The token keeps the source text
+    /// (prefix and `_` separators included); the parser computes its value.
+    ///
+    /// Integers are decimal, or hexadecimal, binary or octal with a `0x`,
+    /// `0b` or `0o` prefix. Floats have a fraction (`3.14`), an exponent
+    /// (`1e-9`) or both. Letters or digits running on from a literal make the
+    /// whole run an invalid number rather than a separate token.
+    fn read_number(&mut self) -> token::TokenKind {
         let position = self.position;
-        while self.is_digit(self.ch) {
+        let radix = match (self.ch, self.peek_char()) {
+            ('0', 'x' | 'X') => 16,
+            ('0', 'b' | 'B') => 2,
+            ('0', 'o' | 'O') => 8,
+            _ => 10,
+        };
+
+        if radix != 10 {
+            self.read_char();
+            self.read_char();
+            self.read_alphanumeric();
+            let literal = self.input[position..self.position].to_string();
+            return if valid_digits(&literal[2..], radix) {
+                token::TokenKind::Int(literal)
+            } else {
+                token::TokenKind::Illegal(token::LexError::InvalidNumber(literal))
+            };
+        }
+
+        let mut is_float = false;
+        self.read_digits();
+        if self.ch == '.' && self.is_digit(self.peek_char()) {
+            is_float = true;
+            self.read_char();
+            self.read_digits();
+        }
+        let signed = matches!(self.peek_char(), '+' | '-') && self.is_digit(self.peek_second_char());
+        if matches!(self.ch, 'e' | 'E') && (self.is_digit(self.peek_char()) || signed) {
+            is_float = true;
+            self.read_char();
+            if signed {
+                self.read_char();
+            }
+            self.read_digits();
+        }
+        let digits_end = self.position;
+        self.read_alphanumeric();
+
+        let literal = self.input[position..self.position].to_string();
+        let valid = digits_end == self.position
+            && literal.split(['.', 'e', 'E', '+', '-']).all(|part| part.is_empty() || valid_digits(part, 10));
+        if !valid {
+            token::TokenKind::Illegal(token::LexError::InvalidNumber(literal))
+        } else if is_float {
+            token::TokenKind::Float(literal)
+        } else {
+            token::TokenKind::Int(literal)
+        }
+    }
+
+    fn read_digits(&mut self) {
+        while self.is_digit(self.ch) || self.ch == '_' {
+            self.read_char();
+        }
+    }
+
+    fn read_alphanumeric(&mut self) {
+        while self.ch.is_alphanumeric() || self.ch == '_' {
             self.read_char();
         }
-        self.input[position..self.position].to_string()
     }
 
     fn is_letter(&self, ch: char) -> bool {
-        'a' <= ch && ch <= 'z' || 'A' <= ch && ch <= 'Z' || ch == '_'
+        ch.is_alphabetic() || ch == '_'
     }
 
     fn is_digit(&self, ch: char) -> bool {
-        '0' <= ch && ch <= '9'
+        ch.is_ascii_digit()
     }
 }
 
+/// Whether `s` is a non-empty run of digits in `radix`, with `_` allowed
+/// only between two digits.
+fn valid_digits(s: &str, radix: u32) -> bool {
+    let bytes = s.as_bytes();
+    !s.is_empty() && bytes.iter().enumerate().all(|(i, &b)| match b {
+        b'_' => i > 0 && i + 1 < bytes.len() && bytes[i - 1] != b'_' && bytes[i + 1] != b'_',
+        b => (b as char).is_digit(radix),
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use crate::token::*;
@@ -156,7 +417,7 @@ let add = fn(x, y) {
 x + y;
 };
 let result = add(five, ten);
-!-/*5;
+!-/ *5;
 5 < 10 > 5;
 if (5 < 10) {
     return true;
@@ -167,308 +428,318 @@ if (5 < 10) {
 10 != 9;
 ");
 		let tests = vec![
-            token::Token {
-                r#type: token::LET.to_string(),
-                literal: "let".to_string(),
-            },
-            token::Token {
-                r#type: token::IDENT.to_string(),
-                literal: "five".to_string(),
-            },
-            token::Token {
-                r#type: token::ASSIGN.to_string(),
-                literal: "=".to_string(),
-            },
-            token::Token {
-                r#type: token::INT.to_string(),
-                literal: "5".to_string(),
-            },
-            token::Token {
-                r#type: token::SEMICOLON.to_string(),
-                literal: ";".to_string(),
-            },
-            token::Token {
-                r#type: token::LET.to_string(),
-                literal: "let".to_string(),
-            },
-            token::Token {
-                r#type: token::IDENT.to_string(),
-                literal: "ten".to_string(),
-            },
-            token::Token {
-                r#type: token::ASSIGN.to_string(),
-                literal: "=".to_string(),
-            },
-            token::Token {
-                r#type: token::INT.to_string(),
-                literal: "10".to_string(),
-            },
-            token::Token {
-                r#type: token::SEMICOLON.to_string(),
-                literal: ";".to_string(),
-            },
-            token::Token {
-                r#type: token::LET.to_string(),
-                literal: "let".to_string(),
-            },
-            token::Token {
-                r#type: token::IDENT.to_string(),
-                literal: "add".to_string(),
-            },
-            token::Token {
-                r#type: token::ASSIGN.to_string(),
-                literal: "=".to_string(),
-            },
-            token::Token {
-                r#type: token::FUNCTION.to_string(),
-                literal: "fn".to_string(),
-            },
-            token::Token {
-                r#type: token::LPAREN.to_string(),
-                literal: "(".to_string(),
-            },
-            token::Token {
-                r#type: token::IDENT.to_string(),
-                literal: "x".to_string(),
-            },
-            token::Token {
-                r#type: token::COMMA.to_string(),
-                literal: ",".to_string(),
-            },
-            token::Token {
-                r#type: token::IDENT.to_string(),
-                literal: "y".to_string(),
-            },
-            token::Token {
-                r#type: token::RPAREN.to_string(),
-                literal: ")".to_string(),
-            },
-            token::Token {
-                r#type: token::LBRACE.to_string(),
-                literal: "{".to_string(),
-            },
-            token::Token {
-                r#type: token::IDENT.to_string(),
-                literal: "x".to_string(),
-            },
-            token::Token {
-                r#type: token::PLUS.to_string(),
-                literal: "+".to_string(),
-            },
-            token::Token {
-                r#type: token::IDENT.to_string(),
-                literal: "y".to_string(),
-            },
-            token::Token {
-                r#type: token::SEMICOLON.to_string(),
-                literal: ";".to_string(),
-            },
-            token::Token {
-                r#type: token::RBRACE.to_string(),
-                literal: "}".to_string(),
-            },
-            token::Token {
-                r#type: token::SEMICOLON.to_string(),
-                literal: ";".to_string(),
-            },
-            token::Token {
-                r#type: token::LET.to_string(),
-                literal: "let".to_string(),
-            },
-            token::Token {
-                r#type: token::IDENT.to_string(),
-                literal: "result".to_string(),
-            },
-            token::Token {
-                r#type: token::ASSIGN.to_string(),
-                literal: "=".to_string(),
-            },
-            token::Token {
-                r#type: token::IDENT.to_string(),
-                literal: "add".to_string(),
-            },
-            token::Token {
-                r#type: token::LPAREN.to_string(),
-                literal: "(".to_string(),
-            },
-            token::Token {
-                r#type: token::IDENT.to_string(),
-                literal: "five".to_string(),
-            },
-            token::Token {
-                r#type: token::COMMA.to_string(),
-                literal: ",".to_string(),
-            },
-            token::Token {
-                r#type: token::IDENT.to_string(),
-                literal: "ten".to_string(),
-            },
-            token::Token {
-                r#type: token::RPAREN.to_string(),
-                literal: ")".to_string(),
-            },
-            token::Token {
-                r#type: token::SEMICOLON.to_string(),
-                literal: ";".to_string(),
-            },
-            token::Token {
-                r#type: token::BANG.to_string(),
-                literal: "!".to_string(),
-            },
-            token::Token {
-                r#type: token::MINUS.to_string(),
-                literal: "-".to_string(),
-            },
-            token::Token {
-                r#type: token::SLASH.to_string(),
-                literal: "/".to_string(),
-            },
-            token::Token {
-                r#type: token::ASTERISK.to_string(),
-                literal: "*".to_string(),
-            },
-            token::Token {
-                r#type: token::INT.to_string(),
-                literal: "5".to_string(),
-            },
-            token::Token {
-                r#type: token::SEMICOLON.to_string(),
-                literal: ";".to_string(),
-            },
-            token::Token {
-                r#type: token::INT.to_string(),
-                literal: "5".to_string(),
-            },
-            token::Token {
-                r#type: token::LT.to_string(),
-                literal: "<".to_string(),
-            },
-            token::Token {
-                r#type: token::INT.to_string(),
-                literal: "10".to_string(),
-            },
-            token::Token {
-                r#type: token::GT.to_string(),
-                literal: ">".to_string(),
-            },
-            token::Token {
-                r#type: token::INT.to_string(),
-                literal: "5".to_string(),
-            },
-            token::Token {
-                r#type: token::SEMICOLON.to_string(),
-                literal: ";".to_string(),
-            },
-            token::Token {
-                r#type: token::IF.to_string(),
-                literal: "if".to_string(),
-            },
-            token::Token {
-                r#type: token::LPAREN.to_string(),
-                literal: "(".to_string(),
-            },
-            token::Token {
-                r#type: token::INT.to_string(),
-                literal: "5".to_string(),
-            },
-            token::Token {
-                r#type: token::LT.to_string(),
-                literal: "<".to_string(),
-            },
-            token::Token {
-                r#type: token::INT.to_string(),
-                literal: "10".to_string(),
-            },
-            token::Token {
-                r#type: token::RPAREN.to_string(),
-                literal: ")".to_string(),
-            },
-            token::Token {
-                r#type: token::LBRACE.to_string(),
-                literal: "{".to_string(),
-            },
-            token::Token {
-                r#type: token::RETURN.to_string(),
-                literal: "return".to_string(),
-            },
-            token::Token {
-                r#type: token::TRUE.to_string(),
-                literal: "true".to_string(),
-            },
-            token::Token {
-                r#type: token::SEMICOLON.to_string(),
-                literal: ";".to_string(),
-            },
-            token::Token {
-                r#type: token::RBRACE.to_string(),
-                literal: "}".to_string(),
-            },
-            token::Token {
-                r#type: token::ELSE.to_string(),
-                literal: "else".to_string(),
-            },
-            token::Token {
-                r#type: token::LBRACE.to_string(),
-                literal: "{".to_string(),
-            },
-            token::Token {
-                r#type: token::RETURN.to_string(),
-                literal: "return".to_string(),
-            },
-            token::Token {
-                r#type: token::FALSE.to_string(),
-                literal: "false".to_string(),
-            },
-            token::Token {
-                r#type: token::SEMICOLON.to_string(),
-                literal: ";".to_string(),
-            },
-            token::Token {
-                r#type: token::RBRACE.to_string(),
-                literal: "}".to_string(),
-            },
-            token::Token {
-                r#type: token::INT.to_string(),
-                literal: "10".to_string(),
-            },
-            token::Token {
-                r#type: token::EQ.to_string(),
-                literal: "==".to_string(),
-            },
-            token::Token {
-                r#type: token::INT.to_string(),
-                literal: "10".to_string(),
-            },
-            token::Token {
-                r#type: token::SEMICOLON.to_string(),
-                literal: ";".to_string(),
-            },
-            token::Token {
-                r#type: token::INT.to_string(),
-                literal: "10".to_string(),
-            }, 
-            token::Token {
-                r#type: token::NOT_EQ.to_string(),
-                literal: "!=".to_string(),
-            },
-            token::Token {
-                r#type: token::INT.to_string(),
-                literal: "9".to_string(),
-            },
-            token::Token {
-                r#type: token::SEMICOLON.to_string(),
-                literal: ";".to_string(),
-            },
-            token::Token {
-                r#type: token::EOF.to_string(),
-                literal: "".to_string(),
-            },
+            token::TokenKind::Let,
+            token::TokenKind::Ident("five".to_string()),
+            token::TokenKind::Assign,
+            token::TokenKind::Int("5".to_string()),
+            token::TokenKind::Semicolon,
+            token::TokenKind::Let,
+            token::TokenKind::Ident("ten".to_string()),
+            token::TokenKind::Assign,
+            token::TokenKind::Int("10".to_string()),
+            token::TokenKind::Semicolon,
+            token::TokenKind::Let,
+            token::TokenKind::Ident("add".to_string()),
+            token::TokenKind::Assign,
+            token::TokenKind::Function,
+            token::TokenKind::LParen,
+            token::TokenKind::Ident("x".to_string()),
+            token::TokenKind::Comma,
+            token::TokenKind::Ident("y".to_string()),
+            token::TokenKind::RParen,
+            token::TokenKind::LBrace,
+            token::TokenKind::Ident("x".to_string()),
+            token::TokenKind::Plus,
+            token::TokenKind::Ident("y".to_string()),
+            token::TokenKind::Semicolon,
+            token::TokenKind::RBrace,
+            token::TokenKind::Semicolon,
+            token::TokenKind::Let,
+            token::TokenKind::Ident("result".to_string()),
+            token::TokenKind::Assign,
+            token::TokenKind::Ident("add".to_string()),
+            token::TokenKind::LParen,
+            token::TokenKind::Ident("five".to_string()),
+            token::TokenKind::Comma,
+            token::TokenKind::Ident("ten".to_string()),
+            token::TokenKind::RParen,
+            token::TokenKind::Semicolon,
+            token::TokenKind::Bang,
+            token::TokenKind::Minus,
+            token::TokenKind::Slash,
+            token::TokenKind::Asterisk,
+            token::TokenKind::Int("5".to_string()),
+            token::TokenKind::Semicolon,
+            token::TokenKind::Int("5".to_string()),
+            token::TokenKind::Lt,
+            token::TokenKind::Int("10".to_string()),
+            token::TokenKind::Gt,
+            token::TokenKind::Int("5".to_string()),
+            token::TokenKind::Semicolon,
+            token::TokenKind::If,
+            token::TokenKind::LParen,
+            token::TokenKind::Int("5".to_string()),
+            token::TokenKind::Lt,
+            token::TokenKind::Int("10".to_string()),
+            token::TokenKind::RParen,
+            token::TokenKind::LBrace,
+            token::TokenKind::Return,
+            token::TokenKind::True,
+            token::TokenKind::Semicolon,
+            token::TokenKind::RBrace,
+            token::TokenKind::Else,
+            token::TokenKind::LBrace,
+            token::TokenKind::Return,
+            token::TokenKind::False,
+            token::TokenKind::Semicolon,
+            token::TokenKind::RBrace,
+            token::TokenKind::Int("10".to_string()),
+            token::TokenKind::Eq,
+            token::TokenKind::Int("10".to_string()),
+            token::TokenKind::Semicolon,
+            token::TokenKind::Int("10".to_string()),
+            token::TokenKind::NotEq,
+            token::TokenKind::Int("9".to_string()),
+            token::TokenKind::Semicolon,
+            token::TokenKind::Eof,
         ];
 		let mut l = lexer::Lexer::new(input);
 		for tt in tests {
 			let tok = l.next_token();
-			assert_eq!(tok.r#type, tt.r#type);
-			assert_eq!(tok.literal, tt.literal);
+			assert_eq!(tok.kind, tt);
 		}
 	}
+
+    #[test]
+    fn token_spans() {
+        let input = String::from("let x = 10;\n  x == y;");
+        let tests = vec![
+            (token::TokenKind::Let, 0, 3, 1, 1),
+            (token::TokenKind::Ident("x".to_string()), 4, 5, 1, 5),
+            (token::TokenKind::Assign, 6, 7, 1, 7),
+            (token::TokenKind::Int("10".to_string()), 8, 10, 1, 9),
+            (token::TokenKind::Semicolon, 10, 11, 1, 11),
+            (token::TokenKind::Ident("x".to_string()), 14, 15, 2, 3),
+            (token::TokenKind::Eq, 16, 18, 2, 5),
+            (token::TokenKind::Ident("y".to_string()), 19, 20, 2, 8),
+            (token::TokenKind::Semicolon, 20, 21, 2, 9),
+            (token::TokenKind::Eof, 21, 21, 2, 10),
+        ];
+        let mut l = lexer::Lexer::new(input);
+        for (kind, start, end, line, column) in tests {
+            let tok = l.next_token();
+            assert_eq!(tok.kind, kind);
+            assert_eq!(tok.span, token::Span { start, end, line, column });
+        }
+    }
+
+    #[test]
+    fn multibyte_input() {
+        let input = String::from("let café = \"€\";\n🦀 ünï");
+        let tests = vec![
+            (token::TokenKind::Let, 0, 3, 1, 1),
+            (token::TokenKind::Ident("café".to_string()), 4, 9, 1, 5),
+            (token::TokenKind::Assign, 10, 11, 1, 10),
+            (token::TokenKind::Str("€".to_string()), 12, 17, 1, 12),
+            (token::TokenKind::Semicolon, 17, 18, 1, 15),
+            (token::TokenKind::Illegal(token::LexError::UnexpectedCharacter('🦀')), 19, 23, 2, 1),
+            (token::TokenKind::Ident("ünï".to_string()), 24, 29, 2, 3),
+            (token::TokenKind::Eof, 29, 29, 2, 6),
+        ];
+        let mut l = lexer::Lexer::new(input);
+        for (kind, start, end, line, column) in tests {
+            let tok = l.next_token();
+            assert_eq!(tok.kind, kind);
+            assert_eq!(tok.span, token::Span { start, end, line, column });
+        }
+    }
+
+    #[test]
+    fn nul_is_not_eof() {
+        let mut l = lexer::Lexer::new("a\0b".to_string());
+        assert_eq!(l.next_token().kind, token::TokenKind::Ident("a".to_string()));
+        assert_eq!(l.next_token().kind, token::TokenKind::Illegal(token::LexError::UnexpectedCharacter('\0')));
+        assert_eq!(l.next_token().kind, token::TokenKind::Ident("b".to_string()));
+        assert_eq!(l.next_token().kind, token::TokenKind::Eof);
+    }
+
+    #[test]
+    fn shebang_line() {
+        let mut l = lexer::Lexer::new("#!/usr/bin/env runkey\nlet".to_string());
+        let tok = l.next_token();
+        assert_eq!(tok.kind, token::TokenKind::Let);
+        assert_eq!(tok.span, token::Span { start: 22, end: 25, line: 2, column: 1 });
+
+        let mut l = lexer::Lexer::new("x #!".to_string());
+        assert_eq!(l.next_token().kind, token::TokenKind::Ident("x".to_string()));
+        assert_eq!(l.next_token().kind, token::TokenKind::Illegal(token::LexError::UnexpectedCharacter('#')));
+    }
+
+    #[test]
+    fn string_literals() {
+        let tests = [
+            (r#""foobar""#, token::TokenKind::Str("foobar".to_string())),
+            (r#""foo bar""#, token::TokenKind::Str("foo bar".to_string())),
+            (r#""""#, token::TokenKind::Str("".to_string())),
+            (r#""a\nb\tc""#, token::TokenKind::Str("a\nb\tc".to_string())),
+            (r#""say \"hi\" \\ bye""#, token::TokenKind::Str("say \"hi\" \\ bye".to_string())),
+            (r#""\u{41}\u{1F980}""#, token::TokenKind::Str("A🦀".to_string())),
+            (r#""unterminated"#, token::TokenKind::Illegal(token::LexError::UnterminatedString)),
+            (r#""ends in \"#, token::TokenKind::Illegal(token::LexError::UnterminatedString)),
+            (r#""\q""#, token::TokenKind::Illegal(token::LexError::InvalidEscape(r"\q".to_string()))),
+            (r#""\u{zz}""#, token::TokenKind::Illegal(token::LexError::InvalidEscape(r"\u{".to_string()))),
+            (r#""\u{110000}""#, token::TokenKind::Illegal(token::LexError::InvalidEscape(r"\u{110000}".to_string()))),
+            (r#""\u{D800}""#, token::TokenKind::Illegal(token::LexError::InvalidEscape(r"\u{D800}".to_string()))),
+            (r#""\u41""#, token::TokenKind::Illegal(token::LexError::InvalidEscape(r"\u".to_string()))),
+        ];
+        for (input, expected) in tests {
+            let mut l = lexer::Lexer::new(input.to_string());
+            let tok = l.next_token();
+            assert_eq!(tok.kind, expected, "input: {}", input);
+            assert_eq!((tok.span.start, tok.span.end), (0, input.len()), "input: {}", input);
+            assert_eq!(l.next_token().kind, token::TokenKind::Eof, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn number_literals() {
+        let tests = [
+            ("0", token::TokenKind::Int("0".to_string())),
+            ("1_000_000", token::TokenKind::Int("1_000_000".to_string())),
+            ("0xFF", token::TokenKind::Int("0xFF".to_string())),
+            ("0xdead_BEEF", token::TokenKind::Int("0xdead_BEEF".to_string())),
+            ("0b1010", token::TokenKind::Int("0b1010".to_string())),
+            ("0o755", token::TokenKind::Int("0o755".to_string())),
+            ("3.14", token::TokenKind::Float("3.14".to_string())),
+            ("1e-9", token::TokenKind::Float("1e-9".to_string())),
+            ("2.5E+3", token::TokenKind::Float("2.5E+3".to_string())),
+            ("1_000.000_1", token::TokenKind::Float("1_000.000_1".to_string())),
+            ("6e23", token::TokenKind::Float("6e23".to_string())),
+            ("0x", token::TokenKind::Illegal(token::LexError::InvalidNumber("0x".to_string()))),
+            ("0b102", token::TokenKind::Illegal(token::LexError::InvalidNumber("0b102".to_string()))),
+            ("0o8", token::TokenKind::Illegal(token::LexError::InvalidNumber("0o8".to_string()))),
+            ("1__0", token::TokenKind::Illegal(token::LexError::InvalidNumber("1__0".to_string()))),
+            ("1_", token::TokenKind::Illegal(token::LexError::InvalidNumber("1_".to_string()))),
+            ("0x_1", token::TokenKind::Illegal(token::LexError::InvalidNumber("0x_1".to_string()))),
+            ("12ab", token::TokenKind::Illegal(token::LexError::InvalidNumber("12ab".to_string()))),
+            ("1e", token::TokenKind::Illegal(token::LexError::InvalidNumber("1e".to_string()))),
+            ("1.5_e3", token::TokenKind::Illegal(token::LexError::InvalidNumber("1.5_e3".to_string()))),
+        ];
+        for (input, expected) in tests {
+            let mut l = lexer::Lexer::new(input.to_string());
+            assert_eq!(l.next_token().kind, expected, "input: {}", input);
+            assert_eq!(l.next_token().kind, token::TokenKind::Eof, "input: {}", input);
+        }
+
+        let mut l = lexer::Lexer::new("1.foo 1..2 -3".to_string());
+        let expected = [
+            token::TokenKind::Int("1".to_string()),
+            token::TokenKind::Illegal(token::LexError::UnexpectedCharacter('.')),
+            token::TokenKind::Ident("foo".to_string()),
+            token::TokenKind::Int("1".to_string()),
+            token::TokenKind::DotDot,
+            token::TokenKind::Int("2".to_string()),
+            token::TokenKind::Minus,
+            token::TokenKind::Int("3".to_string()),
+        ];
+        for kind in expected {
+            assert_eq!(l.next_token().kind, kind);
+        }
+    }
+
+    #[test]
+    fn comments() {
+        let input = "let a = 1; // trailing
+// whole line
+/* block */ a /* nested /* twice */ still comment */ / 2
+//// not a doc comment
+/// Doc line.\r
+///
+let";
+        let mut l = lexer::Lexer::new(input.to_string());
+        let expected = [
+            token::TokenKind::Let,
+            token::TokenKind::Ident("a".to_string()),
+            token::TokenKind::Assign,
+            token::TokenKind::Int("1".to_string()),
+            token::TokenKind::Semicolon,
+            token::TokenKind::Ident("a".to_string()),
+            token::TokenKind::Slash,
+            token::TokenKind::Int("2".to_string()),
+            token::TokenKind::DocComment(" Doc line.".to_string()),
+            token::TokenKind::DocComment("".to_string()),
+            token::TokenKind::Let,
+            token::TokenKind::Eof,
+        ];
+        for kind in expected {
+            assert_eq!(l.next_token().kind, kind);
+        }
+        let comments: Vec<_> = l.comments().iter().map(|span| &input[span.start..span.end]).collect();
+        assert_eq!(comments, [
+            "// trailing",
+            "// whole line",
+            "/* block */",
+            "/* nested /* twice */ still comment */",
+            "//// not a doc comment",
+            "/// Doc line.\r",
+            "///",
+        ]);
+
+        let mut l = lexer::Lexer::new("1 /* a /* b */\n".to_string());
+        assert_eq!(l.next_token().kind, token::TokenKind::Int("1".to_string()));
+        assert_eq!(l.next_token(), token::Token::new(
+            token::TokenKind::Illegal(token::LexError::UnterminatedComment),
+            token::Span { start: 2, end: 15, line: 1, column: 3 },
+        ));
+        assert_eq!(l.next_token().kind, token::TokenKind::Eof);
+    }
+
+    #[test]
+    fn operators() {
+        let mut l = lexer::Lexer::new("<= >= < = > && || % ** * *= += -= /= / == != ! [ : ] & |".to_string());
+        let expected = [
+            token::TokenKind::LtEq,
+            token::TokenKind::GtEq,
+            token::TokenKind::Lt,
+            token::TokenKind::Assign,
+            token::TokenKind::Gt,
+            token::TokenKind::And,
+            token::TokenKind::Or,
+            token::TokenKind::Percent,
+            token::TokenKind::Power,
+            token::TokenKind::Asterisk,
+            token::TokenKind::AsteriskAssign,
+            token::TokenKind::PlusAssign,
+            token::TokenKind::MinusAssign,
+            token::TokenKind::SlashAssign,
+            token::TokenKind::Slash,
+            token::TokenKind::Eq,
+            token::TokenKind::NotEq,
+            token::TokenKind::Bang,
+            token::TokenKind::LBracket,
+            token::TokenKind::Colon,
+            token::TokenKind::RBracket,
+            token::TokenKind::Illegal(token::LexError::UnexpectedCharacter('&')),
+            token::TokenKind::Illegal(token::LexError::UnexpectedCharacter('|')),
+            token::TokenKind::Eof,
+        ];
+        for kind in expected {
+            assert_eq!(l.next_token().kind, kind);
+        }
+
+        // Maximal munch takes `**` before `*=`, and `===` as `==` then `=`.
+        let mut l = lexer::Lexer::new("a**=b===c".to_string());
+        let expected = [
+            token::TokenKind::Ident("a".to_string()),
+            token::TokenKind::Power,
+            token::TokenKind::Assign,
+            token::TokenKind::Ident("b".to_string()),
+            token::TokenKind::Eq,
+            token::TokenKind::Assign,
+            token::TokenKind::Ident("c".to_string()),
+        ];
+        for kind in expected {
+            assert_eq!(l.next_token().kind, kind);
+        }
+    }
 }
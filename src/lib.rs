@@ -0,0 +1,14 @@
+#![allow(clippy::module_inception)]
+
+pub mod token;
+pub mod lexer;
+pub mod ast;
+pub mod parser;
+pub mod object;
+pub mod evaluator;
+pub mod diagnostics;
+pub mod repl;
+pub mod formatter;
+pub mod compiler;
+pub mod vm;
+pub mod engine;
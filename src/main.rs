@@ -1,13 +1,614 @@
-mod token;
-mod lexer;
-mod repl;
-mod ast;
-mod parser;
-use std::io;
-
-fn main() {
-    let user = whoami::username();
-    println!("Hello, {}! This is the Runkey programming language!", user);
-    println!("Feel free to type in commands");
-    repl::repl::start(io::stdin(), io::stdout()).unwrap();
+use runkey::ast::*;
+use runkey::compiler::*;
+use runkey::diagnostics::*;
+use runkey::evaluator::*;
+use runkey::formatter::*;
+use runkey::lexer::*;
+use runkey::object::*;
+use runkey::parser::*;
+use runkey::repl;
+use runkey::token::*;
+use runkey::vm::*;
+use std::fmt::Write as _;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage:
+    runkey                    start the interactive REPL
+    runkey [--engine=<e>] <file>
+                              run a script (`-` reads it from stdin)
+    runkey run [--engine=<e>] <file>
+                              run a script
+    runkey -e [--engine=<e>] <source>
+                              evaluate source and print the result
+    runkey compile <file> [-o <out>]
+                              compile a script to bytecode, written to <out>
+                              or next to the script with the extension .rkc
+    runkey disasm <file>      print the bytecode of a script or .rkc file
+    runkey tokens <file>      print the tokens of a script
+    runkey ast <file>         print the syntax tree of a script
+    runkey fmt <file>...      format scripts in place (`-` formats stdin to stdout)
+
+Options:
+    -h, --help                print this help
+    -V, --version             print the version
+
+Run options:
+    --engine=tree             walk the syntax tree (the default)
+    --engine=vm               compile to bytecode and run it on the virtual
+                              machine
+
+A compiled .rkc file is recognised by its contents and always runs on the
+virtual machine.
+
+Modules a script imports are looked for next to the importing file, then in
+each directory listed in RUNKEY_PATH. Only the tree engine runs imports: a
+script that imports modules cannot be compiled, so neither `--engine=vm` nor
+`compile` accepts it.
+
+Format options:
+    --check                   list the scripts that are not formatted instead
+                              of rewriting them, and exit with status 1 if any
+    --width <n>               wrap call arguments that run past column <n>
+                              (default 100)
+
+Exit status:
+    0   success
+    1   `fmt --check` found scripts that are not formatted
+    64  invalid command-line usage
+    65  the script has syntax errors, could not be compiled, or the compiled
+        file is invalid
+    66  the script could not be read
+    70  the script failed at run time
+";
+
+const EXIT_USAGE: u8 = 64;
+const EXIT_DATAERR: u8 = 65;
+const EXIT_NOINPUT: u8 = 66;
+const EXIT_SOFTWARE: u8 = 70;
+
+#[derive(Debug, PartialEq)]
+enum Input {
+    File(PathBuf),
+    Stdin,
+}
+
+/// How `run` and `-e` execute a program.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+enum Engine {
+    #[default]
+    Tree,
+    Vm,
+}
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Repl,
+    Run(Input, Engine),
+    Eval(String, Engine),
+    Compile {
+        input: Input,
+        output: Option<PathBuf>,
+    },
+    Disasm(Input),
+    Tokens(Input),
+    Ast(Input),
+    Fmt {
+        inputs: Vec<Input>,
+        check: bool,
+        width: usize,
+    },
+    Help,
+    Version,
+}
+
+fn parse_args(args: &[String]) -> Result<Command, String> {
+    let mut engine = None;
+    let mut rest: Vec<String> = Vec::new();
+    for arg in args {
+        match arg.strip_prefix("--engine=") {
+            // The engine may be chosen before the command, or anywhere after `run` and `-e`.
+            Some(name) if rest.first().is_none_or(|command| command == "run" || command == "-e") => {
+                engine = match name {
+                    "tree" => Some(Engine::Tree),
+                    "vm" => Some(Engine::Vm),
+                    _ => return Err(format!("unknown engine `{}`, expected `tree` or `vm`", name)),
+                };
+            }
+            _ => rest.push(arg.clone()),
+        }
+    }
+    let command = parse_command(&rest)?;
+    match (command, engine) {
+        (command, None) => Ok(command),
+        (Command::Run(input, _), Some(engine)) => Ok(Command::Run(input, engine)),
+        (Command::Eval(source, _), Some(engine)) => Ok(Command::Eval(source, engine)),
+        (_, Some(_)) => Err("`--engine` only applies to running a script or `-e`".to_string()),
+    }
+}
+
+fn parse_command(args: &[String]) -> Result<Command, String> {
+    let input = |arg: Option<&String>| match arg.map(String::as_str) {
+        Some("-") => Ok(Input::Stdin),
+        Some(path) => Ok(Input::File(PathBuf::from(path))),
+        None => Err("missing script path".to_string()),
+    };
+    let (command, consumed) = match args.first().map(String::as_str) {
+        None if io::stdin().is_terminal() => (Command::Repl, 0),
+        None => (Command::Run(Input::Stdin, Engine::Tree), 0),
+        Some("-h" | "--help") => (Command::Help, 1),
+        Some("-V" | "--version") => (Command::Version, 1),
+        Some("-e") => match args.get(1) {
+            Some(source) => (Command::Eval(source.clone(), Engine::Tree), 2),
+            None => return Err("`-e` needs source to evaluate".to_string()),
+        },
+        Some("run") => (Command::Run(input(args.get(1))?, Engine::Tree), 2),
+        Some("compile") => return parse_compile_args(&args[1..]),
+        Some("disasm") => (Command::Disasm(input(args.get(1))?), 2),
+        Some("tokens") => (Command::Tokens(input(args.get(1))?), 2),
+        Some("ast") => (Command::Ast(input(args.get(1))?), 2),
+        Some("fmt") => return parse_fmt_args(&args[1..]),
+        Some(arg) if arg.starts_with('-') && arg != "-" => {
+            return Err(format!("unknown option `{}`", arg));
+        }
+        Some(_) => (Command::Run(input(args.first())?, Engine::Tree), 1),
+    };
+    if let Some(extra) = args.get(consumed) {
+        return Err(format!("unexpected argument `{}`", extra));
+    }
+    Ok(command)
+}
+
+fn parse_compile_args(args: &[String]) -> Result<Command, String> {
+    let mut input = None;
+    let mut output = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => match args.next() {
+                Some(path) => output = Some(PathBuf::from(path)),
+                None => return Err("`-o` needs an output path".to_string()),
+            },
+            arg if arg.starts_with('-') && arg != "-" => return Err(format!("unknown option `{}`", arg)),
+            _ if input.is_some() => return Err(format!("unexpected argument `{}`", arg)),
+            "-" => input = Some(Input::Stdin),
+            path => input = Some(Input::File(PathBuf::from(path))),
+        }
+    }
+    match input {
+        Some(input) => Ok(Command::Compile { input, output }),
+        None => Err("missing script path".to_string()),
+    }
+}
+
+fn parse_fmt_args(args: &[String]) -> Result<Command, String> {
+    let mut inputs = Vec::new();
+    let mut check = false;
+    let mut width = formatter::DEFAULT_WIDTH;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--check" => check = true,
+            "--width" => {
+                width = match args.next().map(|n| (n, n.parse())) {
+                    Some((_, Ok(n))) if n > 0 => n,
+                    Some((n, _)) => return Err(format!("`--width` needs a positive number, got `{}`", n)),
+                    None => return Err("`--width` needs a positive number".to_string()),
+                }
+            }
+            "-" => inputs.push(Input::Stdin),
+            arg if arg.starts_with('-') => return Err(format!("unknown option `{}`", arg)),
+            path => inputs.push(Input::File(PathBuf::from(path))),
+        }
+    }
+    if inputs.is_empty() {
+        return Err("missing script path".to_string());
+    }
+    Ok(Command::Fmt { inputs, check, width })
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command = match parse_args(&args) {
+        Ok(command) => command,
+        Err(msg) => {
+            eprintln!("runkey: {}\n\n{}", msg, USAGE);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+
+    match command {
+        Command::Repl => {
+            let user = whoami::username();
+            println!("Hello, {}! This is the Runkey programming language!", user);
+            println!("Feel free to type in commands");
+            match repl::repl::start(io::stdin(), io::stdout()) {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(err) => {
+                    eprintln!("runkey: {}", err);
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        Command::Help => {
+            print!("{}", USAGE);
+            ExitCode::SUCCESS
+        }
+        Command::Version => {
+            println!("runkey {}", env!("CARGO_PKG_VERSION"));
+            ExitCode::SUCCESS
+        }
+        Command::Run(input, engine) => match run_input(&input, engine) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(code) => code,
+        },
+        Command::Eval(source, engine) => {
+            let result = parse("<eval>", &source)
+                .and_then(|program| evaluate("<eval>", &source, &program, None, engine));
+            match result {
+                Ok(value) => {
+                    println!("{}", value);
+                    ExitCode::SUCCESS
+                }
+                Err(code) => code,
+            }
+        }
+        Command::Compile { input, output } => match compile_input(&input, output) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(code) => code,
+        },
+        Command::Disasm(input) => match disassemble_input(&input) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(code) => code,
+        },
+        Command::Tokens(input) => with_source(&input, dump_tokens),
+        Command::Ast(input) => with_source(&input, |name, source| {
+            let program = parse(name, source)?;
+            write_stdout(&format!("{:#?}\n", program))
+        }),
+        Command::Fmt { inputs, check, width } => {
+            let mut status = ExitCode::SUCCESS;
+            for input in &inputs {
+                if let Err(code) = format_input(input, check, width) {
+                    status = code;
+                }
+            }
+            status
+        }
+    }
+}
+
+/// Runs a script, or a compiled file on the virtual machine.
+fn run_input(input: &Input, engine: Engine) -> Result<(), ExitCode> {
+    let (name, bytes) = read_bytes(input)?;
+    if bytes.starts_with(rkc::MAGIC) {
+        return run_bytecode(&name, "", decode(&name, &bytes)?).map(|_| ());
+    }
+    let source = into_source(&name, bytes)?;
+    let program = parse(&name, &source)?;
+    let file = match input {
+        Input::File(path) => Some(path.as_path()),
+        Input::Stdin => None,
+    };
+    evaluate(&name, &source, &program, file, engine).map(|_| ())
+}
+
+/// Compiles a script to `output`, or by default to a `.rkc` file next to
+/// it; compiling stdin writes to stdout.
+fn compile_input(input: &Input, output: Option<PathBuf>) -> Result<(), ExitCode> {
+    let (name, source) = read_input(input)?;
+    let bytecode = compile(&name, &source, &parse(&name, &source)?)?;
+    let bytes = rkc::encode(&bytecode);
+    let output = match (output, input) {
+        (Some(output), _) => output,
+        (None, Input::File(path)) => path.with_extension("rkc"),
+        (None, Input::Stdin) => {
+            return io::stdout().lock().write_all(&bytes).map_err(|err| {
+                eprintln!("runkey: cannot write output: {}", err);
+                ExitCode::FAILURE
+            });
+        }
+    };
+    std::fs::write(&output, bytes).map_err(|err| {
+        eprintln!("runkey: cannot write {}: {}", output.display(), err);
+        ExitCode::FAILURE
+    })
+}
+
+/// Prints the bytecode of a compiled file, or of a script compiled now, with
+/// the script's lines alongside.
+fn disassemble_input(input: &Input) -> Result<(), ExitCode> {
+    let (name, bytes) = read_bytes(input)?;
+    if bytes.starts_with(rkc::MAGIC) {
+        return write_stdout(&decode(&name, &bytes)?.disassemble(None));
+    }
+    let source = into_source(&name, bytes)?;
+    let bytecode = compile(&name, &source, &parse(&name, &source)?)?;
+    write_stdout(&bytecode.disassemble(Some(&source)))
+}
+
+/// Formats one script. A file is rewritten in place, and stdin is written to
+/// stdout; with `check`, the name of a script that is not formatted is
+/// printed instead, and the script is reported as failed.
+fn format_input(input: &Input, check: bool, width: usize) -> Result<(), ExitCode> {
+    let (name, source) = read_input(input)?;
+    let formatted = match formatter::format(&source, width) {
+        Ok(formatted) => formatted,
+        Err(errors) => {
+            let diagnostics: Vec<diagnostics::Diagnostic> = errors.iter().map(Into::into).collect();
+            report(&name, &source, &diagnostics);
+            return Err(ExitCode::from(EXIT_DATAERR));
+        }
+    };
+    if check {
+        if formatted == source {
+            return Ok(());
+        }
+        write_stdout(&format!("{}\n", name))?;
+        return Err(ExitCode::FAILURE);
+    }
+    match input {
+        Input::Stdin => write_stdout(&formatted),
+        Input::File(_) if formatted == source => Ok(()),
+        Input::File(path) => std::fs::write(path, formatted).map_err(|err| {
+            eprintln!("runkey: cannot write {}: {}", path.display(), err);
+            ExitCode::FAILURE
+        }),
+    }
+}
+
+/// Reads the script named by `input` and hands it to `f`, mapping failures to
+/// an exit status.
+fn with_source(input: &Input, f: impl FnOnce(&str, &str) -> Result<(), ExitCode>) -> ExitCode {
+    let (name, source) = match read_input(input) {
+        Ok(loaded) => loaded,
+        Err(code) => return code,
+    };
+    match f(&name, &source) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(code) => code,
+    }
+}
+
+fn read_input(input: &Input) -> Result<(String, String), ExitCode> {
+    let (name, bytes) = read_bytes(input)?;
+    let source = into_source(&name, bytes)?;
+    Ok((name, source))
+}
+
+/// Reads a script or compiled file, which need not be text.
+fn read_bytes(input: &Input) -> Result<(String, Vec<u8>), ExitCode> {
+    match input {
+        Input::File(path) => match std::fs::read(path) {
+            Ok(bytes) => Ok((path.display().to_string(), bytes)),
+            Err(err) => {
+                eprintln!("runkey: cannot read {}: {}", path.display(), err);
+                Err(ExitCode::from(EXIT_NOINPUT))
+            }
+        },
+        Input::Stdin => {
+            let mut bytes = Vec::new();
+            match io::stdin().read_to_end(&mut bytes) {
+                Ok(_) => Ok(("<stdin>".to_string(), bytes)),
+                Err(err) => {
+                    eprintln!("runkey: cannot read stdin: {}", err);
+                    Err(ExitCode::from(EXIT_NOINPUT))
+                }
+            }
+        }
+    }
+}
+
+fn into_source(name: &str, bytes: Vec<u8>) -> Result<String, ExitCode> {
+    String::from_utf8(bytes).map_err(|_| {
+        eprintln!("runkey: cannot read {}: stream did not contain valid UTF-8", name);
+        ExitCode::from(EXIT_NOINPUT)
+    })
+}
+
+/// Writes `text` to stdout; a closed pipe (as in `runkey ast f.rk | head`)
+/// is not an error.
+fn write_stdout(text: &str) -> Result<(), ExitCode> {
+    match io::stdout().lock().write_all(text.as_bytes()) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        Err(err) => {
+            eprintln!("runkey: cannot write output: {}", err);
+            Err(ExitCode::FAILURE)
+        }
+    }
+}
+
+fn report(name: &str, source: &str, diagnostics: &[diagnostics::Diagnostic]) {
+    let stderr = io::stderr();
+    let _ = diagnostics::Renderer::for_stream(&stderr).emit(&mut stderr.lock(), diagnostics, name, source);
+}
+
+fn parse(name: &str, source: &str) -> Result<ast::Program, ExitCode> {
+    let mut p = parser::Parser::new(lexer::Lexer::new(source.to_string()));
+    let program = p.parse_program();
+    if p.errors().is_empty() {
+        return Ok(program);
+    }
+    let diagnostics: Vec<diagnostics::Diagnostic> = p.errors().iter().map(Into::into).collect();
+    report(name, source, &diagnostics);
+    Err(ExitCode::from(EXIT_DATAERR))
+}
+
+/// Runs a program, read from `file` if it came from one, so that its
+/// imports are found next to it.
+fn evaluate(
+    name: &str,
+    source: &str,
+    program: &ast::Program,
+    file: Option<&Path>,
+    engine: Engine,
+) -> Result<object::Object, ExitCode> {
+    match engine {
+        Engine::Tree => {
+            let mut env = environment::Environment::new();
+            let mut loader = match file {
+                Some(_) => modules::Loader::from_env(),
+                None => modules::Loader::from_env().with_working_directory(),
+            };
+            let (result, _) = modules::with_loader(&mut loader, file, || {
+                limits::with_limits(&limits::Limits::default(), || evaluator::eval(program, &mut env))
+            });
+            finish(name, source, result)
+        }
+        Engine::Vm => run_bytecode(name, source, compile(name, source, program)?),
+    }
+}
+
+fn compile(name: &str, source: &str, program: &ast::Program) -> Result<compiler::Bytecode, ExitCode> {
+    // Bytecode holds a single file, so a script that imports modules can
+    // only run on the tree engine.
+    let import = program.statements.iter().find_map(|statement| match statement {
+        ast::Statement::ImportStatement(path, _, span) => Some((path, *span)),
+        _ => None,
+    });
+    if let Some((path, span)) = import {
+        let diagnostic = diagnostics::Diagnostic::error("scripts that import modules cannot be compiled")
+            .with_label(span, format!("imports \"{}\"", path))
+            .with_note("only the tree engine loads modules; bytecode holds a single file")
+            .with_help("run the script with `--engine=tree`, the default");
+        report(name, source, &[diagnostic]);
+        return Err(ExitCode::from(EXIT_DATAERR));
+    }
+    let mut c = compiler::Compiler::new();
+    match limits::with_limits(&limits::Limits::default(), || c.compile(program)).0 {
+        Ok(()) => Ok(c.bytecode()),
+        Err(message) => {
+            report(name, source, &[diagnostics::Diagnostic::error(message)]);
+            Err(ExitCode::from(EXIT_DATAERR))
+        }
+    }
+}
+
+fn decode(name: &str, bytes: &[u8]) -> Result<compiler::Bytecode, ExitCode> {
+    rkc::decode(bytes).map_err(|err| {
+        report(name, "", &[diagnostics::Diagnostic::error(err.to_string())]);
+        ExitCode::from(EXIT_DATAERR)
+    })
+}
+
+fn run_bytecode(name: &str, source: &str, bytecode: compiler::Bytecode) -> Result<object::Object, ExitCode> {
+    let (result, _) = limits::with_limits(&limits::Limits::default(), || vm::VM::new(bytecode).run());
+    finish(name, source, result)
+}
+
+/// Reports a program's result if it is an error.
+fn finish(name: &str, source: &str, result: object::Object) -> Result<object::Object, ExitCode> {
+    match result {
+        object::Object::Error(message) => {
+            report(name, source, &[diagnostics::Diagnostic::error(message)]);
+            Err(ExitCode::from(EXIT_SOFTWARE))
+        }
+        value => Ok(value),
+    }
+}
+
+fn dump_tokens(name: &str, source: &str) -> Result<(), ExitCode> {
+    let mut l = lexer::Lexer::new(source.to_string());
+    let mut out = String::new();
+    let mut diagnostics = Vec::new();
+    loop {
+        let tok = l.next_token();
+        let _ = writeln!(out, "{:<8} {:<8} {}", tok.span.to_string(), tok.kind.name(), tok.kind);
+        match tok.kind {
+            token::TokenKind::Eof => break,
+            token::TokenKind::Illegal(err) => {
+                let err = error::ParseError::InvalidToken { error: err, span: tok.span };
+                diagnostics.push((&err).into());
+            }
+            _ => {}
+        }
+    }
+    write_stdout(&out)?;
+    if diagnostics.is_empty() {
+        Ok(())
+    } else {
+        report(name, source, &diagnostics);
+        Err(ExitCode::from(EXIT_DATAERR))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn commands() {
+        let tests = [
+            (vec!["run", "a.rk"], Command::Run(Input::File(PathBuf::from("a.rk")), Engine::Tree)),
+            (vec!["a.rk"], Command::Run(Input::File(PathBuf::from("a.rk")), Engine::Tree)),
+            (vec!["-"], Command::Run(Input::Stdin, Engine::Tree)),
+            (vec!["run", "-"], Command::Run(Input::Stdin, Engine::Tree)),
+            (vec!["-e", "1 + 2"], Command::Eval("1 + 2".to_string(), Engine::Tree)),
+            (vec!["--engine=vm", "run", "a.rk"], Command::Run(Input::File(PathBuf::from("a.rk")), Engine::Vm)),
+            (vec!["--engine=vm", "--engine=tree", "-"], Command::Run(Input::Stdin, Engine::Tree)),
+            (vec!["--engine=vm", "-e", "1"], Command::Eval("1".to_string(), Engine::Vm)),
+            (vec!["run", "--engine=vm", "a.rk"], Command::Run(Input::File(PathBuf::from("a.rk")), Engine::Vm)),
+            (vec!["run", "a.rk", "--engine=vm"], Command::Run(Input::File(PathBuf::from("a.rk")), Engine::Vm)),
+            (vec!["-e", "--engine=vm", "1"], Command::Eval("1".to_string(), Engine::Vm)),
+            (vec!["--engine=tree", "-e", "1", "--engine=vm"], Command::Eval("1".to_string(), Engine::Vm)),
+            (vec!["tokens", "a.rk"], Command::Tokens(Input::File(PathBuf::from("a.rk")))),
+            (vec!["ast", "-"], Command::Ast(Input::Stdin)),
+            (vec!["compile", "a.rk"], Command::Compile {
+                input: Input::File(PathBuf::from("a.rk")),
+                output: None,
+            }),
+            (vec!["compile", "-o", "b.rkc", "-"], Command::Compile {
+                input: Input::Stdin,
+                output: Some(PathBuf::from("b.rkc")),
+            }),
+            (vec!["disasm", "a.rkc"], Command::Disasm(Input::File(PathBuf::from("a.rkc")))),
+            (vec!["fmt", "a.rk"], Command::Fmt {
+                inputs: vec![Input::File(PathBuf::from("a.rk"))],
+                check: false,
+                width: formatter::DEFAULT_WIDTH,
+            }),
+            (vec!["fmt", "--check", "a.rk", "--width", "80", "-"], Command::Fmt {
+                inputs: vec![Input::File(PathBuf::from("a.rk")), Input::Stdin],
+                check: true,
+                width: 80,
+            }),
+            (vec!["--help"], Command::Help),
+            (vec!["-V"], Command::Version),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(parse_args(&args(&input)), Ok(expected), "args: {:?}", input);
+        }
+    }
+
+    #[test]
+    fn usage_errors() {
+        let tests = [
+            (vec!["-e"], "`-e` needs source to evaluate"),
+            (vec!["run"], "missing script path"),
+            (vec!["--bogus"], "unknown option `--bogus`"),
+            (vec!["run", "a.rk", "b.rk"], "unexpected argument `b.rk`"),
+            (vec!["a.rk", "b.rk"], "unexpected argument `b.rk`"),
+            (vec!["fmt", "--check"], "missing script path"),
+            (vec!["fmt", "a.rk", "--width", "wide"], "`--width` needs a positive number, got `wide`"),
+            (vec!["fmt", "a.rk", "--width"], "`--width` needs a positive number"),
+            (vec!["fmt", "--diff", "a.rk"], "unknown option `--diff`"),
+            (vec!["compile"], "missing script path"),
+            (vec!["compile", "a.rk", "-o"], "`-o` needs an output path"),
+            (vec!["compile", "a.rk", "b.rk"], "unexpected argument `b.rk`"),
+            (vec!["disasm"], "missing script path"),
+            (vec!["--engine=jit", "a.rk"], "unknown engine `jit`, expected `tree` or `vm`"),
+            (vec!["--engine=vm", "ast", "a.rk"], "`--engine` only applies to running a script or `-e`"),
+            (vec!["ast", "--engine=vm", "a.rk"], "unexpected argument `a.rk`"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(parse_args(&args(&input)), Err(expected.to_string()), "args: {:?}", input);
+        }
+    }
 }
@@ -0,0 +1,91 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::object::*;
+
+/// A lexical scope of bindings.
+///
+/// Cloning an `Environment` yields another handle to the same scope, so
+/// bindings made through one handle are visible through all of them.
+#[derive(Debug, Clone, Default)]
+pub struct Environment {
+    store: Rc<RefCell<HashMap<String, object::Object>>>,
+    outer: Option<Box<Environment>>,
+}
+
+impl Environment {
+    pub fn new() -> Environment {
+        Environment::default()
+    }
+
+    /// Creates a scope nested in `outer`; lookups that miss fall through to it.
+    pub fn new_enclosed(outer: &Environment) -> Environment {
+        Environment {
+            store: Rc::default(),
+            outer: Some(Box::new(outer.clone())),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<object::Object> {
+        match self.store.borrow().get(name) {
+            Some(value) => Some(value.clone()),
+            None => self.outer.as_ref().and_then(|outer| outer.get(name)),
+        }
+    }
+
+    pub fn set(&mut self, name: String, value: object::Object) {
+        self.store.borrow_mut().insert(name, value);
+    }
+
+    /// Rebinds `name` in the innermost scope that defines it. Returns false,
+    /// binding nothing, if no scope does.
+    pub fn assign(&mut self, name: &str, value: object::Object) -> bool {
+        self.update(name, move |slot| *slot = value).is_some()
+    }
+
+    /// Calls `f` with the binding of `name` in the innermost scope that
+    /// defines it, so the value can be modified in place. Returns `None`
+    /// without calling `f` if no scope defines the name.
+    pub fn update<T>(&mut self, name: &str, f: impl FnOnce(&mut object::Object) -> T) -> Option<T> {
+        if let Some(slot) = self.store.borrow_mut().get_mut(name) {
+            return Some(f(slot));
+        }
+        match &mut self.outer {
+            Some(outer) => outer.update(name, f),
+            None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::object::*;
+
+    #[test]
+    fn enclosed_lookup() {
+        let mut outer = environment::Environment::new();
+        outer.set("x".to_string(), object::Object::Integer(1));
+        let mut inner = environment::Environment::new_enclosed(&outer);
+        inner.set("y".to_string(), object::Object::Integer(2));
+
+        assert_eq!(inner.get("x"), Some(object::Object::Integer(1)));
+        assert_eq!(inner.get("y"), Some(object::Object::Integer(2)));
+        assert_eq!(outer.get("y"), None);
+
+        inner.set("x".to_string(), object::Object::Integer(3));
+        assert_eq!(outer.get("x"), Some(object::Object::Integer(1)));
+    }
+
+    #[test]
+    fn assign_rebinds_defining_scope() {
+        let mut outer = environment::Environment::new();
+        outer.set("x".to_string(), object::Object::Integer(1));
+        let mut inner = environment::Environment::new_enclosed(&outer);
+
+        assert!(inner.assign("x", object::Object::Integer(2)));
+        assert_eq!(outer.get("x"), Some(object::Object::Integer(2)));
+        assert!(!inner.assign("y", object::Object::Integer(3)));
+        assert_eq!(inner.get("y"), None);
+    }
+}
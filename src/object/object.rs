@@ -0,0 +1,324 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::ast::*;
+use crate::object::*;
+use crate::token::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Object {
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    String(String),
+    /// Arrays share their elements until one handle modifies them.
+    Array(Rc<Vec<Object>>),
+    Hash(Rc<Hash>),
+    /// The integers from the first up to but excluding the second.
+    Range(i64, i64),
+    Null,
+    /// Wraps the value of a `return` while it unwinds to the enclosing
+    /// function or program.
+    ReturnValue(Box<Object>),
+    /// A `break` or `continue`, with its label if any, unwinding to the loop
+    /// it targets.
+    Break(Option<String>),
+    Continue(Option<String>),
+    Error(String),
+    Function(Rc<Function>),
+    Builtin(Builtin),
+    /// A function the host program registered through `engine::Engine`.
+    Native(Rc<Native>),
+    /// A function compiled to bytecode, as it sits in the constant pool.
+    CompiledFunction(Rc<CompiledFunction>),
+    /// A compiled function together with the variables it captures.
+    Closure(Rc<Closure>),
+    /// A variable the virtual machine shares between the scope that binds it
+    /// and the closures that capture it.
+    Cell(Rc<RefCell<Object>>),
+}
+
+impl Object {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::Integer(_) => "INTEGER",
+            Object::Float(_) => "FLOAT",
+            Object::Boolean(_) => "BOOLEAN",
+            Object::String(_) => "STRING",
+            Object::Array(_) => "ARRAY",
+            Object::Hash(_) => "HASH",
+            Object::Range(..) => "RANGE",
+            Object::Null => "NULL",
+            Object::ReturnValue(_) => "RETURN_VALUE",
+            Object::Break(_) => "BREAK",
+            Object::Continue(_) => "CONTINUE",
+            Object::Error(_) => "ERROR",
+            Object::Function(_) => "FUNCTION",
+            Object::Builtin(_) | Object::Native(_) => "BUILTIN",
+            Object::CompiledFunction(_) => "COMPILED_FUNCTION",
+            Object::Closure(_) => "FUNCTION",
+            Object::Cell(_) => "CELL",
+        }
+    }
+
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Object::Null => false,
+            Object::Boolean(value) => *value,
+            _ => true,
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        matches!(self, Object::Error(_))
+    }
+
+    /// The key this value is stored under in a hash, or `None` if it cannot
+    /// be a key.
+    pub fn hash_key(&self) -> Option<HashKey> {
+        match self {
+            Object::Integer(value) => Some(HashKey::Integer(*value)),
+            Object::Boolean(value) => Some(HashKey::Boolean(*value)),
+            Object::String(value) => Some(HashKey::String(value.clone())),
+            _ => None,
+        }
+    }
+
+    /// Writes the value as it appears inside an array or hash, where strings
+    /// are quoted so `["a, b"]` and `["a", "b"]` differ.
+    fn fmt_nested(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Object::String(value) => write!(f, "\"{}\"", token::escape(value)),
+            value => write!(f, "{}", value),
+        }
+    }
+}
+
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Object::Integer(value) => write!(f, "{}", value),
+            // `{:?}` keeps the fractional part of whole floats, e.g. `2.0`.
+            Object::Float(value) => write!(f, "{:?}", value),
+            Object::Boolean(value) => write!(f, "{}", value),
+            Object::String(value) => write!(f, "{}", value),
+            Object::Array(elements) => {
+                write!(f, "[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    element.fmt_nested(f)?;
+                }
+                write!(f, "]")
+            }
+            Object::Hash(hash) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in hash.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    key.fmt_nested(f)?;
+                    write!(f, ": ")?;
+                    value.fmt_nested(f)?;
+                }
+                write!(f, "}}")
+            }
+            Object::Range(start, end) => write!(f, "{}..{}", start, end),
+            Object::Null => write!(f, "null"),
+            Object::ReturnValue(value) => write!(f, "{}", value),
+            Object::Break(_) => write!(f, "break"),
+            Object::Continue(_) => write!(f, "continue"),
+            Object::Error(message) => write!(f, "ERROR: {}", message),
+            Object::Function(function) => write!(f, "{}", function),
+            Object::Builtin(builtin) => write!(f, "{}", builtin),
+            Object::Native(native) => write!(f, "{}", native),
+            Object::CompiledFunction(function) => write!(f, "{}", function),
+            Object::Closure(closure) => write!(f, "{}", closure.function),
+            Object::Cell(cell) => write!(f, "{}", cell.borrow()),
+        }
+    }
+}
+
+/// A function value together with the environment it closes over.
+#[derive(Clone)]
+pub struct Function {
+    pub parameters: Vec<ast::Identifier>,
+    pub body: ast::BlockStatement,
+    pub env: environment::Environment,
+}
+
+impl PartialEq for Function {
+    /// Functions are equal only to themselves; the captured environment may
+    /// contain the function itself, so a structural comparison could recurse.
+    fn eq(&self, other: &Function) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let params: Vec<&str> = self.parameters.iter().map(|p| p.value.as_str()).collect();
+        write!(f, "fn({}) {{ ... }}", params.join(", "))
+    }
+}
+
+/// A function body compiled by `compiler::Compiler`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompiledFunction {
+    pub instructions: Vec<u8>,
+    /// How many stack slots the function's parameters and local bindings
+    /// take, parameters first.
+    pub num_locals: usize,
+    /// How many variables the function captures, which `OpClosure` takes
+    /// from the stack.
+    pub num_free: usize,
+    pub parameters: Vec<String>,
+    /// Which source line each run of instructions comes from, as
+    /// `(offset, line)` pairs in offset order: the code from each offset up
+    /// to the next pair's comes from that line.
+    pub lines: Vec<(usize, usize)>,
+}
+
+impl CompiledFunction {
+    /// The source line of the instruction at `offset`, if known.
+    pub fn line(&self, offset: usize) -> Option<usize> {
+        let i = self.lines.partition_point(|&(start, _)| start <= offset);
+        i.checked_sub(1).map(|i| self.lines[i].1)
+    }
+}
+
+impl fmt::Display for CompiledFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "fn({}) {{ ... }}", self.parameters.join(", "))
+    }
+}
+
+/// A compiled function value. `free` holds a `Cell` for each variable of
+/// enclosing functions that the body uses.
+#[derive(Debug, Clone)]
+pub struct Closure {
+    pub function: Rc<CompiledFunction>,
+    pub free: Vec<Object>,
+}
+
+impl PartialEq for Closure {
+    /// Like `Function`, a closure is equal only to itself.
+    fn eq(&self, other: &Closure) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// The identity of a hash key. Only strings, integers and booleans can be
+/// keys; `1` and `"1"` are different keys.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum HashKey {
+    Integer(i64),
+    Boolean(bool),
+    String(String),
+}
+
+/// A hash map that iterates in insertion order, so printing a hash is
+/// deterministic.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Hash {
+    pairs: Vec<(Object, Object)>,
+    index: HashMap<HashKey, usize>,
+}
+
+impl Hash {
+    pub fn new() -> Hash {
+        Hash::default()
+    }
+
+    pub fn get(&self, key: &HashKey) -> Option<&Object> {
+        self.index.get(key).map(|&i| &self.pairs[i].1)
+    }
+
+    /// Binds `key` to `value`. Rebinding an existing key keeps its original
+    /// position.
+    pub fn insert(&mut self, hash_key: HashKey, key: Object, value: Object) {
+        match self.index.get(&hash_key) {
+            Some(&i) => self.pairs[i].1 = value,
+            None => {
+                self.index.insert(hash_key, self.pairs.len());
+                self.pairs.push((key, value));
+            }
+        }
+    }
+
+    pub fn get_mut(&mut self, key: &HashKey) -> Option<&mut Object> {
+        self.index.get(key).map(|&i| &mut self.pairs[i].1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// The key-value pairs in insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = (&Object, &Object)> {
+        self.pairs.iter().map(|(key, value)| (key, value))
+    }
+}
+
+/// A function implemented natively, such as `len`.
+#[derive(Clone, Copy)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub func: fn(Vec<Object>) -> Object,
+}
+
+impl PartialEq for Builtin {
+    fn eq(&self, other: &Builtin) -> bool {
+        self.name == other.name
+    }
+}
+
+impl fmt::Debug for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl fmt::Display for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "builtin function {}", self.name)
+    }
+}
+
+/// A builtin that can capture state, made from a Rust closure.
+pub struct Native {
+    pub name: String,
+    pub func: Box<dyn Fn(Vec<Object>) -> Object>,
+}
+
+impl PartialEq for Native {
+    /// Like `Function`, a native function is equal only to itself.
+    fn eq(&self, other: &Native) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for Native {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl fmt::Display for Native {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "builtin function {}", self.name)
+    }
+}
@@ -0,0 +1,136 @@
+use std::fmt;
+
+use crate::parser::*;
+use crate::token::*;
+
+/// An error found while parsing, located at `span()`.
+///
+/// `Display` renders only the message; callers decide how to show the
+/// location.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// A specific token was required but another one was found.
+    UnexpectedToken {
+        expected: String,
+        found: token::TokenKind,
+        span: token::Span,
+    },
+    /// The token cannot start an expression.
+    NoPrefixParseFn {
+        found: token::TokenKind,
+        span: token::Span,
+    },
+    /// The input ended before the `}` closing the block opened at `span`.
+    UnterminatedBlock {
+        span: token::Span,
+    },
+    InvalidInteger {
+        literal: String,
+        span: token::Span,
+    },
+    /// A float literal too large to represent, such as `1e999`.
+    InvalidFloat {
+        literal: String,
+        span: token::Span,
+    },
+    /// The left-hand side of an assignment is not something that can be
+    /// assigned to.
+    InvalidAssignmentTarget {
+        span: token::Span,
+    },
+    /// An assignment to a name bound with plain `let`, or to a function
+    /// parameter or loop variable. `declared` is where the binding was made,
+    /// if it is in the same source.
+    ImmutableAssignment {
+        name: String,
+        span: token::Span,
+        declared: Option<token::Span>,
+    },
+    /// An assignment to a name that is not bound in any enclosing scope.
+    UndeclaredAssignment {
+        name: String,
+        span: token::Span,
+    },
+    /// `break` or `continue` appears outside any loop of the current
+    /// function.
+    OutsideLoop {
+        keyword: token::TokenKind,
+        span: token::Span,
+    },
+    /// `break` or `continue` names a label no enclosing loop has.
+    UndeclaredLabel {
+        label: String,
+        span: token::Span,
+    },
+    /// `import` or `export` appears inside a block or function.
+    NotTopLevel {
+        keyword: token::TokenKind,
+        span: token::Span,
+    },
+    /// Expressions nest more than `MAX_NESTING` deep here.
+    TooDeeplyNested {
+        span: token::Span,
+    },
+    /// The lexer could not make sense of the input here.
+    InvalidToken {
+        error: token::LexError,
+        span: token::Span,
+    },
+}
+
+impl ParseError {
+    pub fn span(&self) -> token::Span {
+        match self {
+            ParseError::UnexpectedToken { span, .. }
+            | ParseError::NoPrefixParseFn { span, .. }
+            | ParseError::UnterminatedBlock { span }
+            | ParseError::InvalidInteger { span, .. }
+            | ParseError::InvalidFloat { span, .. }
+            | ParseError::InvalidAssignmentTarget { span }
+            | ParseError::ImmutableAssignment { span, .. }
+            | ParseError::UndeclaredAssignment { span, .. }
+            | ParseError::OutsideLoop { span, .. }
+            | ParseError::UndeclaredLabel { span, .. }
+            | ParseError::NotTopLevel { span, .. }
+            | ParseError::TooDeeplyNested { span }
+            | ParseError::InvalidToken { span, .. } => *span,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::UnexpectedToken { expected, found, .. } => write!(
+                f,
+                "expected next token to be {}, got {} instead",
+                expected,
+                found.name(),
+            ),
+            ParseError::NoPrefixParseFn { found, .. } => {
+                write!(f, "no prefix parse function for {} found", found.name())
+            }
+            ParseError::UnterminatedBlock { .. } => write!(f, "unterminated block: expected }}"),
+            ParseError::InvalidInteger { literal, .. } => {
+                write!(f, "could not parse {} as integer", literal)
+            }
+            ParseError::InvalidFloat { literal, .. } => {
+                write!(f, "could not parse {} as float", literal)
+            }
+            ParseError::InvalidAssignmentTarget { .. } => write!(f, "invalid assignment target"),
+            ParseError::ImmutableAssignment { name, .. } => {
+                write!(f, "cannot assign to immutable binding `{}`", name)
+            }
+            ParseError::UndeclaredAssignment { name, .. } => write!(f, "cannot assign to undeclared name `{}`", name),
+            ParseError::OutsideLoop { keyword, .. } => write!(f, "`{}` outside of a loop", keyword),
+            ParseError::UndeclaredLabel { label, .. } => write!(f, "use of undeclared label `{}`", label),
+            ParseError::NotTopLevel { keyword, .. } => write!(f, "`{}` is only allowed at the top level", keyword),
+            ParseError::TooDeeplyNested { .. } => {
+                write!(f, "expressions nested more than {} deep", parser::MAX_NESTING)
+            }
+            ParseError::InvalidToken { error, .. } => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
@@ -1,115 +1,1014 @@
 use crate::token::*;
 use crate::lexer::*;
 use crate::ast::*;
+use crate::parser::*;
+
+use std::collections::HashMap;
+
+/// Binding power of operators, from loosest to tightest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Precedence {
+    Lowest,
+    Assign,
+    Or,
+    And,
+    Equals,
+    LessGreater,
+    Range,
+    Sum,
+    Product,
+    Prefix,
+    Power,
+    Call,
+    Index,
+}
+
+impl Precedence {
+    pub fn of(kind: &token::TokenKind) -> Precedence {
+        match kind {
+            token::TokenKind::PlusAssign
+            | token::TokenKind::MinusAssign
+            | token::TokenKind::AsteriskAssign
+            | token::TokenKind::SlashAssign
+            | token::TokenKind::Assign => Precedence::Assign,
+            token::TokenKind::Or => Precedence::Or,
+            token::TokenKind::And => Precedence::And,
+            token::TokenKind::Eq | token::TokenKind::NotEq => Precedence::Equals,
+            token::TokenKind::Lt | token::TokenKind::Gt | token::TokenKind::LtEq | token::TokenKind::GtEq => {
+                Precedence::LessGreater
+            }
+            token::TokenKind::DotDot => Precedence::Range,
+            token::TokenKind::Plus | token::TokenKind::Minus => Precedence::Sum,
+            token::TokenKind::Asterisk | token::TokenKind::Slash | token::TokenKind::Percent => Precedence::Product,
+            token::TokenKind::Power => Precedence::Power,
+            token::TokenKind::LParen => Precedence::Call,
+            token::TokenKind::LBracket => Precedence::Index,
+            _ => Precedence::Lowest,
+        }
+    }
+}
+
+/// How deeply expressions may nest, counting the blocks and function
+/// bodies inside them, so that parsing a program cannot overflow the stack
+/// however the program is written. A chain of operators such as
+/// `1 + 2 + 3` does not count, as the parser reads it in a loop; the
+/// evaluator and compiler check the stack as they walk it.
+pub const MAX_NESTING: usize = 256;
+
+/// What the parser knows about a name bound by `let`, `var`, a function
+/// parameter or a loop variable.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Binding {
+    pub mutable: bool,
+    /// Where the name was bound, or `None` for names declared through
+    /// `Parser::declare_globals`.
+    pub span: Option<token::Span>,
+}
+
+type PrefixParseFn = fn(&mut Parser) -> Option<ast::Expression>;
+type InfixParseFn = fn(&mut Parser, ast::Expression) -> Option<ast::Expression>;
 
 pub struct Parser {
     pub l: lexer::Lexer,
     pub cur_token: token::Token,
     pub peek_token: token::Token,
-    pub errors: Vec<String>,
+    pub errors: Vec<error::ParseError>,
+    /// Doc comment lines read just before `cur_token` and `peek_token`.
+    cur_doc: Vec<String>,
+    peek_doc: Vec<String>,
+    /// How many `{` are open as of `cur_token`, counting it.
+    brace_depth: usize,
+    /// How deeply the expressions and blocks being parsed nest as of
+    /// `cur_token`.
+    nesting: usize,
+    /// The labels of the loops enclosing `cur_token` within the current
+    /// function, innermost last.
+    loops: Vec<Option<String>>,
+    /// The names bound in each enclosing scope, outermost (the program's
+    /// globals) first. Scopes follow the evaluator's environments: one per
+    /// function body and one per `for` body. The blocks of `while` and `if`
+    /// are not scopes, so names bound in them stay bound after the block.
+    scopes: Vec<HashMap<String, Binding>>,
 }
 
 impl Parser {
     pub fn new(l: lexer::Lexer) -> Parser {
         let mut p = Parser {
-            l: l,
-            cur_token: token::Token {
-                literal: "".to_string(),
-                r#type: token::ILLEGAL.to_string(),
-            },
-            peek_token: token::Token {
-                literal: "".to_string(),
-                r#type: token::ILLEGAL.to_string(),
-            },
+            l,
+            cur_token: token::Token::new(token::TokenKind::Eof, token::Span::default()),
+            peek_token: token::Token::new(token::TokenKind::Eof, token::Span::default()),
             errors: vec![],
+            cur_doc: vec![],
+            peek_doc: vec![],
+            brace_depth: 0,
+            nesting: 0,
+            loops: vec![],
+            scopes: vec![HashMap::new()],
         };
         p.next_token();
         p.next_token();
         p
     }
 
+    /// Advances by one token. Doc comments never become the current token;
+    /// they are set aside for `parse_let_statement` to pick up, and dropped
+    /// anywhere else.
     pub fn next_token(&mut self) {
-        self.cur_token = self.peek_token.clone();
-        self.peek_token = self.l.next_token();
+        self.cur_doc = std::mem::take(&mut self.peek_doc);
+        let mut tok = self.l.next_token();
+        while let token::TokenKind::DocComment(text) = tok.kind {
+            self.peek_doc.push(text);
+            tok = self.l.next_token();
+        }
+        self.cur_token = std::mem::replace(&mut self.peek_token, tok);
+        match self.cur_token.kind {
+            token::TokenKind::LBrace => self.brace_depth += 1,
+            token::TokenKind::RBrace => self.brace_depth = self.brace_depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+
+    /// Declares names bound before this source, such as by earlier lines in
+    /// the REPL, so assignments to them can be checked.
+    pub fn declare_globals<'a>(&mut self, globals: impl IntoIterator<Item = (&'a str, bool)>) {
+        for (name, mutable) in globals {
+            self.scopes[0].insert(name.to_string(), Binding { mutable, span: None });
+        }
+    }
+
+    /// The names bound at the top level, with whether each is mutable.
+    pub fn globals(&self) -> impl Iterator<Item = (&str, bool)> {
+        self.scopes[0].iter().map(|(name, binding)| (name.as_str(), binding.mutable))
+    }
+
+    fn declare(&mut self, ident: &ast::Identifier, mutable: bool) {
+        let scope = self.scopes.last_mut().expect("the global scope is never popped");
+        scope.insert(ident.value.clone(), Binding { mutable, span: Some(ident.span) });
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Binding> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
     }
 
     pub fn parse_program(&mut self) -> ast::Program {
         let mut program = ast::Program {
             statements: Vec::new(),
         };
-        while self.cur_token.r#type != token::EOF.to_string() {
-            let stmt = self.parse_statement();
-            if let Some(s) = stmt {
-                program.statements.push(s);
+        while !self.cur_token_is(&token::TokenKind::Eof) {
+            if let Some(stmt) = self.parse_next_statement() {
+                program.statements.push(stmt);
             }
-            self.next_token();
         }
         program
     }
 
+    /// Parses the statement at the current token and moves on to the token
+    /// after it. If the statement is malformed, its error has already been
+    /// recorded; the parser skips ahead to the next statement and returns
+    /// `None`, so one mistake yields one error.
+    fn parse_next_statement(&mut self) -> Option<ast::Statement> {
+        let start = self.cur_token.span;
+        let depth = self.brace_depth - usize::from(self.cur_token_is(&token::TokenKind::LBrace));
+        match self.parse_statement() {
+            Some(stmt) => {
+                self.next_token();
+                Some(stmt)
+            }
+            None => {
+                self.synchronize(start, depth);
+                None
+            }
+        }
+    }
+
+    /// Skips tokens until the start of the next statement: just past a `;`,
+    /// or at a statement keyword, a `}` closing the enclosing block, or the
+    /// end of input. Braces opened by the failed statement, whether blocks or
+    /// hash literals, are skipped whole; `depth` is the nesting the statement
+    /// started at. Always makes progress past the failed statement's first
+    /// token.
+    fn synchronize(&mut self, start: token::Span, depth: usize) {
+        if self.cur_token.span == start && !self.cur_token_is(&token::TokenKind::Eof) {
+            self.next_token();
+        }
+        loop {
+            match self.cur_token.kind {
+                token::TokenKind::Eof => return,
+                token::TokenKind::RBrace if self.brace_depth < depth => return,
+                _ if self.brace_depth > depth || self.cur_token_is(&token::TokenKind::RBrace) => {}
+                token::TokenKind::Semicolon => {
+                    self.next_token();
+                    return;
+                }
+                token::TokenKind::Let
+                | token::TokenKind::Var
+                | token::TokenKind::Return
+                | token::TokenKind::While
+                | token::TokenKind::For
+                | token::TokenKind::Break
+                | token::TokenKind::Continue
+                | token::TokenKind::Import
+                | token::TokenKind::Export => return,
+                _ => {}
+            }
+            self.next_token();
+        }
+    }
+
     pub fn parse_statement(&mut self) -> Option<ast::Statement> {
-        match self.cur_token.r#type.as_str() {
-            token::LET => Some(self.parse_let_statement()),
-            token::RETURN => Some(self.parse_return_statement()),
+        match self.cur_token.kind {
+            token::TokenKind::Let | token::TokenKind::Var => self.parse_let_statement(),
+            token::TokenKind::Return => self.parse_return_statement(),
+            token::TokenKind::While | token::TokenKind::For => self.parse_loop_statement(None),
+            token::TokenKind::Break | token::TokenKind::Continue => self.parse_loop_control_statement(),
+            token::TokenKind::Import => self.parse_import_statement(),
+            token::TokenKind::Export => self.parse_export_statement(),
+            token::TokenKind::Ident(_) if self.peek_token_is(&token::TokenKind::Colon) => {
+                self.parse_labeled_statement()
+            }
+            _ => self.parse_expression_statement(),
+        }
+    }
+
+    /// Parses `label: while ...` or `label: for ...`.
+    pub fn parse_labeled_statement(&mut self) -> Option<ast::Statement> {
+        let label = match &self.cur_token.kind {
+            token::TokenKind::Ident(value) => ast::Identifier { value: value.clone(), span: self.cur_token.span },
+            _ => return None,
+        };
+        self.next_token();
+        if !self.peek_token_is(&token::TokenKind::While) && !self.peek_token_is(&token::TokenKind::For) {
+            self.peek_error("WHILE or FOR");
+            return None;
+        }
+        self.next_token();
+        self.parse_loop_statement(Some(label))
+    }
+
+    /// Parses a `while` or `for` loop starting at its keyword, recording its
+    /// label so `break` and `continue` in the body can be checked.
+    pub fn parse_loop_statement(&mut self, label: Option<ast::Identifier>) -> Option<ast::Statement> {
+        let start = label.as_ref().map_or(self.cur_token.span, |label| label.span);
+        self.loops.push(label.as_ref().map(|label| label.value.clone()));
+        let stmt = if self.cur_token_is(&token::TokenKind::While) {
+            self.parse_while_statement(label, start)
+        } else {
+            self.parse_for_statement(label, start)
+        };
+        self.loops.pop();
+        if stmt.is_some() && self.peek_token_is(&token::TokenKind::Semicolon) {
+            self.next_token();
+        }
+        stmt
+    }
+
+    fn parse_while_statement(&mut self, label: Option<ast::Identifier>, start: token::Span) -> Option<ast::Statement> {
+        if !self.expect_peek(&token::TokenKind::LParen) {
+            return None;
+        }
+        self.next_token();
+        let condition = self.parse_expression(Precedence::Lowest)?;
+        if !self.expect_peek(&token::TokenKind::RParen) || !self.expect_peek(&token::TokenKind::LBrace) {
+            return None;
+        }
+        let body = self.parse_block_statement()?;
+        let span = start.to(body.span);
+        Some(ast::Statement::WhileStatement(label, condition, body, span))
+    }
+
+    fn parse_for_statement(&mut self, label: Option<ast::Identifier>, start: token::Span) -> Option<ast::Statement> {
+        let value = self.expect_peek_ident()?;
+        let variable = ast::Identifier { value, span: self.cur_token.span };
+        if !self.expect_peek(&token::TokenKind::In) {
+            return None;
+        }
+        self.next_token();
+        let iterable = self.parse_expression(Precedence::Lowest)?;
+        if !self.expect_peek(&token::TokenKind::LBrace) {
+            return None;
+        }
+        self.scopes.push(HashMap::new());
+        self.declare(&variable, false);
+        let body = self.parse_block_statement();
+        self.scopes.pop();
+        let body = body?;
+        let span = start.to(body.span);
+        Some(ast::Statement::ForStatement(label, variable, iterable, body, span))
+    }
+
+    /// Parses `break` or `continue` with an optional label, checking that it
+    /// is inside a matching loop.
+    pub fn parse_loop_control_statement(&mut self) -> Option<ast::Statement> {
+        let keyword = self.cur_token.clone();
+        let label = match &self.peek_token.kind {
+            token::TokenKind::Ident(value) => {
+                let value = value.clone();
+                self.next_token();
+                Some(ast::Identifier { value, span: self.cur_token.span })
+            }
+            _ => None,
+        };
+        if self.loops.is_empty() {
+            self.errors.push(error::ParseError::OutsideLoop { keyword: keyword.kind, span: keyword.span });
+            return None;
+        }
+        if let Some(label) = &label {
+            if !self.loops.contains(&Some(label.value.clone())) {
+                self.errors.push(error::ParseError::UndeclaredLabel {
+                    label: label.value.clone(),
+                    span: label.span,
+                });
+                return None;
+            }
+        }
+        if self.peek_token_is(&token::TokenKind::Semicolon) {
+            self.next_token();
+        }
+
+        let span = keyword.span.to(self.cur_token.span);
+        match keyword.kind {
+            token::TokenKind::Break => Some(ast::Statement::BreakStatement(label, span)),
+            _ => Some(ast::Statement::ContinueStatement(label, span)),
+        }
+    }
+
+    /// Parses `let name = value;`, `let mut name = value;` or
+    /// `var name = value;`. The name is in scope within the value, so a
+    /// function can refer to itself.
+    pub fn parse_let_statement(&mut self) -> Option<ast::Statement> {
+        let start = self.cur_token.span;
+        let doc = self.take_doc();
+        let mutable = self.cur_token_is(&token::TokenKind::Var) || self.peek_token_is(&token::TokenKind::Mut);
+        if self.cur_token_is(&token::TokenKind::Let) && mutable {
+            self.next_token();
+        }
+
+        let value = self.expect_peek_ident()?;
+        let ident = ast::Identifier { value, span: self.cur_token.span };
+        self.declare(&ident, mutable);
+
+        if !self.expect_peek(&token::TokenKind::Assign) {
+            return None;
+        }
+        self.next_token();
+
+        let value = self.parse_expression(Precedence::Lowest)?;
+
+        if self.peek_token_is(&token::TokenKind::Semicolon) {
+            self.next_token();
+        }
+
+        Some(ast::Statement::LetStatement(ast::LetStatement {
+            name: ident,
+            value,
+            mutable,
+            doc,
+            span: start.to(self.cur_token.span),
+        }))
+    }
+
+    /// Parses `import "path" as name;` or `import { a, b as c } from "path";`,
+    /// binding the imported names as immutable.
+    pub fn parse_import_statement(&mut self) -> Option<ast::Statement> {
+        let start = self.cur_token.span;
+        if !self.check_top_level() {
+            return None;
+        }
+        let (path, import) = if self.peek_token_is(&token::TokenKind::LBrace) {
+            self.next_token();
+            let names = self.parse_import_names()?;
+            if !self.expect_peek_word("from", "FROM") {
+                return None;
+            }
+            (self.expect_peek_string()?, ast::Import::Names(names))
+        } else {
+            let path = self.expect_peek_string()?;
+            if !self.expect_peek_word("as", "AS") {
+                return None;
+            }
+            let value = self.expect_peek_ident()?;
+            (path, ast::Import::Module(ast::Identifier { value, span: self.cur_token.span }))
+        };
+        match &import {
+            ast::Import::Module(name) => self.declare(name, false),
+            ast::Import::Names(names) => {
+                for (_, binding) in names {
+                    self.declare(binding, false);
+                }
+            }
+        }
+
+        if self.peek_token_is(&token::TokenKind::Semicolon) {
+            self.next_token();
+        }
+
+        Some(ast::Statement::ImportStatement(path, import, start.to(self.cur_token.span)))
+    }
+
+    /// Parses the `{ a, b as c }` of a selective import, starting at the
+    /// `{`. Each name is paired with the one it is bound under.
+    fn parse_import_names(&mut self) -> Option<Vec<(ast::Identifier, ast::Identifier)>> {
+        let mut names = vec![];
+        while !self.peek_token_is(&token::TokenKind::RBrace) {
+            let value = self.expect_peek_ident()?;
+            let name = ast::Identifier { value, span: self.cur_token.span };
+            let binding = if self.peek_word_is("as") {
+                self.next_token();
+                let value = self.expect_peek_ident()?;
+                ast::Identifier { value, span: self.cur_token.span }
+            } else {
+                name.clone()
+            };
+            names.push((name, binding));
+            if !self.peek_token_is(&token::TokenKind::RBrace) && !self.expect_peek(&token::TokenKind::Comma) {
+                return None;
+            }
+        }
+        self.next_token();
+        Some(names)
+    }
+
+    /// Parses `export` followed by a `let` or `var` statement. A doc comment
+    /// above the `export` documents the binding.
+    pub fn parse_export_statement(&mut self) -> Option<ast::Statement> {
+        let start = self.cur_token.span;
+        if !self.check_top_level() {
+            return None;
+        }
+        let doc = self.take_doc();
+        if !self.peek_token_is(&token::TokenKind::Let) && !self.peek_token_is(&token::TokenKind::Var) {
+            self.peek_error("LET or VAR");
+            return None;
+        }
+        self.next_token();
+        let mut stmt = match self.parse_let_statement()? {
+            ast::Statement::LetStatement(stmt) => stmt,
+            _ => unreachable!("parse_let_statement makes a LetStatement"),
+        };
+        stmt.doc = stmt.doc.or(doc);
+        Some(ast::Statement::ExportStatement(stmt, start.to(self.cur_token.span)))
+    }
+
+    /// Records an error if the current token, an `import` or `export`, is
+    /// inside a block.
+    fn check_top_level(&mut self) -> bool {
+        if self.brace_depth == 0 {
+            return true;
+        }
+        self.errors.push(error::ParseError::NotTopLevel {
+            keyword: self.cur_token.kind.clone(),
+            span: self.cur_token.span,
+        });
+        false
+    }
+
+    /// Joins the doc comment lines above the current token, dropping the
+    /// space that conventionally follows `///`.
+    fn take_doc(&mut self) -> Option<String> {
+        if self.cur_doc.is_empty() {
+            return None;
+        }
+        let lines: Vec<&str> = self.cur_doc.iter().map(|line| line.strip_prefix(' ').unwrap_or(line)).collect();
+        let doc = lines.join("\n");
+        self.cur_doc.clear();
+        Some(doc)
+    }
+
+    pub fn parse_return_statement(&mut self) -> Option<ast::Statement> {
+        let start = self.cur_token.span;
+        self.next_token();
+
+        let value = self.parse_expression(Precedence::Lowest)?;
+
+        if self.peek_token_is(&token::TokenKind::Semicolon) {
+            self.next_token();
+        }
+
+        Some(ast::Statement::ReturnStatement (value, start.to(self.cur_token.span)))
+    }
+
+    pub fn parse_expression_statement(&mut self) -> Option<ast::Statement> {
+        let start = self.cur_token.span;
+        let expr = self.parse_expression(Precedence::Lowest)?;
+
+        if self.peek_token_is(&token::TokenKind::Semicolon) {
+            self.next_token();
+        }
+
+        Some(ast::Statement::ExpressionStatement(expr, start.to(self.cur_token.span)))
+    }
+
+    pub fn parse_expression(&mut self, precedence: Precedence) -> Option<ast::Expression> {
+        self.nested(|p| p.parse_nested_expression(precedence))
+    }
+
+    fn parse_nested_expression(&mut self, precedence: Precedence) -> Option<ast::Expression> {
+        let prefix = match Self::prefix_parse_fn(&self.cur_token.kind) {
+            Some(prefix) => prefix,
+            None => {
+                self.no_prefix_parse_fn_error();
+                return None;
+            }
+        };
+        let mut left = prefix(self)?;
+
+        while !self.peek_token_is(&token::TokenKind::Semicolon) && precedence < self.peek_precedence() {
+            let infix = match Self::infix_parse_fn(&self.peek_token.kind) {
+                Some(infix) => infix,
+                None => return Some(left),
+            };
+            self.next_token();
+            left = infix(self, left)?;
+        }
+
+        Some(left)
+    }
+
+    /// Runs `f`, which parses what starts at `cur_token`, one level of
+    /// nesting deeper.
+    fn nested<T>(&mut self, f: impl FnOnce(&mut Parser) -> Option<T>) -> Option<T> {
+        let outer = self.nesting;
+        let result = match self.nest(self.cur_token.span) {
+            true => f(self),
+            false => None,
+        };
+        self.nesting = outer;
+        result
+    }
+
+    /// Counts one more level of nesting, recording an error at `span` and
+    /// returning false past `MAX_NESTING`.
+    fn nest(&mut self, span: token::Span) -> bool {
+        if self.nesting == MAX_NESTING {
+            self.errors.push(error::ParseError::TooDeeplyNested { span });
+            return false;
+        }
+        self.nesting += 1;
+        true
+    }
+
+    fn prefix_parse_fn(kind: &token::TokenKind) -> Option<PrefixParseFn> {
+        match kind {
+            token::TokenKind::Ident(_) => Some(Parser::parse_identifier),
+            token::TokenKind::Int(_) => Some(Parser::parse_integer_literal),
+            token::TokenKind::Float(_) => Some(Parser::parse_float_literal),
+            token::TokenKind::Str(_) => Some(Parser::parse_string_literal),
+            token::TokenKind::True | token::TokenKind::False => Some(Parser::parse_boolean),
+            token::TokenKind::Bang | token::TokenKind::Minus => Some(Parser::parse_prefix_expression),
+            token::TokenKind::LParen => Some(Parser::parse_grouped_expression),
+            token::TokenKind::LBracket => Some(Parser::parse_array_literal),
+            token::TokenKind::LBrace => Some(Parser::parse_hash_literal),
+            token::TokenKind::If => Some(Parser::parse_if_expression),
+            token::TokenKind::Function => Some(Parser::parse_function_literal),
             _ => None,
         }
     }
 
-    pub fn parse_let_statement(&mut self) -> ast::Statement {
-        if !self.expect_peek(token::IDENT) {
-            return ast::Statement::EmptyStatement;
+    fn infix_parse_fn(kind: &token::TokenKind) -> Option<InfixParseFn> {
+        match kind {
+            token::TokenKind::Plus
+            | token::TokenKind::Minus
+            | token::TokenKind::Asterisk
+            | token::TokenKind::Slash
+            | token::TokenKind::Percent
+            | token::TokenKind::Power
+            | token::TokenKind::Lt
+            | token::TokenKind::Gt
+            | token::TokenKind::LtEq
+            | token::TokenKind::GtEq
+            | token::TokenKind::Eq
+            | token::TokenKind::NotEq
+            | token::TokenKind::And
+            | token::TokenKind::Or
+            | token::TokenKind::DotDot => Some(Parser::parse_infix_expression),
+            token::TokenKind::PlusAssign
+            | token::TokenKind::MinusAssign
+            | token::TokenKind::AsteriskAssign
+            | token::TokenKind::SlashAssign
+            | token::TokenKind::Assign => Some(Parser::parse_assign_expression),
+            token::TokenKind::LParen => Some(Parser::parse_call_expression),
+            token::TokenKind::LBracket => Some(Parser::parse_index_expression),
+            _ => None,
         }
+    }
+
+    pub fn parse_identifier(&mut self) -> Option<ast::Expression> {
+        match &self.cur_token.kind {
+            token::TokenKind::Ident(value) => Some(ast::Expression::Identifier(ast::Identifier {
+                value: value.clone(),
+                span: self.cur_token.span,
+            })),
+            _ => None,
+        }
+    }
 
-        let ident = ast::Identifier {
-            value: self.cur_token.literal.clone()
+    pub fn parse_integer_literal(&mut self) -> Option<ast::Expression> {
+        let literal = match &self.cur_token.kind {
+            token::TokenKind::Int(literal) => literal,
+            _ => return None,
+        };
+        let digits = literal.replace('_', "");
+        let parsed = match digits.get(..2) {
+            Some("0x" | "0X") => i64::from_str_radix(&digits[2..], 16),
+            Some("0b" | "0B") => i64::from_str_radix(&digits[2..], 2),
+            Some("0o" | "0O") => i64::from_str_radix(&digits[2..], 8),
+            _ => digits.parse::<i64>(),
         };
+        match parsed {
+            Ok(value) => Some(ast::Expression::IntegerLiteral(value, self.cur_token.span)),
+            Err(_) => {
+                self.errors.push(error::ParseError::InvalidInteger {
+                    literal: literal.clone(),
+                    span: self.cur_token.span,
+                });
+                None
+            }
+        }
+    }
 
-        if !self.expect_peek(token::ASSIGN) {
-            return ast::Statement::EmptyStatement;
+    pub fn parse_float_literal(&mut self) -> Option<ast::Expression> {
+        let literal = match &self.cur_token.kind {
+            token::TokenKind::Float(literal) => literal,
+            _ => return None,
+        };
+        match literal.replace('_', "").parse::<f64>() {
+            Ok(value) if value.is_finite() => Some(ast::Expression::FloatLiteral(value, self.cur_token.span)),
+            _ => {
+                self.errors.push(error::ParseError::InvalidFloat {
+                    literal: literal.clone(),
+                    span: self.cur_token.span,
+                });
+                None
+            }
+        }
+    }
+
+    pub fn parse_string_literal(&mut self) -> Option<ast::Expression> {
+        match &self.cur_token.kind {
+            token::TokenKind::Str(value) => Some(ast::Expression::StringLiteral(value.clone(), self.cur_token.span)),
+            _ => None,
         }
+    }
+
+    pub fn parse_boolean(&mut self) -> Option<ast::Expression> {
+        Some(ast::Expression::Boolean(
+            self.cur_token_is(&token::TokenKind::True),
+            self.cur_token.span,
+        ))
+    }
+
+    pub fn parse_prefix_expression(&mut self) -> Option<ast::Expression> {
+        let start = self.cur_token.span;
+        let operator = self.cur_token.kind.clone();
+        self.next_token();
+        let right = self.parse_expression(Precedence::Prefix)?;
+        let span = start.to(right.span());
+        Some(ast::Expression::PrefixExpression(operator, Box::new(right), span))
+    }
 
-        while self.cur_token_is(token::SEMICOLON) {
+    pub fn parse_infix_expression(&mut self, left: ast::Expression) -> Option<ast::Expression> {
+        let operator = self.cur_token.kind.clone();
+        // `**` is right-associative: parsing its right operand one level
+        // looser lets `2 ** 3 ** 2` group as `2 ** (3 ** 2)`.
+        let precedence = match operator {
+            token::TokenKind::Power => Precedence::Prefix,
+            _ => self.cur_precedence(),
+        };
+        self.next_token();
+        let right = self.parse_expression(precedence)?;
+        let span = left.span().to(right.span());
+        Some(ast::Expression::InfixExpression(Box::new(left), operator, Box::new(right), span))
+    }
+
+    /// Parses an assignment such as `x = 1`, `a[i] = v` or `x += 1`. It is
+    /// right-associative. The target must be a name, or an index expression
+    /// on one, and the name must be a mutable binding in scope.
+    pub fn parse_assign_expression(&mut self, target: ast::Expression) -> Option<ast::Expression> {
+        let operator = self.cur_token.kind.clone();
+        let mut root = &target;
+        while let ast::Expression::IndexExpression(left, _, _) = root {
+            root = left;
+        }
+        let ident = match root {
+            ast::Expression::Identifier(ident) => ident,
+            _ => {
+                self.errors.push(error::ParseError::InvalidAssignmentTarget { span: target.span() });
+                return None;
+            }
+        };
+        let error = match self.lookup(&ident.value) {
+            Some(binding) if binding.mutable => None,
+            Some(binding) => Some(error::ParseError::ImmutableAssignment {
+                name: ident.value.clone(),
+                span: ident.span,
+                declared: binding.span,
+            }),
+            None => Some(error::ParseError::UndeclaredAssignment { name: ident.value.clone(), span: ident.span }),
+        };
+        if let Some(error) = error {
+            self.errors.push(error);
+            return None;
+        }
+        self.next_token();
+        let value = self.parse_expression(Precedence::Lowest)?;
+        let span = target.span().to(value.span());
+        Some(ast::Expression::AssignExpression(Box::new(target), operator, Box::new(value), span))
+    }
+
+    pub fn parse_grouped_expression(&mut self) -> Option<ast::Expression> {
+        self.next_token();
+        let expr = self.parse_expression(Precedence::Lowest)?;
+        if !self.expect_peek(&token::TokenKind::RParen) {
+            return None;
+        }
+        Some(expr)
+    }
+
+    pub fn parse_if_expression(&mut self) -> Option<ast::Expression> {
+        let start = self.cur_token.span;
+        if !self.expect_peek(&token::TokenKind::LParen) {
+            return None;
+        }
+        self.next_token();
+        let condition = self.parse_expression(Precedence::Lowest)?;
+        if !self.expect_peek(&token::TokenKind::RParen) {
+            return None;
+        }
+        if !self.expect_peek(&token::TokenKind::LBrace) {
+            return None;
+        }
+        let consequence = self.parse_block_statement()?;
+
+        let alternative = if self.peek_token_is(&token::TokenKind::Else) {
+            self.next_token();
+            if !self.expect_peek(&token::TokenKind::LBrace) {
+                return None;
+            }
+            Some(self.parse_block_statement()?)
+        } else {
+            None
+        };
+
+        let span = start.to(self.cur_token.span);
+        Some(ast::Expression::IfExpression(Box::new(condition), consequence, alternative, span))
+    }
+
+    pub fn parse_block_statement(&mut self) -> Option<ast::BlockStatement> {
+        self.nested(Parser::parse_nested_block)
+    }
+
+    fn parse_nested_block(&mut self) -> Option<ast::BlockStatement> {
+        let start = self.cur_token.span;
+        let mut statements = Vec::new();
+        self.next_token();
+
+        while !self.cur_token_is(&token::TokenKind::RBrace) {
+            if self.cur_token_is(&token::TokenKind::Eof) {
+                self.errors.push(error::ParseError::UnterminatedBlock { span: start });
+                return None;
+            }
+            if let Some(stmt) = self.parse_next_statement() {
+                statements.push(stmt);
+            }
+        }
+
+        Some(ast::BlockStatement { statements, span: start.to(self.cur_token.span) })
+    }
+
+    pub fn parse_function_literal(&mut self) -> Option<ast::Expression> {
+        let start = self.cur_token.span;
+        if !self.expect_peek(&token::TokenKind::LParen) {
+            return None;
+        }
+        let parameters = self.parse_function_parameters()?;
+        if !self.expect_peek(&token::TokenKind::LBrace) {
+            return None;
+        }
+        // Loops around the function literal cannot be broken out of from
+        // inside its body.
+        let loops = std::mem::take(&mut self.loops);
+        self.scopes.push(HashMap::new());
+        for parameter in &parameters {
+            self.declare(parameter, false);
+        }
+        let body = self.parse_block_statement();
+        self.scopes.pop();
+        self.loops = loops;
+        let body = body?;
+        let span = start.to(body.span);
+        Some(ast::Expression::FunctionLiteral(parameters, body, span))
+    }
+
+    fn parse_function_parameters(&mut self) -> Option<Vec<ast::Identifier>> {
+        let mut identifiers = Vec::new();
+        if self.peek_token_is(&token::TokenKind::RParen) {
             self.next_token();
+            return Some(identifiers);
         }
 
-        ast::Statement::LetStatement (ident, ast::Expression::EmptyExpression)
+        let value = self.expect_peek_ident()?;
+        identifiers.push(ast::Identifier { value, span: self.cur_token.span });
+        while self.peek_token_is(&token::TokenKind::Comma) {
+            self.next_token();
+            let value = self.expect_peek_ident()?;
+            identifiers.push(ast::Identifier { value, span: self.cur_token.span });
+        }
+
+        if !self.expect_peek(&token::TokenKind::RParen) {
+            return None;
+        }
+        Some(identifiers)
+    }
+
+    pub fn parse_call_expression(&mut self, function: ast::Expression) -> Option<ast::Expression> {
+        let arguments = self.parse_expression_list(&token::TokenKind::RParen)?;
+        let span = function.span().to(self.cur_token.span);
+        Some(ast::Expression::CallExpression(Box::new(function), arguments, span))
+    }
+
+    pub fn parse_array_literal(&mut self) -> Option<ast::Expression> {
+        let start = self.cur_token.span;
+        let elements = self.parse_expression_list(&token::TokenKind::RBracket)?;
+        Some(ast::Expression::ArrayLiteral(elements, start.to(self.cur_token.span)))
+    }
+
+    /// Parses `{key: value, ...}`. Block statements are only parsed where the
+    /// grammar requires one, after `if`, `else` or a function's parameters,
+    /// so a `{` that starts an expression always opens a hash.
+    pub fn parse_hash_literal(&mut self) -> Option<ast::Expression> {
+        let start = self.cur_token.span;
+        let mut pairs = Vec::new();
+        while !self.peek_token_is(&token::TokenKind::RBrace) {
+            self.next_token();
+            let key = self.parse_expression(Precedence::Lowest)?;
+            if !self.expect_peek(&token::TokenKind::Colon) {
+                return None;
+            }
+            self.next_token();
+            let value = self.parse_expression(Precedence::Lowest)?;
+            pairs.push((key, value));
+            if !self.peek_token_is(&token::TokenKind::RBrace) && !self.expect_peek(&token::TokenKind::Comma) {
+                return None;
+            }
+        }
+        self.next_token();
+        Some(ast::Expression::HashLiteral(pairs, start.to(self.cur_token.span)))
     }
 
-    pub fn parse_return_statement(&mut self) -> ast::Statement {
+    /// Parses `left[index]` or a slice `left[start:end]`; either bound of a
+    /// slice may be left out, as in `a[1:]` or `a[:]`.
+    pub fn parse_index_expression(&mut self, left: ast::Expression) -> Option<ast::Expression> {
         self.next_token();
+        let start = if self.cur_token_is(&token::TokenKind::Colon) {
+            None
+        } else {
+            let index = self.parse_expression(Precedence::Lowest)?;
+            if !self.peek_token_is(&token::TokenKind::Colon) {
+                if !self.expect_peek(&token::TokenKind::RBracket) {
+                    return None;
+                }
+                let span = left.span().to(self.cur_token.span);
+                return Some(ast::Expression::IndexExpression(Box::new(left), Box::new(index), span));
+            }
+            self.next_token();
+            Some(Box::new(index))
+        };
+
+        let end = if self.peek_token_is(&token::TokenKind::RBracket) {
+            None
+        } else {
+            self.next_token();
+            Some(Box::new(self.parse_expression(Precedence::Lowest)?))
+        };
+        if !self.expect_peek(&token::TokenKind::RBracket) {
+            return None;
+        }
+        let span = left.span().to(self.cur_token.span);
+        Some(ast::Expression::SliceExpression(Box::new(left), start, end, span))
+    }
 
-        while self.cur_token_is(token::SEMICOLON) {
+    /// Parses comma-separated expressions up to the closing `end` token,
+    /// leaving the parser on it.
+    fn parse_expression_list(&mut self, end: &token::TokenKind) -> Option<Vec<ast::Expression>> {
+        let mut list = Vec::new();
+        if self.peek_token_is(end) {
             self.next_token();
+            return Some(list);
         }
 
-        ast::Statement::ReturnStatement (ast::Expression::EmptyExpression)
+        self.next_token();
+        list.push(self.parse_expression(Precedence::Lowest)?);
+        while self.peek_token_is(&token::TokenKind::Comma) {
+            self.next_token();
+            self.next_token();
+            list.push(self.parse_expression(Precedence::Lowest)?);
+        }
+
+        if !self.expect_peek(end) {
+            return None;
+        }
+        Some(list)
+    }
+
+    pub fn cur_token_is(&self, t: &token::TokenKind) -> bool {
+        self.cur_token.kind.same_kind(t)
     }
 
-    pub fn cur_token_is(&self, t: &str) -> bool {
-        self.cur_token.r#type == t.to_string()
+    pub fn peek_token_is(&self, t: &token::TokenKind) -> bool {
+        self.peek_token.kind.same_kind(t)
     }
 
-    pub fn peek_token_is(&self, t: &str) -> bool {
-        self.peek_token.r#type == t.to_string()
+    pub fn cur_precedence(&self) -> Precedence {
+        Precedence::of(&self.cur_token.kind)
     }
 
-    pub fn expect_peek(&mut self, t: &str) -> bool {
+    pub fn peek_precedence(&self) -> Precedence {
+        Precedence::of(&self.peek_token.kind)
+    }
+
+    pub fn expect_peek(&mut self, t: &token::TokenKind) -> bool {
         if self.peek_token_is(t) {
             self.next_token();
             true
         } else {
-            self.peek_error(t);
+            self.peek_error(t.name());
             false
         }
     }
 
-    pub fn errors(&self) -> Vec<String> {
-        self.errors.clone()
+    /// Advances past an identifier token and returns its name.
+    pub fn expect_peek_ident(&mut self) -> Option<String> {
+        if let token::TokenKind::Ident(name) = &self.peek_token.kind {
+            let name = name.clone();
+            self.next_token();
+            Some(name)
+        } else {
+            self.peek_error("IDENT");
+            None
+        }
     }
 
-    pub fn peek_error(&mut self, t: &str) {
-        let msg = format!("expected next token to be {}, got {} instead", t, self.peek_token.r#type);
-        self.errors.push(msg);
+    /// Whether the next token is the identifier `word`, which is a keyword
+    /// only where the grammar expects it, like `as` and `from`.
+    fn peek_word_is(&self, word: &str) -> bool {
+        matches!(&self.peek_token.kind, token::TokenKind::Ident(name) if name == word)
+    }
+
+    /// Advances past the identifier `word`, reporting it as `expected` if
+    /// the next token is something else.
+    fn expect_peek_word(&mut self, word: &str, expected: &str) -> bool {
+        if self.peek_word_is(word) {
+            self.next_token();
+            true
+        } else {
+            self.peek_error(expected);
+            false
+        }
+    }
+
+    /// Advances past a string literal and returns its value.
+    fn expect_peek_string(&mut self) -> Option<String> {
+        if let token::TokenKind::Str(value) = &self.peek_token.kind {
+            let value = value.clone();
+            self.next_token();
+            Some(value)
+        } else {
+            self.peek_error("STRING");
+            None
+        }
+    }
+
+    pub fn errors(&self) -> &[error::ParseError] {
+        &self.errors
+    }
+
+    pub fn peek_error(&mut self, expected: &str) {
+        let err = match &self.peek_token.kind {
+            token::TokenKind::Illegal(err) => error::ParseError::InvalidToken {
+                error: err.clone(),
+                span: self.peek_token.span,
+            },
+            found => error::ParseError::UnexpectedToken {
+                expected: expected.to_string(),
+                found: found.clone(),
+                span: self.peek_token.span,
+            },
+        };
+        self.errors.push(err);
+    }
+
+    pub fn no_prefix_parse_fn_error(&mut self) {
+        let err = match &self.cur_token.kind {
+            token::TokenKind::Illegal(err) => error::ParseError::InvalidToken {
+                error: err.clone(),
+                span: self.cur_token.span,
+            },
+            found => error::ParseError::NoPrefixParseFn {
+                found: found.clone(),
+                span: self.cur_token.span,
+            },
+        };
+        self.errors.push(err);
     }
 }
 
@@ -119,6 +1018,7 @@ mod tests {
     use crate::lexer::*;
     use crate::parser::*;
     use crate::ast::*;
+    use crate::token::*;
 
     #[test]
     fn let_statements() {
@@ -135,7 +1035,7 @@ let foobar = 838383;
         if program.statements.len() != 3 {
             panic!("program.statements does not contain 3 statements. got={}", program.statements.len());
         }
-        let tests = vec![
+        let tests = [
             ("x", 5),
             ("y", 10),
             ("foobar", 838383),
@@ -146,6 +1046,9 @@ let foobar = 838383;
             if !test_let_statement(stmt, tt.0) {
                 panic!("test_let_statement failed");
             }
+            if let ast::Statement::LetStatement(stmt) = stmt {
+                test_integer_literal(&stmt.value, tt.1);
+            }
         }
     }
 
@@ -167,17 +1070,765 @@ return 993322;
         }
 
         for stmt in program.statements {
-            if let ast::Statement::ReturnStatement(_expr) = stmt {
+            if let ast::Statement::ReturnStatement(_expr, _span) = stmt {
                 continue;
             }
             panic!("stmt not ast::Statement::ReturnStatement. got={:?}", stmt);
         }
     }
 
+    #[test]
+    fn statement_spans() {
+        let input = "let x = 5 + 5;\nreturn x;";
+        let l = lexer::Lexer::new(input.to_string());
+        let mut p = parser::Parser::new(l);
+
+        let program = p.parse_program();
+        check_parser_errors(&p);
+
+        let spans: Vec<_> = program.statements.iter().map(|s| s.span()).collect();
+        assert_eq!(spans, vec![
+            token::Span { start: 0, end: 14, line: 1, column: 1 },
+            token::Span { start: 15, end: 24, line: 2, column: 1 },
+        ]);
+        if let ast::Statement::LetStatement(stmt) = &program.statements[0] {
+            assert_eq!(stmt.name.span, token::Span { start: 4, end: 5, line: 1, column: 5 });
+            assert_eq!(stmt.value.span(), token::Span { start: 8, end: 13, line: 1, column: 9 });
+        } else {
+            panic!("not a LetStatement");
+        }
+    }
+
+    #[test]
+    fn error_location() {
+        let l = lexer::Lexer::new("let x 5;".to_string());
+        let mut p = parser::Parser::new(l);
+        p.parse_program();
+        assert_eq!(p.errors(), [error::ParseError::UnexpectedToken {
+            expected: "=".to_string(),
+            found: token::TokenKind::Int("5".to_string()),
+            span: token::Span { start: 6, end: 7, line: 1, column: 7 },
+        }]);
+        assert_eq!(p.errors()[0].to_string(), "expected next token to be =, got INT instead");
+    }
+
+    #[test]
+    fn identifier_expression() {
+        let program = parse("foobar;");
+        assert_eq!(program.statements.len(), 1);
+        test_identifier(expression_statement(&program.statements[0]), "foobar");
+    }
+
+    #[test]
+    fn integer_literal_expression() {
+        let program = parse("5;");
+        assert_eq!(program.statements.len(), 1);
+        test_integer_literal(expression_statement(&program.statements[0]), 5);
+    }
+
+    #[test]
+    fn boolean_expression() {
+        let program = parse("true; false;");
+        assert_eq!(program.statements.len(), 2);
+        test_boolean(expression_statement(&program.statements[0]), true);
+        test_boolean(expression_statement(&program.statements[1]), false);
+    }
+
+    #[test]
+    fn number_literals() {
+        let tests = [
+            ("0xFF", "255"),
+            ("0b1010", "10"),
+            ("0o755", "493"),
+            ("1_000_000", "1000000"),
+            ("0x7fff_ffff_ffff_ffff", "9223372036854775807"),
+            ("3.14", "3.14"),
+            ("1e-9", "1e-9"),
+            ("2.5E3", "2500.0"),
+            ("1_000.5", "1000.5"),
+            ("-1.5", "(-1.5)"),
+        ];
+        for (input, expected) in tests {
+            let program = parse(input);
+            assert_eq!(program.statements.len(), 1, "input: {}", input);
+            assert_eq!(expression_statement(&program.statements[0]).to_string(), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn number_overflow() {
+        let tests = [
+            ("0x1_0000_0000_0000_0000", error::ParseError::InvalidInteger {
+                literal: "0x1_0000_0000_0000_0000".to_string(),
+                span: token::Span { start: 0, end: 23, line: 1, column: 1 },
+            }),
+            ("0b1;\n1e999", error::ParseError::InvalidFloat {
+                literal: "1e999".to_string(),
+                span: token::Span { start: 5, end: 10, line: 2, column: 1 },
+            }),
+        ];
+        for (input, expected) in tests {
+            let mut p = parser::Parser::new(lexer::Lexer::new(input.to_string()));
+            p.parse_program();
+            assert_eq!(p.errors(), [expected], "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn doc_comments() {
+        let input = "
+/// Adds one.
+///
+///   Indented.
+let inc = fn(x) { x + 1 };
+let plain = 1;
+/// Not attached to a let.
+plain;
+let sum = 1 /// ignored inside an expression
+    + 2;
+";
+        let program = parse(input);
+        let docs: Vec<Option<&str>> = program
+            .statements
+            .iter()
+            .map(|stmt| match stmt {
+                ast::Statement::LetStatement(stmt) => stmt.doc.as_deref(),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(docs, [Some("Adds one.\n\n  Indented."), None, None, None]);
+    }
+
+    #[test]
+    fn array_literal() {
+        let program = parse("[1, 2 * 2, 3 + 3]; []");
+        match expression_statement(&program.statements[0]) {
+            ast::Expression::ArrayLiteral(elements, span) => {
+                assert_eq!(elements.len(), 3);
+                test_integer_literal(&elements[0], 1);
+                assert_eq!(elements[1].to_string(), "(2 * 2)");
+                assert_eq!(elements[2].to_string(), "(3 + 3)");
+                assert_eq!(*span, token::Span { start: 0, end: 17, line: 1, column: 1 });
+            }
+            e => panic!("not an ArrayLiteral: {:?}", e),
+        }
+        assert_eq!(expression_statement(&program.statements[1]).to_string(), "[]");
+    }
+
+    #[test]
+    fn hash_literals() {
+        let tests = [
+            (r#"{"one": 1, "two": 2, "three": 3}"#, r#"{"one": 1, "two": 2, "three": 3}"#),
+            ("{}", "{}"),
+            ("{1: true, false: 2,}", "{1: true, false: 2}"),
+            (r#"{"a": 0 + 1, b * 2: [1][0]}"#, r#"{"a": (0 + 1), (b * 2): ([1][0])}"#),
+            (r#"{"a": {"b": 1}}["a"]["b"]"#, r#"(({"a": {"b": 1}}["a"])["b"])"#),
+            ("fn() { {1: 2} }", "fn() { {1: 2}; }"),
+        ];
+        for (input, expected) in tests {
+            let program = parse(input);
+            assert_eq!(program.statements.len(), 1, "input: {}", input);
+            assert_eq!(expression_statement(&program.statements[0]).to_string(), expected, "input: {}", input);
+        }
+
+        let tests = [
+            ("{1 2}", "expected next token to be :, got INT instead"),
+            ("{1: 2 3: 4}", "expected next token to be ,, got INT instead"),
+            ("{1: 2", "expected next token to be ,, got EOF instead"),
+        ];
+        for (input, expected) in tests {
+            let mut p = parser::Parser::new(lexer::Lexer::new(input.to_string()));
+            p.parse_program();
+            let errors: Vec<String> = p.errors().iter().map(ToString::to_string).collect();
+            assert_eq!(errors, [expected], "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn mutable_bindings() {
+        let program = parse("let a = 1; let mut b = 2; var c = 3;");
+        let mutable: Vec<bool> = program
+            .statements
+            .iter()
+            .map(|stmt| match stmt {
+                ast::Statement::LetStatement(stmt) => stmt.mutable,
+                _ => panic!("not a LetStatement: {:?}", stmt),
+            })
+            .collect();
+        assert_eq!(mutable, [false, true, true]);
+    }
+
+    #[test]
+    fn assignment_expressions() {
+        let tests = [
+            ("x = 1", "(x = 1)"),
+            ("x = y = 1 + 2", "(x = (y = (1 + 2)))"),
+            ("a[0] = x == y", "((a[0]) = (x == y))"),
+            ("h[\"k\"][i + 1] = 2", "(((h[\"k\"])[(i + 1)]) = 2)"),
+            ("x += 1 + 2", "(x += (1 + 2))"),
+            ("x -= y *= 2", "(x -= (y *= 2))"),
+            ("x /= a || b", "(x /= (a || b))"),
+            ("a[0] *= 2", "((a[0]) *= 2)"),
+        ];
+        for (input, expected) in tests {
+            let program = parse(&format!("var x = 0; var y = 0; var a = []; var h = {{}}; {}", input));
+            let last = program.statements.last().unwrap();
+            assert_eq!(expression_statement(last).to_string(), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn assignment_errors() {
+        let tests = [
+            ("let x = 1; x = 2;", error::ParseError::ImmutableAssignment {
+                name: "x".to_string(),
+                span: token::Span { start: 11, end: 12, line: 1, column: 12 },
+                declared: Some(token::Span { start: 4, end: 5, line: 1, column: 5 }),
+            }),
+            ("let a = [1]; a[0] += 1;", error::ParseError::ImmutableAssignment {
+                name: "a".to_string(),
+                span: token::Span { start: 13, end: 14, line: 1, column: 14 },
+                declared: Some(token::Span { start: 4, end: 5, line: 1, column: 5 }),
+            }),
+            ("y = 1;", error::ParseError::UndeclaredAssignment {
+                name: "y".to_string(),
+                span: token::Span { start: 0, end: 1, line: 1, column: 1 },
+            }),
+            ("fn(p) { p = 1 }", error::ParseError::ImmutableAssignment {
+                name: "p".to_string(),
+                span: token::Span { start: 8, end: 9, line: 1, column: 9 },
+                declared: Some(token::Span { start: 3, end: 4, line: 1, column: 4 }),
+            }),
+            ("for i in 0..3 { i += 1 }", error::ParseError::ImmutableAssignment {
+                name: "i".to_string(),
+                span: token::Span { start: 16, end: 17, line: 1, column: 17 },
+                declared: Some(token::Span { start: 4, end: 5, line: 1, column: 5 }),
+            }),
+            ("fn() { var t = 1; }; t = 2;", error::ParseError::UndeclaredAssignment {
+                name: "t".to_string(),
+                span: token::Span { start: 21, end: 22, line: 1, column: 22 },
+            }),
+            ("var m = 1; let m = 2; m = 3;", error::ParseError::ImmutableAssignment {
+                name: "m".to_string(),
+                span: token::Span { start: 22, end: 23, line: 1, column: 23 },
+                declared: Some(token::Span { start: 15, end: 16, line: 1, column: 16 }),
+            }),
+            ("f() = 1;", error::ParseError::InvalidAssignmentTarget {
+                span: token::Span { start: 0, end: 3, line: 1, column: 1 },
+            }),
+            ("f()[0] = 1;", error::ParseError::InvalidAssignmentTarget {
+                span: token::Span { start: 0, end: 6, line: 1, column: 1 },
+            }),
+        ];
+        for (input, expected) in tests {
+            let mut p = parser::Parser::new(lexer::Lexer::new(input.to_string()));
+            p.parse_program();
+            assert_eq!(p.errors(), [expected], "input: {}", input);
+        }
+
+        let valid = [
+            "var x = 1; let f = fn() { x = 2 }; x = 3;",
+            "let mut n = 0; for i in 0..3 { n += i }",
+            "let x = 1; fn(x) { let mut x = x; x = 2 }",
+            "let mut f = fn() { f = 1 };",
+        ];
+        for input in valid {
+            parse(input);
+        }
+
+        let mut p = parser::Parser::new(lexer::Lexer::new("x = 1; y = 2; var z = 3;".to_string()));
+        p.declare_globals([("x", true), ("y", false)]);
+        p.parse_program();
+        assert_eq!(p.errors(), [error::ParseError::ImmutableAssignment {
+            name: "y".to_string(),
+            span: token::Span { start: 7, end: 8, line: 1, column: 8 },
+            declared: None,
+        }]);
+        let mut globals: Vec<_> = p.globals().collect();
+        globals.sort();
+        assert_eq!(globals, [("x", true), ("y", false), ("z", true)]);
+    }
+
+    #[test]
+    fn block_scopes() {
+        let tests = [
+            ("var c = true; while (c) { var n = 1; c = false; } n = 2;", vec![]),
+            ("if (true) { var n = 1; } else { } n += 1;", vec![]),
+            ("if (true) { let k = 1; } k = 2;", vec!["cannot assign to immutable binding `k`"]),
+            ("for x in [1] { var m = 1; } m = 2;", vec!["cannot assign to undeclared name `m`"]),
+            ("let f = fn() { var l = 1; }; l = 2;", vec!["cannot assign to undeclared name `l`"]),
+        ];
+        for (input, expected) in tests {
+            let mut p = parser::Parser::new(lexer::Lexer::new(input.to_string()));
+            p.parse_program();
+            let errors: Vec<String> = p.errors().iter().map(ToString::to_string).collect();
+            assert_eq!(errors, expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn loop_statements() {
+        let program = parse("var x = 0; while (x < 10) { x += 1; } outer: for k in 0..n { break outer; continue; }");
+        assert_eq!(program.statements.len(), 3);
+        match &program.statements[1] {
+            ast::Statement::WhileStatement(None, condition, body, _) => {
+                assert_eq!(condition.to_string(), "(x < 10)");
+                assert_eq!(body.statements.len(), 1);
+            }
+            s => panic!("not a WhileStatement: {:?}", s),
+        }
+        match &program.statements[2] {
+            ast::Statement::ForStatement(Some(label), variable, iterable, body, span) => {
+                assert_eq!(label.value, "outer");
+                assert_eq!(variable.value, "k");
+                assert_eq!(iterable.to_string(), "(0 .. n)");
+                assert!(matches!(
+                    &body.statements[..],
+                    [ast::Statement::BreakStatement(Some(_), _), ast::Statement::ContinueStatement(None, _)]
+                ));
+                assert_eq!(span.start, 38);
+            }
+            s => panic!("not a ForStatement: {:?}", s),
+        }
+    }
+
+    #[test]
+    fn import_statements() {
+        let program = parse("import \"lib/a.rk\" as a; import { x, y as z, } from \"b.rk\"\n/// Doc.\nexport var n = z;");
+        assert_eq!(program.statements.len(), 3);
+        match &program.statements[0] {
+            ast::Statement::ImportStatement(path, ast::Import::Module(name), _) => {
+                assert_eq!(path, "lib/a.rk");
+                assert_eq!(name.value, "a");
+            }
+            s => panic!("not a module ImportStatement: {:?}", s),
+        }
+        match &program.statements[1] {
+            ast::Statement::ImportStatement(path, ast::Import::Names(names), span) => {
+                assert_eq!(path, "b.rk");
+                let names: Vec<_> = names.iter().map(|(name, binding)| (name.value.as_str(), binding.value.as_str())).collect();
+                assert_eq!(names, [("x", "x"), ("y", "z")]);
+                assert_eq!((span.start, span.end), (24, 57));
+            }
+            s => panic!("not a selective ImportStatement: {:?}", s),
+        }
+        match &program.statements[2] {
+            ast::Statement::ExportStatement(stmt, _) => {
+                assert_eq!(stmt.name.value, "n");
+                assert!(stmt.mutable);
+                assert_eq!(stmt.doc.as_deref(), Some("Doc."));
+            }
+            s => panic!("not an ExportStatement: {:?}", s),
+        }
+    }
+
+    #[test]
+    fn import_statement_errors() {
+        let tests = [
+            ("import \"a.rk\" as a; a = 1;", "cannot assign to immutable binding `a`"),
+            ("import { x as y } from \"a.rk\"; y += 1;", "cannot assign to immutable binding `y`"),
+            ("if (true) { import \"a.rk\" as a; }", "`import` is only allowed at the top level"),
+            ("let f = fn() { export let x = 1; };", "`export` is only allowed at the top level"),
+            ("import a;", "expected next token to be STRING, got IDENT instead"),
+            ("import \"a.rk\";", "expected next token to be AS, got ; instead"),
+            ("import { x } \"a.rk\";", "expected next token to be FROM, got STRING instead"),
+            ("import { x y } from \"a.rk\";", "expected next token to be ,, got IDENT instead"),
+            ("export 1;", "expected next token to be LET or VAR, got INT instead"),
+        ];
+        for (input, expected) in tests {
+            let mut p = parser::Parser::new(lexer::Lexer::new(input.to_string()));
+            p.parse_program();
+            let errors: Vec<String> = p.errors().iter().map(ToString::to_string).collect();
+            assert_eq!(errors, [expected], "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn loop_statement_errors() {
+        let tests = [
+            ("break;", "`break` outside of a loop"),
+            ("if (x) { continue }", "`continue` outside of a loop"),
+            ("while (true) { fn() { break; } }", "`break` outside of a loop"),
+            ("while (true) { break outer; }", "use of undeclared label `outer`"),
+            ("a: for x in y { fn() { b: while (true) { continue a; } } }", "use of undeclared label `a`"),
+            ("a: let x = 1;", "expected next token to be WHILE or FOR, got LET instead"),
+            ("for (x in y) { }", "expected next token to be IDENT, got ( instead"),
+            ("for x of y { }", "expected next token to be IN, got IDENT instead"),
+            ("while x { }", "expected next token to be (, got IDENT instead"),
+        ];
+        for (input, expected) in tests {
+            let mut p = parser::Parser::new(lexer::Lexer::new(input.to_string()));
+            p.parse_program();
+            let errors: Vec<String> = p.errors().iter().map(ToString::to_string).collect();
+            assert_eq!(errors, [expected], "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn integer_overflow() {
+        let l = lexer::Lexer::new("99999999999999999999;".to_string());
+        let mut p = parser::Parser::new(l);
+        p.parse_program();
+        assert_eq!(p.errors(), [error::ParseError::InvalidInteger {
+            literal: "99999999999999999999".to_string(),
+            span: token::Span { start: 0, end: 20, line: 1, column: 1 },
+        }]);
+    }
+
+    #[test]
+    fn string_literal_expression() {
+        let program = parse(r#""hello\tworld";"#);
+        assert_eq!(program.statements.len(), 1);
+        match expression_statement(&program.statements[0]) {
+            ast::Expression::StringLiteral(value, _) => assert_eq!(value, "hello\tworld"),
+            other => panic!("not a StringLiteral: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unterminated_string() {
+        let l = lexer::Lexer::new("let s = \"abc;".to_string());
+        let mut p = parser::Parser::new(l);
+        p.parse_program();
+        assert_eq!(p.errors(), [error::ParseError::InvalidToken {
+            error: token::LexError::UnterminatedString,
+            span: token::Span { start: 8, end: 13, line: 1, column: 9 },
+        }]);
+        assert_eq!(p.errors()[0].to_string(), "unterminated string literal");
+    }
+
+    #[test]
+    fn prefix_expressions() {
+        let tests = [
+            ("!5;", token::TokenKind::Bang, 5),
+            ("-15;", token::TokenKind::Minus, 15),
+        ];
+        for (input, operator, value) in tests {
+            let program = parse(input);
+            assert_eq!(program.statements.len(), 1);
+            if let ast::Expression::PrefixExpression(op, right, _) = expression_statement(&program.statements[0]) {
+                assert_eq!(*op, operator);
+                test_integer_literal(right, value);
+            } else {
+                panic!("not a PrefixExpression: {:?}", program.statements[0]);
+            }
+        }
+    }
+
+    #[test]
+    fn infix_expressions() {
+        let tests = [
+            ("5 + 5;", token::TokenKind::Plus),
+            ("5 - 5;", token::TokenKind::Minus),
+            ("5 * 5;", token::TokenKind::Asterisk),
+            ("5 / 5;", token::TokenKind::Slash),
+            ("5 > 5;", token::TokenKind::Gt),
+            ("5 < 5;", token::TokenKind::Lt),
+            ("5 == 5;", token::TokenKind::Eq),
+            ("5 != 5;", token::TokenKind::NotEq),
+        ];
+        for (input, operator) in tests {
+            let program = parse(input);
+            assert_eq!(program.statements.len(), 1);
+            if let ast::Expression::InfixExpression(left, op, right, _) = expression_statement(&program.statements[0]) {
+                test_integer_literal(left, 5);
+                assert_eq!(*op, operator);
+                test_integer_literal(right, 5);
+            } else {
+                panic!("not an InfixExpression: {:?}", program.statements[0]);
+            }
+        }
+    }
+
+    #[test]
+    fn operator_precedence() {
+        let tests = [
+            ("-a * b", "((-a) * b)"),
+            ("!-a", "(!(-a))"),
+            ("a + b + c", "((a + b) + c)"),
+            ("a + b - c", "((a + b) - c)"),
+            ("a * b * c", "((a * b) * c)"),
+            ("a * b / c", "((a * b) / c)"),
+            ("a + b / c", "(a + (b / c))"),
+            ("a + b * c + d / e - f", "(((a + (b * c)) + (d / e)) - f)"),
+            ("3 + 4; -5 * 5", "(3 + 4)((-5) * 5)"),
+            ("5 > 4 == 3 < 4", "((5 > 4) == (3 < 4))"),
+            ("3 + 4 * 5 == 3 * 1 + 4 * 5", "((3 + (4 * 5)) == ((3 * 1) + (4 * 5)))"),
+            ("true != false", "(true != false)"),
+            ("3 > 5 == false", "((3 > 5) == false)"),
+            ("1 + (2 + 3) + 4", "((1 + (2 + 3)) + 4)"),
+            ("(5 + 5) * 2", "((5 + 5) * 2)"),
+            ("-(5 + 5)", "(-(5 + 5))"),
+            ("!(true == true)", "(!(true == true))"),
+            ("a + add(b * c) + d", "((a + add((b * c))) + d)"),
+            ("add(a, b, 1, 2 * 3, 4 + 5, add(6, 7 * 8))", "add(a, b, 1, (2 * 3), (4 + 5), add(6, (7 * 8)))"),
+            ("add(a + b + c * d / f + g)", "add((((a + b) + ((c * d) / f)) + g))"),
+            ("a <= b == b >= a", "((a <= b) == (b >= a))"),
+            ("a % b * c + d", "(((a % b) * c) + d)"),
+            ("2 ** 3 ** 2", "(2 ** (3 ** 2))"),
+            ("-2 ** 2", "(-(2 ** 2))"),
+            ("2 ** -1", "(2 ** (-1))"),
+            ("a * b ** c", "(a * (b ** c))"),
+            ("a || b && c", "(a || (b && c))"),
+            ("a && b || c && d", "((a && b) || (c && d))"),
+            ("a == b && c < d", "((a == b) && (c < d))"),
+            ("a * [1, 2, 3, 4][b * c] * d", "((a * ([1, 2, 3, 4][(b * c)])) * d)"),
+            ("add(a * b[2], b[1], 2 * [1, 2][1])", "add((a * (b[2])), (b[1]), (2 * ([1, 2][1])))"),
+            ("a[0](1)", "(a[0])(1)"),
+            ("f()[2]", "(f()[2])"),
+            ("-a[0]", "(-(a[0]))"),
+            ("a[1:3][0]", "((a[1:3])[0])"),
+            ("a[:n - 1]", "(a[:(n - 1)])"),
+            ("a[-2:]", "(a[(-2):])"),
+            ("a[:]", "(a[:])"),
+            ("0..n + 1", "(0 .. (n + 1))"),
+            ("a..b == c..d", "((a .. b) == (c .. d))"),
+        ];
+        for (input, expected) in tests {
+            let program = parse(input);
+            let actual: String = program.statements.iter()
+                .map(|s| expression_statement(s).to_string())
+                .collect();
+            assert_eq!(actual, expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn let_statement_expression() {
+        let program = parse("let x = 1 + 2 * 3;");
+        if let ast::Statement::LetStatement(ast::LetStatement {
+            value: ast::Expression::InfixExpression(left, op, right, _),
+            ..
+        }) = &program.statements[0]
+        {
+            test_integer_literal(left, 1);
+            assert_eq!(*op, token::TokenKind::Plus);
+            assert_eq!(right.to_string(), "(2 * 3)");
+        } else {
+            panic!("unexpected statement: {:?}", program.statements[0]);
+        }
+    }
+
+    #[test]
+    fn if_expression() {
+        let program = parse("if (x < y) { x }");
+        assert_eq!(program.statements.len(), 1);
+        if let ast::Expression::IfExpression(condition, consequence, alternative, span) = expression_statement(&program.statements[0]) {
+            assert_eq!(condition.to_string(), "(x < y)");
+            assert_eq!(consequence.statements.len(), 1);
+            test_identifier(expression_statement(&consequence.statements[0]), "x");
+            assert!(alternative.is_none());
+            assert_eq!((span.start, span.end), (0, 16));
+        } else {
+            panic!("not an IfExpression: {:?}", program.statements[0]);
+        }
+    }
+
+    #[test]
+    fn if_else_expression() {
+        let program = parse("if (x < y) { x } else { y }");
+        assert_eq!(program.statements.len(), 1);
+        if let ast::Expression::IfExpression(condition, consequence, Some(alternative), span) = expression_statement(&program.statements[0]) {
+            assert_eq!(condition.to_string(), "(x < y)");
+            test_identifier(expression_statement(&consequence.statements[0]), "x");
+            assert_eq!(alternative.statements.len(), 1);
+            test_identifier(expression_statement(&alternative.statements[0]), "y");
+            assert_eq!((span.start, span.end), (0, 27));
+        } else {
+            panic!("not an IfExpression with an alternative: {:?}", program.statements[0]);
+        }
+    }
+
+    #[test]
+    fn function_literal() {
+        let program = parse("fn(x, y) { x + y; }");
+        assert_eq!(program.statements.len(), 1);
+        if let ast::Expression::FunctionLiteral(params, body, span) = expression_statement(&program.statements[0]) {
+            assert_eq!(params.len(), 2);
+            assert_eq!(params[0].value, "x");
+            assert_eq!(params[1].value, "y");
+            assert_eq!(body.statements.len(), 1);
+            assert_eq!(expression_statement(&body.statements[0]).to_string(), "(x + y)");
+            assert_eq!((span.start, span.end), (0, 19));
+        } else {
+            panic!("not a FunctionLiteral: {:?}", program.statements[0]);
+        }
+    }
+
+    #[test]
+    fn function_parameters() {
+        let tests = [
+            ("fn() {};", vec![]),
+            ("fn(x) {};", vec!["x"]),
+            ("fn(x, y, z) {};", vec!["x", "y", "z"]),
+        ];
+        for (input, expected) in tests {
+            let program = parse(input);
+            if let ast::Expression::FunctionLiteral(params, _, _) = expression_statement(&program.statements[0]) {
+                let names: Vec<_> = params.iter().map(|p| p.value.as_str()).collect();
+                assert_eq!(names, expected);
+            } else {
+                panic!("not a FunctionLiteral: {:?}", program.statements[0]);
+            }
+        }
+    }
+
+    #[test]
+    fn call_expression() {
+        let program = parse("add(1, 2 * 3, 4 + 5);");
+        assert_eq!(program.statements.len(), 1);
+        if let ast::Expression::CallExpression(function, args, span) = expression_statement(&program.statements[0]) {
+            test_identifier(function, "add");
+            assert_eq!(args.len(), 3);
+            test_integer_literal(&args[0], 1);
+            assert_eq!(&args[1].to_string(), "(2 * 3)");
+            assert_eq!(&args[2].to_string(), "(4 + 5)");
+            assert_eq!((span.start, span.end), (0, 20));
+        } else {
+            panic!("not a CallExpression: {:?}", program.statements[0]);
+        }
+    }
+
+    #[test]
+    fn no_prefix_parse_fn() {
+        let l = lexer::Lexer::new("let x = * 5;".to_string());
+        let mut p = parser::Parser::new(l);
+        p.parse_program();
+        assert_eq!(p.errors(), [error::ParseError::NoPrefixParseFn {
+            found: token::TokenKind::Asterisk,
+            span: token::Span { start: 8, end: 9, line: 1, column: 9 },
+        }]);
+        assert_eq!(p.errors()[0].to_string(), "no prefix parse function for * found");
+    }
+
+    #[test]
+    fn error_recovery() {
+        let tests = [
+            ("let x 5; let y = 10;", 1, 1),
+            ("let = 10; let y = 10; y;", 1, 2),
+            ("let x = ; let y = 10;", 1, 1),
+            ("let x = 1 +\nlet y = 2;", 1, 1),
+            ("add(1, 2; let y = 2;", 1, 1),
+            ("let x 5; let y 6; let z = 7;", 2, 1),
+            ("let f = fn() { let x = ; 1 }; let y = 2;", 1, 2),
+            ("fn() { 1 + }; 2", 1, 2),
+            ("if (x { y }; 1;", 1, 1),
+            ("5 @ 3;", 1, 1),
+            ("1 += 2; var x = 1; x += 1;", 1, 2),
+            ("let x = 1; x = 2; let y = 3;", 1, 2),
+            ("a & b; c | d;", 2, 2),
+            ("[1, 2; let y = 2;", 1, 1),
+            ("a[1:2:3]; a[];", 2, 0),
+        ];
+        for (input, errors, statements) in tests {
+            let l = lexer::Lexer::new(input.to_string());
+            let mut p = parser::Parser::new(l);
+            let program = p.parse_program();
+            assert_eq!(p.errors().len(), errors, "input: {}, errors: {:?}", input, p.errors());
+            assert_eq!(program.statements.len(), statements, "input: {}, program: {:?}", input, program);
+        }
+    }
+
+    #[test]
+    fn unterminated_block() {
+        let l = lexer::Lexer::new("let f = fn(x) {\n  x + 1;\n".to_string());
+        let mut p = parser::Parser::new(l);
+        let program = p.parse_program();
+        assert_eq!(p.errors(), [error::ParseError::UnterminatedBlock {
+            span: token::Span { start: 14, end: 15, line: 1, column: 15 },
+        }]);
+        assert!(program.statements.is_empty());
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let nested = |open: &str, inner: &str, close: &str, n: usize| open.repeat(n) + inner + &close.repeat(n);
+        let tests = [
+            (nested("(", "1", ")", 100), true),
+            (nested("-", "1", "", 100), true),
+            (nested("", "1", " + 1", 10_000), true),
+            (nested("f", "", "()", 10_000), true),
+            (nested("while (true) { ", "1", " }", 100), true),
+            (nested("(", "1", ")", 10_000), false),
+            (nested("-", "1", "", 10_000), false),
+            (nested("!", "true", "", 10_000), false),
+            (nested("[", "1", "]", 10_000), false),
+            (nested("f(", "1", ")", 10_000), false),
+            (nested("{1: ", "1", "}", 10_000), false),
+            (nested("while (true) { ", "1", " }", 10_000), false),
+            (nested("fn() { ", "1", " }", 10_000), false),
+        ];
+        for (input, ok) in tests {
+            let l = lexer::Lexer::new(input.clone());
+            let mut p = parser::Parser::new(l);
+            p.parse_program();
+            let errors: Vec<_> = p.errors().iter().map(ToString::to_string).collect();
+            let expected = match ok {
+                true => vec![],
+                false => vec![format!("expressions nested more than {} deep", parser::MAX_NESTING)],
+            };
+            assert_eq!(errors, expected, "input: {}...", &input[..20]);
+        }
+
+        let l = lexer::Lexer::new(nested("(", "1", ")", 1000));
+        let mut p = parser::Parser::new(l);
+        p.parse_program();
+        assert_eq!(p.errors(), [error::ParseError::TooDeeplyNested {
+            span: token::Span { start: 256, end: 257, line: 1, column: 257 },
+        }]);
+    }
+
+    #[test]
+    fn illegal_token() {
+        let l = lexer::Lexer::new("let x = 1 # 2;".to_string());
+        let mut p = parser::Parser::new(l);
+        p.parse_program();
+        assert_eq!(p.errors(), [error::ParseError::InvalidToken {
+            error: token::LexError::UnexpectedCharacter('#'),
+            span: token::Span { start: 10, end: 11, line: 1, column: 11 },
+        }]);
+    }
+
+    fn parse(input: &str) -> ast::Program {
+        let l = lexer::Lexer::new(input.to_string());
+        let mut p = parser::Parser::new(l);
+        let program = p.parse_program();
+        check_parser_errors(&p);
+        program
+    }
+
+    fn expression_statement(s: &ast::Statement) -> &ast::Expression {
+        match s {
+            ast::Statement::ExpressionStatement(expr, _) => expr,
+            _ => panic!("s not an ExpressionStatement. got={:?}", s),
+        }
+    }
+
+    fn test_identifier(e: &ast::Expression, value: &str) {
+        match e {
+            ast::Expression::Identifier(ident) => assert_eq!(ident.value, value),
+            _ => panic!("e not an Identifier. got={:?}", e),
+        }
+    }
+
+    fn test_integer_literal(e: &ast::Expression, value: i64) {
+        match e {
+            ast::Expression::IntegerLiteral(v, _) => assert_eq!(*v, value),
+            _ => panic!("e not an IntegerLiteral. got={:?}", e),
+        }
+    }
+
+    fn test_boolean(e: &ast::Expression, value: bool) {
+        match e {
+            ast::Expression::Boolean(v, _) => assert_eq!(*v, value),
+            _ => panic!("e not a Boolean. got={:?}", e),
+        }
+    }
+
     fn test_let_statement(s: &ast::Statement, name: &str) -> bool {
-        if let ast::Statement::LetStatement(ident, _expr) = s {
-            if ident.value != name {
-                panic!("s.Name not {}. got={}", name, ident.value);
+        if let ast::Statement::LetStatement(stmt) = s {
+            if stmt.name.value != name {
+                panic!("s.Name not {}. got={}", name, stmt.name.value);
             }
         } else {
             panic!("s not a LetStatement. got={:?}", s);
@@ -187,12 +1838,12 @@ return 993322;
 
     fn check_parser_errors(p: &parser::Parser) {
         let errors = p.errors();
-        if errors.len() == 0 {
+        if errors.is_empty() {
             return;
         }
         eprintln!("parser has {} errors", errors.len());
-        for msg in errors {
-            eprintln!("parser error: {}", msg);
+        for err in errors {
+            eprintln!("parser error: {}: {}", err.span(), err);
         }
         panic!();
     }
@@ -1,22 +1,54 @@
+use std::collections::HashMap;
 use std::io::{self, Write};
+use crate::ast::*;
+use crate::diagnostics::*;
+use crate::evaluator::*;
 use crate::lexer::*;
-use crate::token::*;
+use crate::object::*;
+use crate::parser::*;
 
 pub const PROMPT: &str = ">> ";
 
 pub fn start(r#in: io::Stdin, mut out: io::Stdout) -> io::Result<()> {
+    let mut env = environment::Environment::new();
+    let mut loader = modules::Loader::from_env().with_working_directory();
+    // The names bound by earlier lines and whether each is mutable, so the
+    // parser can check assignments to them.
+    let mut globals: HashMap<String, bool> = HashMap::new();
     let mut input = String::new();
     loop {
-        out.write(PROMPT.as_bytes())?;
+        out.write_all(PROMPT.as_bytes())?;
         out.flush()?;
-        r#in.read_line(&mut input)?;
-        let mut l = lexer::Lexer::new(input.clone());
-        loop {
-            let tok = l.next_token();
-            println!("{:?}", tok);
-            if tok.r#type == token::EOF.to_string() {
-                break;
-            }
+        input.clear();
+        if r#in.read_line(&mut input)? == 0 {
+            return Ok(());
+        }
+
+        let l = lexer::Lexer::new(input.clone());
+        let mut p = parser::Parser::new(l);
+        p.declare_globals(globals.iter().map(|(name, mutable)| (name.as_str(), *mutable)));
+        let program = p.parse_program();
+        if !p.errors().is_empty() {
+            print_parser_errors(&mut out, p.errors(), &input)?;
+            continue;
+        }
+        globals.extend(p.globals().map(|(name, mutable)| (name.to_string(), mutable)));
+
+        let (evaluated, _) = modules::with_loader(&mut loader, None, || {
+            limits::with_limits(&limits::Limits::default(), || evaluator::eval(&program, &mut env))
+        });
+        if evaluated.is_error() || !ends_with_let(&program) {
+            writeln!(out, "{}", evaluated)?;
         }
     }
 }
+
+/// A line such as `let x = 5;` has no value worth echoing.
+fn ends_with_let(program: &ast::Program) -> bool {
+    matches!(program.statements.last(), Some(ast::Statement::LetStatement(..)) | None)
+}
+
+fn print_parser_errors(out: &mut io::Stdout, errors: &[error::ParseError], source: &str) -> io::Result<()> {
+    let diagnostics: Vec<diagnostics::Diagnostic> = errors.iter().map(Into::into).collect();
+    diagnostics::Renderer::for_stream(out).emit(out, &diagnostics, "<repl>", source)
+}
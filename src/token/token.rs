@@ -1,63 +1,298 @@
+use std::fmt;
 use phf::phf_map;
 
-#[derive(Debug, Clone)]
+/// A range of source text. `start` and `end` are byte offsets (end exclusive);
+/// `line` and `column` are the 1-based position of `start`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    /// Returns a span covering both `self` and `other`, where `other` ends
+    /// at or after `self`.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start,
+            end: other.end.max(self.end),
+            line: self.line,
+            column: self.column,
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Token {
-    pub r#type: String,
-    pub literal: String,
-}
-
-pub const ILLEGAL: &str = "ILLEGAL";
-pub const EOF: &str = "EOF";
-
-// Identifiers + literals
-pub const IDENT: &str = "IDENT";
-pub const INT: &str = "INT";
-
-// Operators
-pub const ASSIGN: &str = "=";
-pub const PLUS: &str = "+";
-pub const MINUS: &str = "-";
-pub const BANG: &str = "!";
-pub const ASTERISK: &str = "*";
-pub const SLASH: &str = "/";
-
-pub const LT: &str = "<";
-pub const GT: &str = ">";
-
-pub const EQ: &str = "==";
-pub const NOT_EQ: &str = "!=";
-
-// Delimiters
-pub const COMMA: &str = ",";
-pub const SEMICOLON: &str = ";";
-
-pub const LPAREN: &str = "(";
-pub const RPAREN: &str = ")";
-pub const LBRACE: &str = "{";
-pub const RBRACE: &str = "}";
-
-// Keywords
-pub const FUNCTION: &str = "FUNCTION";
-pub const LET: &str = "LET";
-pub const TRUE: &str = "TRUE";
-pub const FALSE: &str = "FALSE";
-pub const IF: &str = "IF";
-pub const ELSE: &str = "ELSE";
-pub const RETURN: &str = "RETURN";
-
-pub const KEYWORDS: phf::Map<&'static str, &'static str> = phf_map! {
-    "fn" => FUNCTION,
-    "let" => LET,
-    "true" => TRUE,
-    "false" => FALSE,
-    "if" => IF,
-    "else" => ELSE,
-    "return" => RETURN,
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+impl Token {
+    pub fn new(kind: TokenKind, span: Span) -> Token {
+        Token { kind, span }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    Illegal(LexError),
+    Eof,
+
+    // Identifiers + literals
+    Ident(String),
+    /// Integer and float literals keep their source text, e.g. `0xFF` or
+    /// `1_000.5`.
+    Int(String),
+    Float(String),
+    /// The text of a `///` comment, after the slashes.
+    DocComment(String),
+    /// A string literal's value, with escape sequences already resolved.
+    Str(String),
+
+    // Operators
+    Assign,
+    Plus,
+    Minus,
+    Bang,
+    Asterisk,
+    Slash,
+    Percent,
+    /// `**`, exponentiation.
+    Power,
+
+    Lt,
+    Gt,
+    LtEq,
+    GtEq,
+
+    Eq,
+    NotEq,
+
+    And,
+    Or,
+
+    /// `..`, the exclusive range operator.
+    DotDot,
+
+    PlusAssign,
+    MinusAssign,
+    AsteriskAssign,
+    SlashAssign,
+
+    // Delimiters
+    Comma,
+    Semicolon,
+    Colon,
+
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    LBracket,
+    RBracket,
+
+    // Keywords
+    Function,
+    Let,
+    Mut,
+    Var,
+    True,
+    False,
+    If,
+    Else,
+    Return,
+    While,
+    For,
+    In,
+    Break,
+    Continue,
+    Import,
+    Export,
+}
+
+impl TokenKind {
+    /// The name of the kind, independent of any payload. Used in diagnostics.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TokenKind::Illegal(_) => "ILLEGAL",
+            TokenKind::Eof => "EOF",
+            TokenKind::Ident(_) => "IDENT",
+            TokenKind::Int(_) => "INT",
+            TokenKind::Float(_) => "FLOAT",
+            TokenKind::DocComment(_) => "DOC_COMMENT",
+            TokenKind::Str(_) => "STRING",
+            TokenKind::Assign => "=",
+            TokenKind::Plus => "+",
+            TokenKind::Minus => "-",
+            TokenKind::Bang => "!",
+            TokenKind::Asterisk => "*",
+            TokenKind::Slash => "/",
+            TokenKind::Percent => "%",
+            TokenKind::Power => "**",
+            TokenKind::Lt => "<",
+            TokenKind::Gt => ">",
+            TokenKind::LtEq => "<=",
+            TokenKind::GtEq => ">=",
+            TokenKind::Eq => "==",
+            TokenKind::NotEq => "!=",
+            TokenKind::And => "&&",
+            TokenKind::Or => "||",
+            TokenKind::DotDot => "..",
+            TokenKind::PlusAssign => "+=",
+            TokenKind::MinusAssign => "-=",
+            TokenKind::AsteriskAssign => "*=",
+            TokenKind::SlashAssign => "/=",
+            TokenKind::Comma => ",",
+            TokenKind::Semicolon => ";",
+            TokenKind::Colon => ":",
+            TokenKind::LParen => "(",
+            TokenKind::RParen => ")",
+            TokenKind::LBrace => "{",
+            TokenKind::RBrace => "}",
+            TokenKind::LBracket => "[",
+            TokenKind::RBracket => "]",
+            TokenKind::Function => "FUNCTION",
+            TokenKind::Let => "LET",
+            TokenKind::Mut => "MUT",
+            TokenKind::Var => "VAR",
+            TokenKind::True => "TRUE",
+            TokenKind::False => "FALSE",
+            TokenKind::If => "IF",
+            TokenKind::Else => "ELSE",
+            TokenKind::Return => "RETURN",
+            TokenKind::While => "WHILE",
+            TokenKind::For => "FOR",
+            TokenKind::In => "IN",
+            TokenKind::Break => "BREAK",
+            TokenKind::Continue => "CONTINUE",
+            TokenKind::Import => "IMPORT",
+            TokenKind::Export => "EXPORT",
+        }
+    }
+
+    /// The binary operator a compound assignment applies, e.g. `Plus` for
+    /// `+=`.
+    pub fn compound_operator(&self) -> Option<TokenKind> {
+        match self {
+            TokenKind::PlusAssign => Some(TokenKind::Plus),
+            TokenKind::MinusAssign => Some(TokenKind::Minus),
+            TokenKind::AsteriskAssign => Some(TokenKind::Asterisk),
+            TokenKind::SlashAssign => Some(TokenKind::Slash),
+            _ => None,
+        }
+    }
+
+    /// Whether both kinds are the same variant, ignoring any payload.
+    pub fn same_kind(&self, other: &TokenKind) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenKind::Illegal(LexError::UnexpectedCharacter(ch)) => write!(f, "{}", ch),
+            TokenKind::Illegal(err) => write!(f, "{}", err),
+            TokenKind::Ident(s) | TokenKind::Int(s) | TokenKind::Float(s) => write!(f, "{}", s),
+            TokenKind::Str(s) => write!(f, "\"{}\"", escape(s)),
+            TokenKind::DocComment(text) => write!(f, "///{}", text),
+            TokenKind::Eof => Ok(()),
+            TokenKind::Function => write!(f, "fn"),
+            TokenKind::Let => write!(f, "let"),
+            TokenKind::Mut => write!(f, "mut"),
+            TokenKind::Var => write!(f, "var"),
+            TokenKind::True => write!(f, "true"),
+            TokenKind::False => write!(f, "false"),
+            TokenKind::If => write!(f, "if"),
+            TokenKind::Else => write!(f, "else"),
+            TokenKind::Return => write!(f, "return"),
+            TokenKind::While => write!(f, "while"),
+            TokenKind::For => write!(f, "for"),
+            TokenKind::In => write!(f, "in"),
+            TokenKind::Break => write!(f, "break"),
+            TokenKind::Continue => write!(f, "continue"),
+            TokenKind::Import => write!(f, "import"),
+            TokenKind::Export => write!(f, "export"),
+            _ => write!(f, "{}", self.name()),
+        }
+    }
+}
+
+/// Why the lexer could not produce a valid token. Carried by
+/// `TokenKind::Illegal` so the parser can report it where it occurred.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LexError {
+    UnexpectedCharacter(char),
+    UnterminatedString,
+    /// An unknown or malformed escape sequence, as written in the source.
+    InvalidEscape(String),
+    /// The input ended inside a `/* */` comment.
+    UnterminatedComment,
+    /// A malformed numeric literal, such as `0x`, `1__0` or `12ab`.
+    InvalidNumber(String),
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LexError::UnexpectedCharacter(ch) => write!(f, "unexpected character {:?}", ch),
+            LexError::UnterminatedString => write!(f, "unterminated string literal"),
+            LexError::UnterminatedComment => write!(f, "unterminated block comment"),
+            LexError::InvalidEscape(escape) => write!(f, "invalid escape sequence `{}`", escape),
+            LexError::InvalidNumber(literal) => write!(f, "invalid number literal `{}`", literal),
+        }
+    }
+}
+
+impl std::error::Error for LexError {}
+
+/// Writes `s` back out the way it would appear inside a string literal.
+pub fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            ch if ch.is_control() => out.push_str(&format!("\\u{{{:x}}}", ch as u32)),
+            ch => out.push(ch),
+        }
+    }
+    out
+}
+
+pub const KEYWORDS: phf::Map<&'static str, TokenKind> = phf_map! {
+    "fn" => TokenKind::Function,
+    "let" => TokenKind::Let,
+    "mut" => TokenKind::Mut,
+    "var" => TokenKind::Var,
+    "true" => TokenKind::True,
+    "false" => TokenKind::False,
+    "if" => TokenKind::If,
+    "else" => TokenKind::Else,
+    "return" => TokenKind::Return,
+    "while" => TokenKind::While,
+    "for" => TokenKind::For,
+    "in" => TokenKind::In,
+    "break" => TokenKind::Break,
+    "continue" => TokenKind::Continue,
+    "import" => TokenKind::Import,
+    "export" => TokenKind::Export,
 };
 
-pub fn lookup_ident(ident: String) -> String {
-    match KEYWORDS.get(&ident.as_str()) {
-        Some(keyword) => keyword.to_string(),
-        None => IDENT.to_string(),
+pub fn lookup_ident(ident: String) -> TokenKind {
+    match KEYWORDS.get(ident.as_str()) {
+        Some(keyword) => keyword.clone(),
+        None => TokenKind::Ident(ident),
     }
 }
@@ -0,0 +1,577 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::compiler::*;
+use crate::evaluator::*;
+use crate::object::*;
+use crate::token::*;
+
+/// The deepest the calls of a program may nest, whatever
+/// `limits::Limits::max_depth` allows.
+pub const MAX_FRAMES: usize = 10_000;
+
+/// A call in progress.
+struct Frame {
+    closure: Rc<object::Closure>,
+    /// The offset of the next instruction to run.
+    ip: usize,
+    /// Where the function's locals start on the stack, parameters first.
+    base: usize,
+}
+
+/// Runs `compiler::Bytecode` on an operand stack.
+///
+/// Values and errors are the evaluator's: the operators, indexing and
+/// builtins are shared with it, so a program gives the same result on
+/// either engine. So are its limits: run it inside `limits::with_limits`
+/// to bound a program, whose steps are counted at each loop iteration and
+/// call.
+pub struct VM {
+    constants: Vec<object::Object>,
+    /// Each global's value, or `None` before its `let` has run.
+    globals: Vec<Option<object::Object>>,
+    global_names: Vec<String>,
+    stack: Vec<object::Object>,
+    frames: Vec<Frame>,
+}
+
+impl VM {
+    pub fn new(bytecode: compiler::Bytecode) -> VM {
+        let num_locals = bytecode.main.num_locals;
+        let main = Rc::new(object::Closure {
+            function: bytecode.main,
+            free: vec![],
+        });
+        VM {
+            constants: bytecode.constants,
+            globals: vec![None; bytecode.globals.len()],
+            global_names: bytecode.globals,
+            stack: vec![object::Object::Null; num_locals],
+            frames: vec![Frame {
+                closure: main,
+                ip: 0,
+                base: 0,
+            }],
+        }
+    }
+
+    /// Runs the program to the end, returning the value of its last
+    /// statement or the error that stopped it.
+    pub fn run(&mut self) -> object::Object {
+        match self.execute() {
+            Ok(value) => value,
+            Err(message) => object::Object::Error(message),
+        }
+    }
+
+    fn execute(&mut self) -> Result<object::Object, String> {
+        // The current frame lives in these locals while it runs and is
+        // written back to `frames` only when it calls another function.
+        let frame = self.frames.last().expect("main frame");
+        let mut closure = frame.closure.clone();
+        let mut ip = frame.ip;
+        let mut base = frame.base;
+
+        loop {
+            let instructions = &closure.function.instructions;
+            let op = code::Opcode::from_byte(instructions[ip])
+                .ok_or_else(|| format!("unknown opcode {} at {}", instructions[ip], ip))?;
+            let operand_u8 = |i: usize| instructions[ip + 1 + i] as usize;
+            let operand_u16 = |i: usize| code::read_u16(instructions, ip + 1 + i) as usize;
+            let mut next = ip + 1 + op.definition().operand_widths.iter().sum::<usize>();
+
+            match op {
+                code::Opcode::Constant => self.push(self.constants[operand_u16(0)].clone()),
+                code::Opcode::Pop => {
+                    self.pop();
+                }
+                code::Opcode::Null => self.push(object::Object::Null),
+                code::Opcode::True => self.push(object::Object::Boolean(true)),
+                code::Opcode::False => self.push(object::Object::Boolean(false)),
+                code::Opcode::Add
+                | code::Opcode::Sub
+                | code::Opcode::Mul
+                | code::Opcode::Div
+                | code::Opcode::Mod
+                | code::Opcode::Pow
+                | code::Opcode::Equal
+                | code::Opcode::NotEqual
+                | code::Opcode::Less
+                | code::Opcode::Greater
+                | code::Opcode::LessEqual
+                | code::Opcode::GreaterEqual
+                | code::Opcode::Range => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    let value = evaluator::eval_infix_expression(&infix_operator(op), left, right);
+                    self.push(allocated(value)?);
+                }
+                code::Opcode::Minus | code::Opcode::Bang => {
+                    let operator = match op {
+                        code::Opcode::Minus => token::TokenKind::Minus,
+                        _ => token::TokenKind::Bang,
+                    };
+                    let right = self.pop();
+                    self.push(check(evaluator::eval_prefix_expression(&operator, right))?);
+                }
+                code::Opcode::Jump => {
+                    next = operand_u16(0);
+                    // Jumping back is how loops repeat.
+                    if next <= ip {
+                        limits::step().map_err(message)?;
+                    }
+                }
+                code::Opcode::JumpNotTruthy => {
+                    if !self.pop().is_truthy() {
+                        next = operand_u16(0);
+                    }
+                }
+                code::Opcode::GetGlobal => {
+                    let value = self.global(operand_u16(0))?;
+                    self.push(value);
+                }
+                code::Opcode::SetGlobal => {
+                    let value = self.pop();
+                    self.globals[operand_u16(0)] = Some(value);
+                }
+                code::Opcode::GetLocal => {
+                    let value = match &self.stack[base + operand_u8(0)] {
+                        object::Object::Cell(cell) => cell.borrow().clone(),
+                        value => value.clone(),
+                    };
+                    self.push(value);
+                }
+                code::Opcode::SetLocal => {
+                    let value = self.pop();
+                    match &mut self.stack[base + operand_u8(0)] {
+                        object::Object::Cell(cell) => *cell.borrow_mut() = value,
+                        slot => *slot = value,
+                    }
+                }
+                code::Opcode::ClearLocal => self.stack[base + operand_u8(0)] = object::Object::Null,
+                code::Opcode::GetFree => {
+                    let value = match &closure.free[operand_u8(0)] {
+                        object::Object::Cell(cell) => cell.borrow().clone(),
+                        value => value.clone(),
+                    };
+                    self.push(value);
+                }
+                code::Opcode::GetBuiltin => self.push(object::Object::Builtin(builtins::BUILTINS[operand_u8(0)])),
+                code::Opcode::CaptureLocal => {
+                    let slot = &mut self.stack[base + operand_u8(0)];
+                    if !matches!(slot, object::Object::Cell(_)) {
+                        let value = std::mem::replace(slot, object::Object::Null);
+                        *slot = object::Object::Cell(Rc::new(RefCell::new(value)));
+                    }
+                    let cell = slot.clone();
+                    self.push(cell);
+                }
+                code::Opcode::CaptureFree => self.push(closure.free[operand_u8(0)].clone()),
+                code::Opcode::Closure => {
+                    let function = match &self.constants[operand_u16(0)] {
+                        object::Object::CompiledFunction(function) => function.clone(),
+                        other => return Err(format!("not a function: {}", other.type_name())),
+                    };
+                    let free = self.stack.split_off(self.stack.len() - operand_u8(2));
+                    self.push(object::Object::Closure(Rc::new(object::Closure { function, free })));
+                }
+                code::Opcode::Array => {
+                    let elements = self.stack.split_off(self.stack.len() - operand_u16(0));
+                    self.push(allocated(object::Object::Array(Rc::new(elements)))?);
+                }
+                code::Opcode::Hash => {
+                    let entries = self.stack.split_off(self.stack.len() - 2 * operand_u16(0));
+                    let mut hash = object::Hash::new();
+                    let mut entries = entries.into_iter();
+                    while let (Some(key), Some(value)) = (entries.next(), entries.next()) {
+                        let hash_key = key
+                            .hash_key()
+                            .ok_or_else(|| format!("unusable as hash key: {}", key.type_name()))?;
+                        hash.insert(hash_key, key, value);
+                    }
+                    self.push(allocated(object::Object::Hash(Rc::new(hash)))?);
+                }
+                code::Opcode::Index => {
+                    let index = self.pop();
+                    let left = self.pop();
+                    self.push(check(evaluator::eval_index_expression(left, index))?);
+                }
+                code::Opcode::Slice => {
+                    let flags = operand_u8(0);
+                    let end = (flags & code::SLICE_END != 0).then(|| self.pop());
+                    let start = (flags & code::SLICE_START != 0).then(|| self.pop());
+                    let left = self.pop();
+                    self.push(allocated(evaluator::eval_slice_expression(left, start, end))?);
+                }
+                code::Opcode::LoadPath => {
+                    let keys = self.stack.len() - operand_u8(3);
+                    let mut value = self.variable(&closure, base, operand_u8(0), operand_u16(1))?;
+                    for i in keys..self.stack.len() {
+                        value = check(evaluator::eval_index_expression(value, self.stack[i].clone()))?;
+                    }
+                    self.push(value);
+                }
+                code::Opcode::Assign => {
+                    let value = self.pop();
+                    let keys = self.stack.split_off(self.stack.len() - operand_u8(3));
+                    let (scope, index) = (operand_u8(0), operand_u16(1));
+                    let assigned = value.clone();
+                    let stored = match scope {
+                        code::SCOPE_GLOBAL => match &mut self.globals[index] {
+                            Some(slot) => evaluator::assign_path(slot, &keys, assigned),
+                            None => return Err(format!("identifier not found: {}", self.global_names[index])),
+                        },
+                        code::SCOPE_LOCAL => match &mut self.stack[base + index] {
+                            object::Object::Cell(cell) => evaluator::assign_path(&mut cell.borrow_mut(), &keys, assigned),
+                            slot => evaluator::assign_path(slot, &keys, assigned),
+                        },
+                        _ => match &closure.free[index] {
+                            object::Object::Cell(cell) => evaluator::assign_path(&mut cell.borrow_mut(), &keys, assigned),
+                            // Only a hand-made `.rkc` file captures other values.
+                            _ => return Err("cannot assign to a variable that is not captured".to_string()),
+                        },
+                    };
+                    stored.map_err(message)?;
+                    self.push(value);
+                }
+                code::Opcode::IterStart => {
+                    let iteration = match self.pop() {
+                        object::Object::Array(elements) => (object::Object::Array(elements), 0),
+                        object::Object::Hash(hash) => {
+                            let keys = hash.iter().map(|(key, _)| key.clone()).collect();
+                            (object::Object::Array(Rc::new(keys)), 0)
+                        }
+                        object::Object::Range(start, end) => (object::Object::Range(start, end), start),
+                        other => return Err(format!("not iterable: {}", other.type_name())),
+                    };
+                    self.push(iteration.0);
+                    self.push(object::Object::Integer(iteration.1));
+                }
+                code::Opcode::IterNext => {
+                    let len = self.stack.len();
+                    // Only a hand-made `.rkc` file runs `OpIterNext` on values
+                    // that `OpIterStart` did not push.
+                    let not_iterating = || "OpIterNext without OpIterStart".to_string();
+                    let counter = match self.stack[len - 1] {
+                        object::Object::Integer(counter) => counter,
+                        _ => return Err(not_iterating()),
+                    };
+                    let element = match &self.stack[len - 2] {
+                        object::Object::Array(elements) => elements.get(counter as usize).cloned(),
+                        object::Object::Range(_, end) => (counter < *end).then_some(object::Object::Integer(counter)),
+                        _ => return Err(not_iterating()),
+                    };
+                    match element {
+                        Some(element) => {
+                            // The counter stops below `end` or the array's
+                            // length, so this cannot overflow.
+                            self.stack[len - 1] = object::Object::Integer(counter + 1);
+                            self.push(element);
+                        }
+                        None => {
+                            self.stack.truncate(len - 2);
+                            next = operand_u16(0);
+                        }
+                    }
+                }
+                code::Opcode::Call => {
+                    limits::step().map_err(message)?;
+                    let num_args = operand_u8(0);
+                    let callee = self.stack.len() - 1 - num_args;
+                    match self.stack[callee].clone() {
+                        object::Object::Closure(callee) => {
+                            let function = &callee.function;
+                            if function.parameters.len() != num_args {
+                                return Err(format!(
+                                    "wrong number of arguments: want={}, got={}",
+                                    function.parameters.len(),
+                                    num_args,
+                                ));
+                            }
+                            limits::check_depth(self.frames.len(), MAX_FRAMES).map_err(message)?;
+                            self.frames.last_mut().expect("frame").ip = next;
+                            base = self.stack.len() - num_args;
+                            self.stack.resize(base + function.num_locals, object::Object::Null);
+                            self.frames.push(Frame {
+                                closure: callee.clone(),
+                                ip: 0,
+                                base,
+                            });
+                            closure = callee;
+                            next = 0;
+                        }
+                        object::Object::Builtin(builtin) => {
+                            let args = self.stack.split_off(callee + 1);
+                            self.pop();
+                            self.push(allocated((builtin.func)(args))?);
+                        }
+                        object::Object::Native(native) => {
+                            let args = self.stack.split_off(callee + 1);
+                            self.pop();
+                            self.push(allocated((native.func)(args))?);
+                        }
+                        other => return Err(format!("not a function: {}", other.type_name())),
+                    }
+                }
+                code::Opcode::ReturnValue => {
+                    let value = self.pop();
+                    if self.frames.len() == 1 {
+                        return Ok(value);
+                    }
+                    let finished = self.frames.pop().expect("frame");
+                    self.stack.truncate(finished.base - 1);
+                    self.push(value);
+                    let frame = self.frames.last().expect("frame");
+                    closure = frame.closure.clone();
+                    next = frame.ip;
+                    base = frame.base;
+                }
+            }
+            ip = next;
+        }
+    }
+
+    fn push(&mut self, value: object::Object) {
+        self.stack.push(value);
+    }
+
+    fn pop(&mut self) -> object::Object {
+        self.stack.pop().expect("stack underflow")
+    }
+
+    /// The value of a global. A name the program never binds may be a
+    /// builtin that the compiler took for a global declared later.
+    fn global(&self, index: usize) -> Result<object::Object, String> {
+        if let Some(value) = &self.globals[index] {
+            return Ok(value.clone());
+        }
+        let name = &self.global_names[index];
+        match builtins::lookup(name) {
+            Some(builtin) => Ok(object::Object::Builtin(builtin)),
+            None => Err(format!("identifier not found: {}", name)),
+        }
+    }
+
+    /// The value of the variable an assignment refers to.
+    fn variable(&self, closure: &object::Closure, base: usize, scope: usize, index: usize) -> Result<object::Object, String> {
+        let slot = match scope {
+            code::SCOPE_GLOBAL => return self.global(index),
+            code::SCOPE_LOCAL => &self.stack[base + index],
+            _ => &closure.free[index],
+        };
+        match slot {
+            object::Object::Cell(cell) => Ok(cell.borrow().clone()),
+            value => Ok(value.clone()),
+        }
+    }
+}
+
+fn infix_operator(op: code::Opcode) -> token::TokenKind {
+    match op {
+        code::Opcode::Add => token::TokenKind::Plus,
+        code::Opcode::Sub => token::TokenKind::Minus,
+        code::Opcode::Mul => token::TokenKind::Asterisk,
+        code::Opcode::Div => token::TokenKind::Slash,
+        code::Opcode::Mod => token::TokenKind::Percent,
+        code::Opcode::Pow => token::TokenKind::Power,
+        code::Opcode::Equal => token::TokenKind::Eq,
+        code::Opcode::NotEqual => token::TokenKind::NotEq,
+        code::Opcode::Less => token::TokenKind::Lt,
+        code::Opcode::Greater => token::TokenKind::Gt,
+        code::Opcode::LessEqual => token::TokenKind::LtEq,
+        code::Opcode::GreaterEqual => token::TokenKind::GtEq,
+        code::Opcode::Range => token::TokenKind::DotDot,
+        _ => unreachable!("not a binary operator: {:?}", op),
+    }
+}
+
+/// Turns an error value from the shared evaluator code into an `Err`.
+fn check(value: object::Object) -> Result<object::Object, String> {
+    match value {
+        object::Object::Error(message) => Err(message),
+        value => Ok(value),
+    }
+}
+
+/// Like `check`, also counting the value against the memory and size
+/// limits.
+fn allocated(value: object::Object) -> Result<object::Object, String> {
+    let value = check(value)?;
+    limits::allocate(&value).map_err(message)?;
+    Ok(value)
+}
+
+fn message(error: object::Object) -> String {
+    match error {
+        object::Object::Error(message) => message,
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::*;
+    use crate::compiler::*;
+    use crate::evaluator::*;
+    use crate::lexer::*;
+    use crate::object::*;
+    use crate::parser::*;
+    use crate::vm::*;
+
+    fn parse(input: &str) -> ast::Program {
+        let mut p = parser::Parser::new(lexer::Lexer::new(input.to_string()));
+        let program = p.parse_program();
+        assert!(p.errors().is_empty(), "input: {}, errors: {:?}", input, p.errors());
+        program
+    }
+
+    fn run(program: &ast::Program) -> object::Object {
+        let mut c = compiler::Compiler::new();
+        c.compile(program).unwrap();
+        vm::VM::new(c.bytecode()).run()
+    }
+
+    /// Checks that both engines give the expected result.
+    fn assert_same(tests: &[(&str, &str)]) {
+        for (input, expected) in tests {
+            let program = parse(input);
+            let vm_result = run(&program);
+            let tree_result = evaluator::eval(&program, &mut environment::Environment::new());
+            assert_eq!(vm_result.to_string(), *expected, "input: {}", input);
+            assert_eq!(tree_result.to_string(), *expected, "input: {} (tree)", input);
+        }
+    }
+
+    #[test]
+    fn expressions() {
+        assert_same(&[
+            ("1 + 2 * 3", "7"),
+            ("2 ** 10 % 1000", "24"),
+            ("-(5 - 10) / 2.0", "2.5"),
+            ("1 < 2 == true", "true"),
+            ("!(1 >= 2)", "true"),
+            ("\"a\" + \"b\"", "ab"),
+            ("1 && 0", "true"),
+            ("false || \"\"", "true"),
+            ("[1, 2, 3][-1]", "3"),
+            ("[1, 2, 3][1:]", "[2, 3]"),
+            ("{\"a\": 1, 2: [3]}[2][0]", "3"),
+            ("if (1 > 2) { 10 }", "null"),
+            ("if (1 < 2) { 10 } else { 20 }", "10"),
+            ("let x = 1;", "null"),
+            ("", "null"),
+        ]);
+    }
+
+    #[test]
+    fn functions_and_closures() {
+        assert_same(&[
+            ("let add = fn(a, b) { a + b }; add(1, 2)", "3"),
+            ("let f = fn() { return 1; 2 }; f()", "1"),
+            ("let f = fn() { let g = fn() { h() }; let h = fn() { 1 }; g() }; f()", "1"),
+            (
+                "let f = fn(n) { let even = fn(n) { if (n == 0) { true } else { odd(n - 1) } }; \
+                 let odd = fn(n) { if (n == 0) { false } else { even(n - 1) } }; even(n) }; [f(4), f(5)]",
+                "[true, false]",
+            ),
+            ("let x = 1; let f = fn() { let y = x; let x = 2; [y, x] }; f()", "[1, 2]"),
+            ("let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(15)", "610"),
+            ("let adder = fn(a) { fn(b) { fn(c) { a + b + c } } }; adder(1)(2)(3)", "6"),
+            (
+                "let counter = fn() { var n = 0; fn() { n += 1; n } }; let c = counter(); c(); c(); c()",
+                "3",
+            ),
+            ("let f = fn() { var x = 1; let g = fn() { x }; x = 5; g() }; f()", "5"),
+            ("let f = fn(h) { var c = h; c[\"k\"] = 2; c }; let h = {\"k\": 1}; [f(h)[\"k\"], h[\"k\"]]", "[2, 1]"),
+            ("len(rest([1, 2, 3]))", "2"),
+            ("let len = fn(x) { 0 }; len([1])", "0"),
+            ("let f = fn() { g() }; let g = fn() { 7 }; f()", "7"),
+        ]);
+    }
+
+    #[test]
+    fn loops_and_assignment() {
+        assert_same(&[
+            ("var i = 0; var s = 0; while (i < 5) { i += 1; s += i; } s", "15"),
+            ("var s = 0; for x in 1..5 { if (x == 3) { continue; } s += x; } s", "7"),
+            ("var s = []; for k in {\"a\": 1, \"b\": 2} { s = push(s, k); } s", "[\"a\", \"b\"]"),
+            (
+                "var n = 0; outer: for i in 0..10 { for j in 0..10 { if (j == 2) { continue outer; } if (i == 3) { break outer; } n += 1; } } n",
+                "6",
+            ),
+            ("var fs = []; for i in 0..3 { fs = push(fs, fn() { i }); } [fs[0](), fs[2]()]", "[0, 2]"),
+            ("let f = fn() { for x in [1, 2, 3] { if (x == 2) { return x * 10; } } 0 }; f()", "20"),
+            ("var a = [[1, 2], [3]]; a[0][1] *= 5; a", "[[1, 10], [3]]"),
+            ("var h = {}; h[\"x\"] = 1; h[\"x\"] += 1; h", "{\"x\": 2}"),
+            ("var x = 1; let f = fn() { x = 2; }; f(); x", "2"),
+            ("var i = 0; while (i < 2) { var last = i; i += 1; } last", "1"),
+            ("if (true) { let k = 3; } k", "3"),
+        ]);
+    }
+
+    #[test]
+    fn errors() {
+        assert_same(&[
+            ("5 + true; 5", "ERROR: type mismatch: INTEGER + BOOLEAN"),
+            ("foobar", "ERROR: identifier not found: foobar"),
+            ("1(2)", "ERROR: not a function: INTEGER"),
+            ("fn(a) { a }()", "ERROR: wrong number of arguments: want=1, got=0"),
+            ("for x in 5 {}", "ERROR: not iterable: INTEGER"),
+            ("{[1]: 2}", "ERROR: unusable as hash key: ARRAY"),
+            ("var a = [1]; a[3] = 2", "ERROR: index out of bounds: index 3, length 1"),
+            ("1 / 0", "ERROR: division by zero"),
+        ]);
+    }
+
+    #[test]
+    fn unwinding() {
+        let tests = [
+            // `break` drops the values the enclosing expressions had pushed.
+            ("var s = 0; for x in [1, 2, 3] { s += [10, if (x == 2) { break; } else { x }][1]; } s", "1"),
+            ("let f = fn() { f() }; f()", "ERROR: call depth limit of 10000 exceeded"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(run(&parse(input)).to_string(), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn limits() {
+        let steps = limits::Limits { max_steps: Some(10_000), ..limits::Limits::default() };
+        let depth = limits::Limits { max_depth: Some(20), ..limits::Limits::default() };
+        let strings = limits::Limits { max_string_len: Some(1000), ..limits::Limits::default() };
+        let arrays = limits::Limits { max_array_len: Some(100), ..limits::Limits::default() };
+        let pairs = limits::Limits { max_array_len: Some(2), ..limits::Limits::default() };
+        let tests = [
+            (&steps, "while (true) {}", Some(limits::Limit::Steps(10_000))),
+            (&steps, "for x in 0..1000000000 {}", Some(limits::Limit::Steps(10_000))),
+            (&depth, "let f = fn(n) { f(n + 1) }; f(0)", Some(limits::Limit::Depth(20))),
+            (&depth, "let f = fn(n) { if (n == 0) { 0 } else { f(n - 1) } }; f(19)", None),
+            (&depth, "let f = fn(n) { if (n == 0) { 0 } else { f(n - 1) } }; f(20)", Some(limits::Limit::Depth(20))),
+            (&strings, "var s = \"x\"; while (true) { s = s + s; }", Some(limits::Limit::StringLen(1000))),
+            (&strings, "var s = \"x\"; while (true) { s += s; }", Some(limits::Limit::StringLen(1000))),
+            (&arrays, "var a = []; for i in 0..1000 { a = push(a, i); }", Some(limits::Limit::ArrayLen(100))),
+            (&arrays, "var a = []; for i in 0..100 { a = push(a, i); } a[0:100]", None),
+            (&pairs, "[1, 2, 3]", Some(limits::Limit::ArrayLen(2))),
+            (&pairs, "{1: 1, 2: 2, 3: 3}", Some(limits::Limit::ArrayLen(2))),
+        ];
+        for (limits, input, expected) in tests {
+            let program = parse(input);
+            let (_, vm_exceeded) = limits::with_limits(limits, || run(&program));
+            assert_eq!(vm_exceeded, expected, "input: {}", input);
+            let (_, tree_exceeded) =
+                limits::with_limits(limits, || evaluator::eval(&program, &mut environment::Environment::new()));
+            assert_eq!(tree_exceeded, expected, "input: {}", input);
+        }
+
+        let timeout = std::time::Duration::from_millis(50);
+        let limits = limits::Limits { timeout: Some(timeout), ..limits::Limits::default() };
+        let (result, exceeded) = limits::with_limits(&limits, || run(&parse("while (true) {}")));
+        assert_eq!(exceeded, Some(limits::Limit::Time(timeout)));
+        assert_eq!(result.to_string(), "ERROR: time limit of 50ms exceeded");
+
+        // Without a depth limit, the VM still stops at `MAX_FRAMES`.
+        let (_, exceeded) = limits::with_limits(&limits::Limits::default(), || run(&parse("let f = fn() { f() }; f()")));
+        assert_eq!(exceeded, Some(limits::Limit::Depth(vm::MAX_FRAMES)));
+    }
+}